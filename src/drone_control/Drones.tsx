@@ -63,7 +63,7 @@ export const Drones = ({ selectedDrone, onSelectedDroneChanged }: Props): JSX.El
               </Box>
               <Box style={{ flex: '1' }}>&nbsp;</Box>
 
-              {state.state !== 'undefined' && (
+              {state.state !== 'undefined' && state.state !== 'launched' && (
                 <Button variant="contained" color="info" onClick={droneReady(state.id)}>
                   ready
                 </Button>
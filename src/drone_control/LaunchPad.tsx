@@ -15,7 +15,7 @@ import {
   TextField,
   Paper,
 } from '@material-ui/core'
-import { LaunchPadTwins } from '../fish/LaunchpadTwin'
+import { isDue, LaunchPadTwins } from '../fish/LaunchpadTwin'
 import { DefinedState, MissionTwins } from '../fish/MissionTwin'
 import { useRegistryFish } from '@actyx-contrib/react-pond'
 import { DroneTwins } from '../fish/DroneTwin'
@@ -121,14 +121,20 @@ export const LaunchPad = ({
                 <Paper className="MissionInfo">
                   <Box className="MissionInfoHeader">Next missions</Box>
                   <Box>
-                    {launchpadMissionLog.state.nextMissions.map((m) => (
-                      <Box>
-                        {m}
-                        {selectedDrone && (
-                          <button onClick={() => dropMission(selectedDrone, m)}>drop</button>
-                        )}
-                      </Box>
-                    ))}
+                    {launchpadMissionLog.state.nextMissions.map((m) => {
+                      const schedule = launchpadMissionLog.state.schedules[String(m)]
+                      return (
+                        <Box>
+                          {m}
+                          {schedule?.notBefore && !isDue(schedule, Date.now()) && (
+                            <span> at {new Date(schedule.notBefore).toLocaleString()}</span>
+                          )}
+                          {selectedDrone && (
+                            <button onClick={() => dropMission(selectedDrone, m)}>drop</button>
+                          )}
+                        </Box>
+                      )
+                    })}
                   </Box>
                 </Paper>
                 {launchpadMissionLog.state.currentMission && (
@@ -136,35 +136,17 @@ export const MissionPlanner = (): JSX.Element => {
   )
 }
 
-const waypointCoordsToWaypoints = (coords: WaypointCoords[]): Waypoints => {
-  if (coords.length === 0) {
-    return []
-  }
-
-  return coords.reduce<Waypoints>((acc, { mapX, mapY, height, angle, distance }) => {
-    return [
-      ...acc,
-      {
-        type: 'goto',
-        mapX,
-        mapY,
-        height,
-        angle,
-        distance,
-        duration: 2000 + distance * 5000,
-      },
-      ...(angle > 1 || angle < -1
-        ? ([
-            {
-              type: 'turn',
-              deg: Math.round(angle),
-              duration: 4_000,
-            },
-          ] as Waypoints)
-        : ([] as Waypoints)),
-    ]
-  }, [])
-}
+const waypointCoordsToWaypoints = (coords: WaypointCoords[]): Waypoints =>
+  // the drone turns by `angle` at the end of the goto
+  coords.map<GoToWaypoint>(({ mapX, mapY, height, angle, distance }) => ({
+    type: 'goto',
+    mapX,
+    mapY,
+    height,
+    angle,
+    distance,
+    duration: 2000 + distance * 5000 + (angle > 1 || angle < -1 ? 4_000 : 0),
+  }))
 
 const countRealWaypoints = (wp: Waypoints, idx: number): number =>
   Math.max(
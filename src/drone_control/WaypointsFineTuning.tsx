@@ -32,7 +32,7 @@ const waypointId = (idx: number, wp: Waypoint): string => {
     case 'delay':
       return `${idx}-delay:${wp.duration}`
     default:
-      return 'someOther'
+      return `${idx}-${wp.type}`
   }
 }
 
@@ -137,6 +137,26 @@ export const WaypointsFineTuning = ({
         return <Typography>rotate: {wp.deg}°</Typography>
       case 'delay':
         return <Typography></Typography>
+      case 'setSpeed':
+        return <Typography>speed: {wp.speed}cm/s</Typography>
+      case 'curve':
+        return (
+          <Typography>
+            curve: {wp.x1}/{wp.y1}/{wp.z1} to {wp.x2}/{wp.y2}/{wp.z2}cm
+          </Typography>
+        )
+      case 'flip':
+        return <Typography>flip: {wp.direction}</Typography>
+      case 'camera':
+        return <Typography>camera: {wp.action}</Typography>
+      case 'land':
+        return <Typography>land</Typography>
+      case 'missionPad':
+        return (
+          <Typography>
+            pad {wp.pad}: {wp.x}/{wp.y}/{wp.z}cm
+          </Typography>
+        )
     }
   }
   const mkDuration = (wp: Waypoint, idx: number): string | JSX.Element => {
@@ -144,7 +164,7 @@ export const WaypointsFineTuning = ({
       let step = 0.1
       let scale = 1000
       let unit = 'sec'
-      if (wp.type === 'delay') {
+      if (wp.type === 'delay' || wp.type === 'land') {
         step = 100
         scale = 1
         unit = 'ms'
@@ -185,6 +205,7 @@ export const WaypointsFineTuning = ({
           </Box>
         )
       case 'delay':
+      case 'land':
         return (
           <Box style={{ display: 'flex' }}>
             <Typography onClick={() => changeDuration(idx)} className="changeable-value">
@@ -193,6 +214,8 @@ export const WaypointsFineTuning = ({
             <Typography>ms</Typography>
           </Box>
         )
+      default:
+        return <Typography></Typography>
     }
   }
   const mkActions = (wp: Waypoint, idx: number): JSX.Element => {
@@ -205,7 +228,7 @@ export const WaypointsFineTuning = ({
         )
       case 'turn':
         return <Button onClick={() => addDelay(idx)}>+ Delay</Button>
-      case 'delay':
+      default:
         return <Button onClick={() => removeWaypoint(idx)}>Remove</Button>
     }
   }
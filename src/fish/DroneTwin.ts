@@ -12,6 +12,14 @@ export type ReadyState = {
   ssid: string
   battery: number
 }
+export type EnabledState = {
+  state: 'enabled'
+  id: string
+  ip: string
+  ssid: string
+  battery: number
+  since: number
+}
 export type ConnectedState = {
   state: 'connected'
   id: string
@@ -25,8 +33,11 @@ export type LaunchedState = {
   ip: string
   ssid: string
   missionId: string
+  // the drone flies this version of the mission
+  missionVersion?: number
   atWaypointId: number
   targetWaypointId?: number
+  aborted?: boolean
   battery: number
 }
 export type UsedState = {
@@ -37,7 +48,38 @@ export type UsedState = {
   lastMissionId: string
   battery: number
 }
-export type DroneState = UndefinedState | ReadyState | ConnectedState | LaunchedState | UsedState
+export type DisconnectedState = {
+  state: 'disconnected'
+  id: string
+  ip: string
+  ssid: string
+  battery: number
+}
+export type ChargingState = {
+  state: 'charging'
+  id: string
+  ip: string
+  ssid: string
+  battery: number
+}
+export type FaultState = {
+  state: 'fault'
+  id: string
+  ip: string
+  ssid: string
+  battery: number
+  reason: string
+}
+export type DroneState =
+  | UndefinedState
+  | ReadyState
+  | EnabledState
+  | ConnectedState
+  | LaunchedState
+  | UsedState
+  | DisconnectedState
+  | ChargingState
+  | FaultState
 
 /*
 droneReady(id: string, ip: string)
@@ -60,6 +102,11 @@ export type DroneReadyEvent = {
   eventType: 'droneReady'
   id: string
 }
+export type DroneActivatedEvent = {
+  eventType: 'droneActivated'
+  id: string
+  launchpadId: string
+}
 export type DroneConnectedEvent = {
   eventType: 'droneConnected'
   id: string
@@ -73,6 +120,7 @@ export type DroneLaunchedEvent = {
   eventType: 'droneLaunched'
   id: string
   missionId: string
+  missionVersion?: number
 }
 export type DroneStartedToNextWaypointEvent = {
   eventType: 'droneStartedToNextWaypoint'
@@ -86,10 +134,33 @@ export type DroneArrivedAtWaypointEvent = {
   missionId: string
   waypointId: number
 }
+export type MissionOutcome = 'completed' | 'aborted' | 'refused'
 export type DroneMissionCompletedEvent = {
   eventType: 'droneMissionCompleted'
   id: string
   missionId: string
+  outcome?: MissionOutcome
+}
+export type MissionAbortedEvent = {
+  eventType: 'missionAborted'
+  id: string
+  missionId: string
+  reason?: string
+}
+export type StopCommand = 'emergency' | 'land'
+export type EmergencyStopEvent = {
+  eventType: 'emergencyStop'
+  id: string
+  command: StopCommand
+}
+export type BatteryDecision = 'launchApproved' | 'launchRefused' | 'missionInfeasible' | 'landing'
+export type BatteryCheckedEvent = {
+  eventType: 'batteryChecked'
+  id: string
+  missionId: string
+  battery: number
+  required: number
+  decision: BatteryDecision
 }
 export type DroneLandedEvent = {
   eventType: 'droneLanded'
@@ -100,17 +171,48 @@ export type DroneDisconnectedEvent = {
   eventType: 'droneDisconnected'
   id: string
 }
+export type DroneChargingStartedEvent = {
+  eventType: 'droneChargingStarted'
+  id: string
+}
+export type DroneChargingCompletedEvent = {
+  eventType: 'droneChargingCompleted'
+  id: string
+  battery: number
+}
+export type DroneFaultDetectedEvent = {
+  eventType: 'droneFaultDetected'
+  id: string
+  reason: string
+}
+export type DroneFaultClearedEvent = {
+  eventType: 'droneFaultCleared'
+  id: string
+}
+export type DroneRemovedEvent = {
+  eventType: 'droneRemoved'
+  id: string
+}
 export type DroneEvent =
   | DroneDefinedEvent
   | DroneReadyEvent
+  | DroneActivatedEvent
   | DroneConnectedEvent
   | DroneStatsUpdatedEvent
   | DroneLaunchedEvent
   | DroneStartedToNextWaypointEvent
   | DroneArrivedAtWaypointEvent
   | DroneMissionCompletedEvent
+  | MissionAbortedEvent
+  | EmergencyStopEvent
+  | BatteryCheckedEvent
   | DroneLandedEvent
   | DroneDisconnectedEvent
+  | DroneChargingStartedEvent
+  | DroneChargingCompletedEvent
+  | DroneFaultDetectedEvent
+  | DroneFaultClearedEvent
+  | DroneRemovedEvent
 
 const emitDroneDefined: Emitter<DroneDefinedEvent> = (emit, event) =>
   emit(droneTag.withId(event.id).and(droneDefinedTag), { eventType: 'droneDefined', ...event })
@@ -118,6 +220,9 @@ const emitDroneDefined: Emitter<DroneDefinedEvent> = (emit, event) =>
 const emitDroneReady: Emitter<DroneReadyEvent> = (emit, event) =>
   emit(droneTag.withId(event.id), { eventType: 'droneReady', ...event })
 
+const emitDroneActivated: Emitter<DroneActivatedEvent> = (emit, event) =>
+  emit(droneTag.withId(event.id), { eventType: 'droneActivated', ...event })
+
 const emitDroneConnected: Emitter<DroneConnectedEvent> = (emit, event) =>
   emit(droneTag.withId(event.id), { eventType: 'droneConnected', ...event })
 
@@ -142,16 +247,41 @@ const emitDroneMissionCompleted: Emitter<DroneMissionCompletedEvent> = (emit, ev
     ...event,
   })
 
+const emitMissionAborted: Emitter<MissionAbortedEvent> = (emit, event) =>
+  emit(droneTag.withId(event.id), { eventType: 'missionAborted', ...event })
+
+const emitEmergencyStop: Emitter<EmergencyStopEvent> = (emit, event) =>
+  emit(droneTag.withId(event.id).and(droneEmergencyTag), { eventType: 'emergencyStop', ...event })
+
+const emitBatteryChecked: Emitter<BatteryCheckedEvent> = (emit, event) =>
+  emit(droneTag.withId(event.id), { eventType: 'batteryChecked', ...event })
+
 const emitDroneLanded: Emitter<DroneLandedEvent> = (emit, event) =>
   emit(droneTag.withId(event.id), { eventType: 'droneLanded', ...event })
 
 const emitDroneDisconnected: Emitter<DroneDisconnectedEvent> = (emit, event) =>
   emit(droneTag.withId(event.id), { eventType: 'droneDisconnected', ...event })
 
+const emitDroneChargingStarted: Emitter<DroneChargingStartedEvent> = (emit, event) =>
+  emit(droneTag.withId(event.id), { eventType: 'droneChargingStarted', ...event })
+
+const emitDroneChargingCompleted: Emitter<DroneChargingCompletedEvent> = (emit, event) =>
+  emit(droneTag.withId(event.id), { eventType: 'droneChargingCompleted', ...event })
+
+const emitDroneFaultDetected: Emitter<DroneFaultDetectedEvent> = (emit, event) =>
+  emit(droneTag.withId(event.id), { eventType: 'droneFaultDetected', ...event })
+
+const emitDroneFaultCleared: Emitter<DroneFaultClearedEvent> = (emit, event) =>
+  emit(droneTag.withId(event.id), { eventType: 'droneFaultCleared', ...event })
+
+const emitDroneRemoved: Emitter<DroneRemovedEvent> = (emit, event) =>
+  emit(droneTag.withId(event.id).and(droneDefinedTag), { eventType: 'droneRemoved', ...event })
+
 const droneTag = Tag<DroneEvent>('drone')
-const droneDefinedTag = Tag<DroneDefinedEvent>('drone.defined')
+const droneDefinedTag = Tag<DroneDefinedEvent | DroneRemovedEvent>('drone.defined')
 const droneMissionStartedTag = Tag<DroneLaunchedEvent>('drone.mission.started')
 const droneMissionCompletedTag = Tag<DroneMissionCompletedEvent>('drone.mission.completed')
+const droneEmergencyTag = Tag<EmergencyStopEvent>('drone.emergency')
 
 export const DroneTwins = {
   // Tags
@@ -160,23 +290,34 @@ export const DroneTwins = {
     droneDefinedTag,
     droneMissionStartedTag,
     droneMissionCompletedTag,
+    droneEmergencyTag,
   },
   // Twins
   of: (id: string): Fish<DroneState, DroneEvent> => ({
     fishId: FishId.of('com.awesome-home.launchpad', id, 0),
     initialState: { state: 'undefined', id },
     where: droneTag.withId(id),
-    onEvent: (state, event) => {
-      if (state.state === 'undefined') {
-        if (event.eventType === 'droneDefined') {
-          return {
-            state: 'ready',
-            id,
-            ip: event.ip,
-            ssid: event.ssid,
-            battery: 0,
-          }
+    onEvent: (state, event, metadata) => {
+      // same transitions as the drone twin of the launchpad
+      if (event.eventType === 'droneDefined') {
+        if (state.state === 'launched') {
+          return state
         }
+        return {
+          state: 'ready',
+          id,
+          ip: event.ip,
+          ssid: event.ssid,
+          battery: 0,
+        }
+      }
+      if (event.eventType === 'droneRemoved') {
+        if (state.state === 'launched') {
+          return state
+        }
+        return { state: 'undefined', id }
+      }
+      if (state.state === 'undefined') {
         return state
       }
 
@@ -184,39 +325,51 @@ export const DroneTwins = {
         state.battery = event.battery
         return state
       }
+      // only explains the launchpad's decision
+      if (event.eventType === 'batteryChecked') {
+        return state
+      }
+
+      const drone = { id, ip: state.ip, ssid: state.ssid, battery: state.battery }
+      if (event.eventType === 'droneFaultDetected' && state.state !== 'launched') {
+        return { ...drone, state: 'fault', reason: event.reason }
+      }
 
       switch (state.state) {
         case 'ready':
-          switch (event.eventType) {
-            case 'droneConnected':
-              return {
-                ...state,
-                state: 'connected',
-              }
-
-            default:
-              console.log(`Never reach: ${event.eventType} in 'ready' state`)
-              return state
-          }
+        case 'enabled':
         case 'connected':
+        case 'used':
+        case 'disconnected':
           switch (event.eventType) {
-            case 'droneLaunched':
-              return {
-                ...state,
-                state: 'launched',
-                missionId: event.missionId,
-                atWaypointId: 0,
-                targetWaypointId: undefined,
-              }
+            case 'droneReady':
+              return { ...drone, state: 'ready' }
+            case 'droneActivated':
+              return state.state === 'connected'
+                ? state
+                : { ...drone, state: 'enabled', since: metadata.timestampMicros }
+            case 'droneConnected':
+              return state.state === 'used' ? state : { ...drone, state: 'connected' }
             case 'droneDisconnected':
-              return {
-                ...state,
-                state: 'ready',
+              return state.state === 'connected' ? { ...drone, state: 'disconnected' } : state
+            case 'droneChargingStarted':
+              return { ...drone, state: 'charging' }
+            case 'droneLaunched':
+              if (state.state === 'connected') {
+                return {
+                  ...drone,
+                  state: 'launched',
+                  missionId: event.missionId,
+                  missionVersion: event.missionVersion,
+                  atWaypointId: 0,
+                  targetWaypointId: undefined,
+                }
               }
+              break
             default:
-              console.log(`Never reach: ${event.eventType} in 'connected' state`)
-              return state
+              break
           }
+          break
         case 'launched':
           switch (event.eventType) {
             case 'droneStartedToNextWaypoint':
@@ -232,51 +385,49 @@ export const DroneTwins = {
                 atWaypointId: event.waypointId,
                 targetWaypointId: undefined,
               }
+            case 'missionAborted':
+              return event.missionId === state.missionId ? { ...state, aborted: true } : state
+            case 'emergencyStop':
             case 'droneLanded':
-              return {
-                state: 'used',
-                id,
-                ip: state.ip,
-                ssid: state.ssid,
-                battery: state.battery,
-                lastMissionId: state.missionId,
-              }
+              return { ...drone, state: 'used', lastMissionId: state.missionId }
+            default:
+              break
+          }
+          break
+        case 'charging':
+          switch (event.eventType) {
             case 'droneReady':
-              return {
-                state: 'ready',
-                id,
-                ip: state.ip,
-                ssid: state.ssid,
-                battery: state.battery,
-              }
+              return { ...drone, state: 'ready' }
+            case 'droneChargingCompleted':
+              return { ...drone, state: 'ready', battery: event.battery }
             default:
-              console.log(`Never reach: ${event.eventType} in 'connected' state`)
-              return state
+              break
           }
-        case 'used':
-          if (event.eventType === 'droneReady') {
-            return {
-              ...state,
-              state: 'ready',
-            }
-          } else {
-            console.log(`Never reach: ${event.eventType} in 'used' state`)
-            return state
+          break
+        case 'fault':
+          if (event.eventType === 'droneFaultCleared') {
+            return { ...drone, state: 'ready' }
           }
+          break
 
         default:
           break
       }
+      console.log(`Never reach: ${event.eventType} in '${state.state}' state`)
       return state
     },
   }),
 
-  all: (): Fish<Record<string, boolean>, DroneDefinedEvent> => ({
+  all: (): Fish<Record<string, boolean>, DroneDefinedEvent | DroneRemovedEvent> => ({
     fishId: FishId.of('com.awesome-home.drone.reg', 'all', 0),
     initialState: {},
     where: droneDefinedTag,
     onEvent: (state, event) => {
-      state[event.id] = true
+      if (event.eventType === 'droneRemoved') {
+        delete state[event.id]
+      } else {
+        state[event.id] = true
+      }
       return state
     },
   }),
@@ -284,12 +435,21 @@ export const DroneTwins = {
   // Emitters
   emitDroneDefined,
   emitDroneReady,
+  emitDroneActivated,
   emitDroneConnected,
   emitDroneStatsUpdated,
   emitDroneLaunched,
   emitDroneStartedToNextWaypoint,
   emitDroneArrivedAtWaypoint,
   emitDroneMissionCompleted,
+  emitMissionAborted,
+  emitEmergencyStop,
+  emitBatteryChecked,
   emitDroneLanded,
   emitDroneDisconnected,
+  emitDroneChargingStarted,
+  emitDroneChargingCompleted,
+  emitDroneFaultDetected,
+  emitDroneFaultCleared,
+  emitDroneRemoved,
 }
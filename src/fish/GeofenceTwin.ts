@@ -0,0 +1,135 @@
+import { Fish, FishId, Tag } from '@actyx/pond'
+import { Emitter } from './types'
+
+// fence: the drones stay inside, noFlyZone: the drones stay outside
+export type GeofenceKind = 'fence' | 'noFlyZone'
+
+export type UndefinedState = {
+  defined: false
+  id: string
+}
+export type DefinedState = {
+  defined: true
+  id: string
+  name: string
+  kind: GeofenceKind
+  // corners on the map, like mapX and mapY of the goto waypoints
+  polygon: [number, number][]
+  // in cm, like the height of the goto waypoints
+  minHeight: number
+  maxHeight: number
+}
+export type GeofenceState = UndefinedState | DefinedState
+
+/*
+defineGeofence(
+  id: string,
+  name: string,
+  kind: GeofenceKind,
+  polygon: [number, number][],
+  minHeight: number,
+  maxHeight: number,
+)
+removeGeofence(
+  id: string,
+)
+geofenceViolated(
+  id: string,
+  missionId: string,
+  droneId?: string,
+  waypointId: number,
+  reason: string,
+)
+ */
+
+export type DefineGeofenceEvent = {
+  eventType: 'defineGeofence'
+  id: string
+  name: string
+  kind: GeofenceKind
+  polygon: [number, number][]
+  minHeight: number
+  maxHeight: number
+}
+export type RemoveGeofenceEvent = {
+  eventType: 'removeGeofence'
+  id: string
+}
+// the mission crosses the geofence, or the drone left it during the mission
+export type GeofenceViolatedEvent = {
+  eventType: 'geofenceViolated'
+  id: string
+  missionId: string
+  // missing if the mission was rejected at activation
+  droneId?: string
+  waypointId: number
+  reason: string
+}
+export type GeofenceEvent = DefineGeofenceEvent | RemoveGeofenceEvent | GeofenceViolatedEvent
+
+const emitDefineGeofence: Emitter<DefineGeofenceEvent> = (emit, event) =>
+  emit(geofenceTag.withId(event.id).and(geofenceDefinedTag), {
+    eventType: 'defineGeofence',
+    ...event,
+  })
+
+const emitRemoveGeofence: Emitter<RemoveGeofenceEvent> = (emit, event) =>
+  emit(geofenceTag.withId(event.id).and(geofenceDefinedTag), {
+    eventType: 'removeGeofence',
+    ...event,
+  })
+
+const emitGeofenceViolated: Emitter<GeofenceViolatedEvent> = (emit, event) =>
+  emit(geofenceTag.withId(event.id), { eventType: 'geofenceViolated', ...event })
+
+const geofenceTag = Tag<GeofenceEvent>('geofence')
+const geofenceDefinedTag = Tag<DefineGeofenceEvent | RemoveGeofenceEvent>('geofence.defined')
+export const GeofenceTwins = {
+  // Tags
+  tags: {
+    geofenceTag,
+    geofenceDefinedTag,
+  },
+  // Twins
+  of: (id: string): Fish<GeofenceState, GeofenceEvent> => ({
+    fishId: FishId.of('com.awesome-home.geofence', id, 0),
+    initialState: { defined: false, id },
+    where: geofenceTag.withId(id),
+    onEvent: (state, event) => {
+      switch (event.eventType) {
+        case 'defineGeofence':
+          return {
+            defined: true,
+            id,
+            name: event.name,
+            kind: event.kind,
+            polygon: event.polygon,
+            minHeight: event.minHeight,
+            maxHeight: event.maxHeight,
+          }
+        case 'removeGeofence':
+          return { defined: false, id }
+      }
+      return state
+    },
+  }),
+
+  all: (): Fish<Record<string, boolean>, DefineGeofenceEvent | RemoveGeofenceEvent> => ({
+    fishId: FishId.of('com.awesome-home.geofence.reg', 'all', 0),
+    initialState: {},
+    where: geofenceDefinedTag,
+    onEvent: (state, event) => {
+      if (event.eventType === 'removeGeofence') {
+        delete state[event.id]
+      } else {
+        state[event.id] = true
+      }
+      return state
+    },
+  }),
+
+  // Emitters
+  emitDefineGeofence,
+  emitRemoveGeofence,
+  emitGeofenceViolated,
+}
@@ -25,6 +25,8 @@ export type LaunchPadState = UndefinedState | ReadyState | ActivatedState | Used
 
 export type MissionLogState = {
   nextMissions: Array<String>
+  /** schedules of the next missions that don't fly as soon as possible */
+  schedules: Record<string, Schedule>
   currentMission?: {
     assignedDrone: string
     id: string
@@ -40,6 +42,10 @@ export type LaunchPadRegisteredEvent = {
   eventType: 'launchPadRegistered'
   id: string
 }
+export type LaunchPadDeregisteredEvent = {
+  eventType: 'launchPadDeregistered'
+  id: string
+}
 export type DroneMountedEvent = {
   eventType: 'droneMounted'
   id: string
@@ -62,10 +68,88 @@ export type DroneStartedEvent = {
   missionId: string
 }
 
+/** unix times in ms, times of day like '06:30' in UTC */
+export type Schedule = {
+  notBefore?: number
+  recurrence?: { type: 'every'; minutes: number } | { type: 'daily'; at: string[] }
+  expires?: number
+}
+
+const MINUTE = 60_000
+const DAY = 24 * 60 * MINUTE
+
+const isExpired = (schedule: Schedule, now: number): boolean =>
+  schedule.expires !== undefined && schedule.expires <= now
+
+export const isDue = (schedule: Schedule, now: number): boolean =>
+  (schedule.notBefore === undefined || schedule.notBefore <= now) && !isExpired(schedule, now)
+
+/** '06:30' in ms after midnight */
+const timeOfDay = (at: string): number | undefined => {
+  const [hours, minutes] = at.split(':').map((v) => Number(v.trim()))
+  return Number.isInteger(hours) && Number.isInteger(minutes) && hours < 24 && minutes < 60
+    ? (hours * 60 + minutes) * MINUTE
+    : undefined
+}
+
+const nextTimeOfDay = (at: string[], after: number): number | undefined => {
+  const day = Math.floor(after / DAY) * DAY
+  const times = at
+    .map(timeOfDay)
+    .flatMap((t) => (t === undefined ? [] : [day + t, day + DAY + t]))
+    .filter((t) => t > after)
+  return times.length ? Math.min(...times) : undefined
+}
+
+/** schedule of a mission queued at `queued`, a daily mission waits for its next time of day */
+const firstFlight = (schedule: Schedule, queued: number): Schedule => {
+  const { recurrence, notBefore } = schedule
+  return recurrence?.type === 'daily' && notBefore === undefined
+    ? { ...schedule, notBefore: nextTimeOfDay(recurrence.at, queued) }
+    : schedule
+}
+
+/** schedule of the next flight after `completed`, undefined if the mission doesn't fly again */
+const nextFlight = (schedule: Schedule, completed: number): Schedule | undefined => {
+  const { recurrence, notBefore } = schedule
+  let next: number | undefined
+  if (recurrence?.type === 'every') {
+    const every = Math.max(recurrence.minutes, 1) * MINUTE
+    // stays on the times of the first flight, missed flights are skipped
+    next =
+      notBefore !== undefined && notBefore <= completed
+        ? notBefore + (Math.floor((completed - notBefore) / every) + 1) * every
+        : completed + every
+  } else if (recurrence?.type === 'daily') {
+    next = nextTimeOfDay(recurrence.at, completed)
+  }
+  if (next === undefined || isExpired(schedule, next)) {
+    return undefined
+  }
+  return { ...schedule, notBefore: next }
+}
+
 export type MissionQueuedEvent = {
   eventType: 'missionQueued'
   missionId: string
   launchpadId: string
+  schedule?: Schedule
+}
+
+export type MissionDequeuedEvent = {
+  eventType: 'missionDequeued'
+  missionId: string
+  launchpadId: string
+}
+export type MissionMovedEvent = {
+  eventType: 'missionMoved'
+  missionId: string
+  launchpadId: string
+  position: number
+}
+export type MissionQueueClearedEvent = {
+  eventType: 'missionQueueCleared'
+  launchpadId: string
 }
 
 export type LaunchPadEvent =
@@ -74,9 +158,19 @@ export type LaunchPadEvent =
   | ActivateDroneTimeoutEvent
   | DroneStartedEvent
   | LaunchPadRegisteredEvent
+  | LaunchPadDeregisteredEvent
   | MissionQueuedEvent
+  | MissionDequeuedEvent
+  | MissionMovedEvent
+  | MissionQueueClearedEvent
 
-type MissionLogEvent = DroneStartedEvent | MissionQueuedEvent | DroneMissionCompletedEvent
+type MissionLogEvent =
+  | DroneStartedEvent
+  | MissionQueuedEvent
+  | MissionDequeuedEvent
+  | MissionMovedEvent
+  | MissionQueueClearedEvent
+  | DroneMissionCompletedEvent
 
 const emitLaunchPadRegistered: Emitter<LaunchPadRegisteredEvent> = (emit, event) =>
   emit(launchpadTag.withId(event.id).and(launchpadRegisteredTag), {
@@ -84,6 +178,12 @@ const emitLaunchPadRegistered: Emitter<LaunchPadRegisteredEvent> = (emit, event)
     ...event,
   })
 
+const emitLaunchPadDeregistered: Emitter<LaunchPadDeregisteredEvent> = (emit, event) =>
+  emit(launchpadTag.withId(event.id).and(launchpadRegisteredTag), {
+    eventType: 'launchPadDeregistered',
+    ...event,
+  })
+
 const emitDroneMounted: Emitter<DroneMountedEvent> = (emit, event) =>
   emit(launchpadTag.withId(event.id), {
     eventType: 'droneMounted',
@@ -108,10 +208,33 @@ const emitMissionCreated: Emitter<MissionQueuedEvent> = (emit, event) =>
     ...event,
   })
 
+const emitMissionDequeued: Emitter<MissionDequeuedEvent> = (emit, event) =>
+  emit(launchpadTag.withId(event.launchpadId).and(missionQueueChangedTag), {
+    eventType: 'missionDequeued',
+    ...event,
+  })
+
+const emitMissionMoved: Emitter<MissionMovedEvent> = (emit, event) =>
+  emit(launchpadTag.withId(event.launchpadId).and(missionQueueChangedTag), {
+    eventType: 'missionMoved',
+    ...event,
+  })
+
+const emitMissionQueueCleared: Emitter<MissionQueueClearedEvent> = (emit, event) =>
+  emit(launchpadTag.withId(event.launchpadId).and(missionQueueChangedTag), {
+    eventType: 'missionQueueCleared',
+    ...event,
+  })
+
 const launchpadTag = Tag<LaunchPadEvent>('launchpad')
 const launchpadLaunchTag = Tag<DroneStartedEvent>('launchpad.launch')
-const launchpadRegisteredTag = Tag<LaunchPadRegisteredEvent>('launchpad.registered')
+const launchpadRegisteredTag = Tag<LaunchPadRegisteredEvent | LaunchPadDeregisteredEvent>(
+  'launchpad.registered',
+)
 const missionQueuedTag = Tag<MissionQueuedEvent>('mission.queued')
+const missionQueueChangedTag = Tag<
+  MissionDequeuedEvent | MissionMovedEvent | MissionQueueClearedEvent
+>('mission.queue.changed')
 
 export const LaunchPadTwins = {
   // Tags
@@ -157,33 +280,66 @@ export const LaunchPadTwins = {
     },
   }),
 
-  all: (): Fish<Record<string, boolean>, LaunchPadRegisteredEvent> => ({
+  all: (): Fish<
+    Record<string, boolean>,
+    LaunchPadRegisteredEvent | LaunchPadDeregisteredEvent
+  > => ({
     fishId: FishId.of('com.awesome-home.launchpad.reg', 'all', 0),
     initialState: {},
     where: launchpadRegisteredTag,
     onEvent: (state, event) => {
-      state[event.id] = true
+      if (event.eventType === 'launchPadDeregistered') {
+        delete state[event.id]
+      } else {
+        state[event.id] = true
+      }
       return state
     },
   }),
 
   missionLog: (id: string): Fish<MissionLogState, MissionLogEvent> => ({
-    fishId: FishId.of('com.awesome-home.launchpad.missionLog', id, 0),
+    fishId: FishId.of('com.awesome-home.launchpad.missionLog', id, 1),
     initialState: {
       nextMissions: [],
+      schedules: {},
       currentMission: undefined,
       completedMissions: [],
     },
     where: missionQueuedTag
       .or(launchpadTag.withId(id).and(launchpadLaunchTag))
+      .or(launchpadTag.withId(id).and(missionQueueChangedTag))
       .or(DroneTwins.tags.droneMissionCompletedTag),
-    onEvent: (state, event, { timestampAsDate }) => {
+    onEvent: (state, event, { timestampAsDate, timestampMicros }) => {
       switch (event.eventType) {
         case 'missionQueued':
           if (event.launchpadId === id) {
             state.nextMissions.push(event.missionId)
+            if (event.schedule) {
+              state.schedules[event.missionId] = firstFlight(
+                event.schedule,
+                Math.floor(timestampMicros / 1000),
+              )
+            } else {
+              delete state.schedules[event.missionId]
+            }
+          }
+          return state
+        case 'missionDequeued':
+          state.nextMissions = state.nextMissions.filter((s) => s !== event.missionId)
+          delete state.schedules[event.missionId]
+          return state
+        case 'missionMoved': {
+          const nextMissions = state.nextMissions.filter((s) => s !== event.missionId)
+          if (nextMissions.length !== state.nextMissions.length) {
+            nextMissions.splice(event.position, 0, event.missionId)
+            state.nextMissions = nextMissions
           }
           return state
+        }
+        case 'missionQueueCleared':
+          state.nextMissions = []
+          state.schedules = {}
+          return state
         case 'droneStarted':
           if (event.id === id) {
             const nextIdx = state.nextMissions.findIndex((s) => s === event.missionId)
@@ -199,12 +355,21 @@ export const LaunchPadTwins = {
             }
           }
           return state
-        case 'droneMissionCompleted':
+        case 'droneMissionCompleted': {
           state.nextMissions = state.nextMissions.filter((s) => s !== event.missionId)
           console.log(state.currentMission, event.missionId)
           if (state.currentMission?.id === event.missionId) {
             state.currentMission = undefined
           }
+          // a recurring mission is queued again for its next flight
+          const schedule = state.schedules[event.missionId]
+          const next = schedule && nextFlight(schedule, Math.floor(timestampMicros / 1000))
+          if (next) {
+            state.nextMissions.push(event.missionId)
+            state.schedules[event.missionId] = next
+          } else {
+            delete state.schedules[event.missionId]
+          }
 
           state.completedMissions.push({
             drone: event.id,
@@ -212,6 +377,7 @@ export const LaunchPadTwins = {
             ts: timestampAsDate(),
           })
           return state
+        }
 
         default:
           break
@@ -226,5 +392,9 @@ export const LaunchPadTwins = {
   emitActivateDroneTimeout,
   emitDroneStarted,
   emitLaunchPadRegistered,
+  emitLaunchPadDeregistered,
   emitMissionCreated,
+  emitMissionDequeued,
+  emitMissionMoved,
+  emitMissionQueueCleared,
 }
@@ -22,19 +22,81 @@ export type DelayWaypoint = {
   duration: number
 }
 
-export type Waypoint = GoToWaypoint | TurnWaypoint | DelayWaypoint
+export type SetSpeedWaypoint = {
+  type: 'setSpeed'
+  // cm/s of the following goto and missionPad waypoints
+  speed: number
+}
+
+// arc through x1 y1 z1 to x2 y2 z2, in cm relative to the drone
+export type CurveWaypoint = {
+  type: 'curve'
+  x1: number
+  y1: number
+  z1: number
+  x2: number
+  y2: number
+  z2: number
+  speed: number
+}
+
+export type FlipWaypoint = {
+  type: 'flip'
+  direction: 'left' | 'right' | 'forward' | 'back'
+}
+
+export type CameraWaypoint = {
+  type: 'camera'
+  action: 'startVideo' | 'stopVideo'
+}
+
+// lands and takes off again after duration ms
+export type LandWaypoint = {
+  type: 'land'
+  duration: number
+}
+
+// x y z in cm relative to the mission pad 1-8
+export type MissionPadWaypoint = {
+  type: 'missionPad'
+  pad: number
+  x: number
+  y: number
+  z: number
+  speed: number
+}
+
+export type Waypoint =
+  | GoToWaypoint
+  | TurnWaypoint
+  | DelayWaypoint
+  | SetSpeedWaypoint
+  | CurveWaypoint
+  | FlipWaypoint
+  | CameraWaypoint
+  | LandWaypoint
+  | MissionPadWaypoint
 export type Waypoints = Array<Waypoint>
 
 export type UndefinedState = {
   defined: false
   id: string
 }
+export type MissionVersion = {
+  version: number
+  name: string
+  waypoints: Waypoints
+  definedAt: Date
+}
 export type DefinedState = {
   defined: true
   id: string
   name: string
   visible: boolean
   waypoints: Waypoints
+  // version of the last defineMission, name and waypoints are the ones of this version
+  version: number
+  history: MissionVersion[]
 }
 export type MissionState = UndefinedState | DefinedState
 
@@ -54,6 +116,8 @@ export type DefineMissionEvent = {
   id: string
   name: string
   waypoints: Waypoints
+  // set by the planner, counted by the twins if missing
+  version?: number
 }
 export type ShowMissionEvent = {
   eventType: 'showMission'
@@ -81,16 +145,28 @@ export const MissionTwins = {
     fishId: FishId.of('com.awesome-home.mission', id, 0),
     initialState: { defined: false, id },
     where: missionTag.withId(id),
-    onEvent: (state, event) => {
+    onEvent: (state, event, { timestampAsDate }) => {
       switch (event.eventType) {
-        case 'defineMission':
+        case 'defineMission': {
+          const version = event.version ?? (state.defined ? state.version + 1 : 1)
+          // a version defined again replaces the older definition
+          const history = state.defined ? state.history.filter((v) => v.version !== version) : []
+          history.push({
+            version,
+            name: event.name,
+            waypoints: event.waypoints,
+            definedAt: timestampAsDate(),
+          })
           return {
             defined: true,
             id,
             name: event.name,
             visible: state.defined ? state.visible : true,
             waypoints: event.waypoints,
+            version,
+            history,
           }
+        }
         case 'showMission':
           if (state.defined) {
             state.visible = event.visible
@@ -102,6 +178,8 @@ export const MissionTwins = {
               name: id,
               visible: event.visible,
               waypoints: [],
+              version: 0,
+              history: [],
             }
           }
       }
@@ -0,0 +1,243 @@
+//! Derive macros for the event enums of the twins.
+//!
+//! ## `#[derive(EventEmitter)]`
+//!
+//! Every variant gets an `emit_<variant>` function that publishes the event with the tags of its
+//! twin, `<base>` and `<base>:<id>` plus the extra `tag`s of the variant.
+//!
+//! ```ignore
+//! #[derive(Serialize, EventEmitter)]
+//! #[emitter(base = "launchpad")]
+//! pub enum LaunchPadEvent {
+//!     #[emitter(tag = "launchpad.registered")]
+//!     LaunchPadRegistered(LaunchPadRegisteredEvent),
+//!     // the id of the launchpad is in another field than `id`
+//!     #[emitter(id = "launchpad_id")]
+//!     MissionActivated(MissionActivatedEvent),
+//!     // published by the drone
+//!     #[emitter(skip)]
+//!     DroneMissionCompleted(DroneMissionCompletedEvent),
+//! }
+//!
+//! LaunchPadEvent::emit_mission_activated(service, MissionActivatedEvent { .. }).await?;
+//! ```
+//!
+//! The generated code publishes with `crate::twin::{tag_with_id, mk_publish_request}` of the
+//! launchpad.
+//!
+//! ## `#[derive(Reducer)]`
+//!
+//! Generates the trait `<Enum>Reducer` for twins with `Twin::Event = <Enum>`. It has an
+//! `on_<variant>` handler per variant and `on_event`, that passes an event to its handler. A new
+//! variant doesn't compile until every twin handles it.
+//!
+//! ```ignore
+//! impl Twin for MissionTwin {
+//!     type Event = MissionEvent;
+//!     fn reducer(state: Self::State, event: Event<MissionEvent>) -> Self::State {
+//!         Self::on_event(state, event)
+//!     }
+//! }
+//! impl MissionEventReducer for MissionTwin {
+//!     fn on_define_mission(state: Self::State, event: DefineMissionEvent, meta: &Metadata) -> Self::State { .. }
+//!     fn on_show_mission(state: Self::State, event: ShowMissionEvent, meta: &Metadata) -> Self::State { .. }
+//! }
+//! ```
+extern crate proc_macro;
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{
+    parse_macro_input, Attribute, Data, DeriveInput, Error, Fields, Ident, Lit, LitStr, Meta,
+    MetaNameValue, NestedMeta, Type, Variant,
+};
+
+#[proc_macro_derive(EventEmitter, attributes(emitter))]
+pub fn derive_event_emitter(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
+
+#[proc_macro_derive(Reducer)]
+pub fn derive_reducer(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_reducer(input)
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
+
+/// content of the `#[emitter(...)]` attributes of the enum or a variant
+#[derive(Default)]
+struct Options {
+    base: Option<LitStr>,
+    id: Option<LitStr>,
+    tags: Vec<LitStr>,
+    skip: bool,
+}
+
+fn options(attrs: &[Attribute]) -> syn::Result<Options> {
+    let mut options = Options::default();
+    for attr in attrs.iter().filter(|a| a.path.is_ident("emitter")) {
+        let list = match attr.parse_meta()? {
+            Meta::List(list) => list,
+            meta => return Err(Error::new_spanned(meta, "expected `#[emitter(...)]`")),
+        };
+        for nested in list.nested {
+            match nested {
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("skip") => options.skip = true,
+                NestedMeta::Meta(Meta::NameValue(MetaNameValue {
+                    path,
+                    lit: Lit::Str(value),
+                    ..
+                })) => {
+                    if path.is_ident("base") {
+                        options.base = Some(value);
+                    } else if path.is_ident("id") {
+                        options.id = Some(value);
+                    } else if path.is_ident("tag") {
+                        options.tags.push(value);
+                    } else {
+                        return Err(Error::new_spanned(path, "unknown emitter option"));
+                    }
+                }
+                other => return Err(Error::new_spanned(other, "unknown emitter option")),
+            }
+        }
+    }
+    Ok(options)
+}
+
+/// the variants of an event enum with their event
+fn variants(input: &DeriveInput) -> syn::Result<Vec<(&Variant, &Type)>> {
+    let data = match &input.data {
+        Data::Enum(data) => data,
+        _ => {
+            return Err(Error::new_spanned(
+                &input.ident,
+                "only event enums are supported",
+            ))
+        }
+    };
+    data.variants
+        .iter()
+        .map(|variant| match &variant.fields {
+            Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+                Ok((variant, &fields.unnamed[0].ty))
+            }
+            _ => Err(Error::new_spanned(
+                variant,
+                "expected variants with one event, like `DroneReady(DroneReadyEvent)`",
+            )),
+        })
+        .collect()
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let enum_options = options(&input.attrs)?;
+    let base = enum_options
+        .base
+        .as_ref()
+        .ok_or_else(|| Error::new_spanned(&input.ident, "missing `#[emitter(base = \"...\")]`"))?;
+    let name = &input.ident;
+
+    let mut functions = Vec::new();
+    for (variant, payload) in variants(&input)? {
+        let options = options(&variant.attrs)?;
+        if options.skip {
+            continue;
+        }
+        let id = match options.id.as_ref().or(enum_options.id.as_ref()) {
+            Some(id) => Ident::new(&id.value(), id.span()),
+            None => format_ident!("id"),
+        };
+        let tags = &options.tags;
+        let ident = &variant.ident;
+        let function = format_ident!("emit_{}", snake_case(&ident.to_string()));
+        let doc = format!("publishes `{}::{}` with the tags of its twin", name, ident);
+        functions.push(quote! {
+            #[doc = #doc]
+            #[allow(dead_code)]
+            pub async fn #function(
+                service: impl ::actyx_sdk::service::EventService,
+                event: #payload,
+            ) -> ::std::result::Result<::actyx_sdk::service::PublishResponse, ::anyhow::Error> {
+                let tags = crate::twin::tag_with_id(#base, &event.#id) #(+ ::actyx_sdk::tag!(#tags))*;
+                service
+                    .publish(crate::twin::mk_publish_request(tags, &Self::#ident(event)))
+                    .await
+            }
+        });
+    }
+
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics #name #ty_generics #where_clause {
+            #(#functions)*
+        }
+    })
+}
+
+fn expand_reducer(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let vis = &input.vis;
+    let reducer = format_ident!("{}Reducer", name);
+    let doc = format!(
+        "reducer of a twin with `{}`s, with a handler per event",
+        name
+    );
+
+    let mut handlers = Vec::new();
+    let mut arms = Vec::new();
+    for (variant, payload) in variants(&input)? {
+        let ident = &variant.ident;
+        let handler = format_ident!("on_{}", snake_case(&ident.to_string()));
+        handlers.push(quote! {
+            fn #handler(
+                state: Self::State,
+                event: #payload,
+                meta: &::actyx_sdk::Metadata,
+            ) -> Self::State;
+        });
+        arms.push(quote! {
+            #name::#ident(e) => Self::#handler(state, e, &event.meta),
+        });
+    }
+
+    Ok(quote! {
+        #[doc = #doc]
+        #vis trait #reducer: crate::twin::Twin<Event = #name> {
+            #(#handlers)*
+
+            /// passes the event to its handler
+            fn on_event(state: Self::State, event: ::actyx_sdk::Event<#name>) -> Self::State {
+                match event.payload {
+                    #(#arms)*
+                }
+            }
+        }
+    })
+}
+
+fn snake_case(name: &str) -> String {
+    let mut snake = String::new();
+    for (idx, c) in name.char_indices() {
+        if c.is_uppercase() {
+            if idx > 0 {
+                snake.push('_');
+            }
+            snake.extend(c.to_lowercase());
+        } else {
+            snake.push(c);
+        }
+    }
+    snake
+}
+
+#[test]
+fn emit_functions_are_named_after_the_variant() {
+    assert_eq!(snake_case("DroneReady"), "drone_ready");
+    assert_eq!(snake_case("LaunchPadRegistered"), "launch_pad_registered");
+    assert_eq!(snake_case("Refused"), "refused");
+}
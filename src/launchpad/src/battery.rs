@@ -0,0 +1,164 @@
+use crate::twins::{drone_twin::events::BatteryDecision, mission_twin::types::*};
+use serde::Deserialize;
+
+/// time the drone needs to take off and land, in s
+const TAKE_OFF_AND_LANDING: f32 = 6.0;
+/// speed of `go` commands, in m/s
+const GOTO_SPEED: f32 = 1.0;
+/// speed of `cw` / `ccw`, in deg/s
+const TURN_SPEED: f32 = 90.0;
+/// time of a flip, in s
+const FLIP_TIME: f32 = 2.0;
+
+/// When the launchpad lets the drone fly. All levels are in percent.
+///
+/// ```toml
+/// [battery]
+/// min_launch = 30
+/// min_flight = 15
+/// drain_per_second = 0.13
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BatteryPolicy {
+    /// the drone doesn't take off below this level
+    pub min_launch: u8,
+    /// the drone aborts its mission and lands below this level
+    pub min_flight: u8,
+    /// drain while the drone is in the air, a Tello flies about 13 minutes
+    pub drain_per_second: f32,
+}
+
+impl Default for BatteryPolicy {
+    fn default() -> Self {
+        Self {
+            min_launch: 30,
+            min_flight: 15,
+            drain_per_second: 0.13,
+        }
+    }
+}
+
+impl BatteryPolicy {
+    pub fn validate(&self) -> Result<(), anyhow::Error> {
+        if self.min_flight > self.min_launch || self.min_launch > 100 {
+            return Err(anyhow::Error::msg(format!(
+                "battery levels need min_flight ({}) <= min_launch ({}) <= 100",
+                self.min_flight, self.min_launch
+            )));
+        }
+        if self.drain_per_second.is_nan() || self.drain_per_second < 0.0 {
+            return Err(anyhow::Error::msg(
+                "battery drain_per_second must not be negative",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Battery the `waypoints` take, including take off and landing. The drone needs the
+    /// `duration` (ms) of a waypoint or longer, if it can't cover the distance in time.
+    pub fn required(&self, waypoints: &[Waypoint]) -> u8 {
+        let seconds: f32 = waypoints
+            .iter()
+            .map(|wp| match wp {
+                Waypoint::Goto(GoToWaypoint {
+                    distance, duration, ..
+                }) => (duration / 1000.0).max(distance / GOTO_SPEED),
+                Waypoint::Turn(TurnWaypoint { deg, duration }) => {
+                    (duration / 1000.0).max(deg.abs() as f32 / TURN_SPEED)
+                }
+                Waypoint::Delay(DelayWaypoint { duration }) => duration / 1000.0,
+                Waypoint::SetSpeed(_) | Waypoint::Camera(_) => 0.0,
+                Waypoint::Curve(CurveWaypoint {
+                    x1,
+                    y1,
+                    z1,
+                    x2,
+                    y2,
+                    z2,
+                    speed,
+                }) => {
+                    // the arc is about as long as its two chords
+                    let chords = length([*x1, *y1, *z1]) + length([x2 - x1, y2 - y1, z2 - z1]);
+                    chords / (*speed).max(1) as f32
+                }
+                Waypoint::Flip(_) => FLIP_TIME,
+                // the motors are off while the drone waits on the ground
+                Waypoint::Land(_) => TAKE_OFF_AND_LANDING,
+                Waypoint::MissionPad(MissionPadWaypoint { x, y, z, speed, .. }) => {
+                    length([*x, *y, *z]) / (*speed).max(1) as f32
+                }
+            })
+            .sum();
+        let required = (seconds + TAKE_OFF_AND_LANDING) * self.drain_per_second;
+        required.ceil().min(u8::MAX as f32) as u8
+    }
+
+    /// decision before take off, with the `required` battery of the mission
+    pub fn check_launch(&self, battery: u8, required: u8) -> BatteryDecision {
+        if battery < self.min_launch {
+            BatteryDecision::LaunchRefused
+        } else if (battery as u16) < self.min_flight as u16 + required as u16 {
+            BatteryDecision::MissionInfeasible
+        } else {
+            BatteryDecision::LaunchApproved
+        }
+    }
+
+    /// the drone lands in place if its battery dropped below `min_flight`
+    pub fn must_land(&self, battery: u8) -> bool {
+        battery < self.min_flight
+    }
+}
+
+/// length of a move, in cm
+fn length(v: [i16; 3]) -> f32 {
+    v.iter().map(|c| (*c as f32).powi(2)).sum::<f32>().sqrt()
+}
+
+#[test]
+fn estimates_the_battery_of_a_mission() {
+    let policy = BatteryPolicy::default();
+    let goto = |distance: f32, duration: f32| {
+        Waypoint::Goto(GoToWaypoint {
+            map_x: 0.0,
+            map_y: 0.0,
+            height: 100,
+            angle: None,
+            distance,
+            duration,
+        })
+    };
+    // 6s take off and landing, 4s goto (faster than 1m/s is impossible), 20s goto, 1s turn, 9s delay
+    let waypoints = vec![
+        goto(4.0, 2000.0),
+        goto(10.0, 20000.0),
+        Waypoint::Turn(TurnWaypoint {
+            deg: -90,
+            duration: 0.0,
+        }),
+        Waypoint::Delay(DelayWaypoint { duration: 9000.0 }),
+    ];
+    assert_eq!(policy.required(&waypoints), 6);
+    assert_eq!(policy.required(&[]), 1);
+
+    assert_eq!(policy.check_launch(29, 6), BatteryDecision::LaunchRefused);
+    assert_eq!(policy.check_launch(30, 6), BatteryDecision::LaunchApproved);
+    assert_eq!(
+        policy.check_launch(30, 16),
+        BatteryDecision::MissionInfeasible
+    );
+    assert_eq!(
+        policy.check_launch(100, 250),
+        BatteryDecision::MissionInfeasible
+    );
+    assert!(policy.must_land(14));
+    assert!(!policy.must_land(15));
+
+    assert!(BatteryPolicy {
+        min_flight: 40,
+        ..Default::default()
+    }
+    .validate()
+    .is_err());
+}
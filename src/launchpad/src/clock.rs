@@ -0,0 +1,39 @@
+use std::time::SystemTime;
+
+/// Source of the current time for the decisions of the controller. Twin states only hold the
+/// timestamps of their events, whether a time is passed is decided with this clock.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> SystemTime;
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// clock of the tests, it only moves when it is told to
+#[cfg(test)]
+#[derive(Clone)]
+pub struct FakeClock(std::sync::Arc<std::sync::Mutex<SystemTime>>);
+
+#[cfg(test)]
+impl FakeClock {
+    pub fn new(now: SystemTime) -> Self {
+        Self(std::sync::Arc::new(std::sync::Mutex::new(now)))
+    }
+    #[allow(dead_code)]
+    pub fn advance(&self, by: std::time::Duration) {
+        *self.0.lock().unwrap() += by;
+    }
+}
+
+#[cfg(test)]
+impl Clock for FakeClock {
+    fn now(&self) -> SystemTime {
+        *self.0.lock().unwrap()
+    }
+}
@@ -0,0 +1,448 @@
+use crate::{battery::BatteryPolicy, controller::AbortAction, hardware::HardwareConfig};
+use actyx_sdk::{AppId, AppManifest};
+use serde::Deserialize;
+use std::{convert::TryFrom, path::Path, path::PathBuf};
+use structopt::StructOpt;
+use url::Url;
+
+const DEFAULT_NAME: &str = "Launchpad-01";
+const DEFAULT_ACTYX_URL: &str = "http://localhost:4454";
+const DEFAULT_APP_ID: &str = "com.example.launchpad";
+const DEFAULT_APP_NAME: &str = "Drone Launchpad";
+const DEFAULT_APP_VERSION: &str = "0.1.0";
+
+/// Command line arguments. Every option can also be set with an env var or in the config file,
+/// the command line wins over the env var, the env var over the file.
+#[derive(Debug, Default, StructOpt)]
+#[structopt(name = "launchpad", about = "Controls a drone launchpad")]
+pub struct Args {
+    /// TOML file with the launchpad configuration
+    #[structopt(short, long, env = "LAUNCHPAD_CONFIG", parse(from_os_str))]
+    pub config: Option<PathBuf>,
+    /// Name of the launchpad, used as launchpad id in all events
+    #[structopt(short, long, env = "LAUNCHPAD_NAME")]
+    pub name: Option<String>,
+    /// Url of the Actyx node
+    #[structopt(long, env = "LAUNCHPAD_ACTYX_URL")]
+    pub actyx_url: Option<String>,
+    /// App id of the app manifest, has to start with `com.example.` without signature
+    #[structopt(long, env = "LAUNCHPAD_APP_ID")]
+    pub app_id: Option<String>,
+    /// Display name of the app manifest
+    #[structopt(long, env = "LAUNCHPAD_APP_NAME")]
+    pub app_name: Option<String>,
+    /// Version of the app manifest
+    #[structopt(long, env = "LAUNCHPAD_APP_VERSION")]
+    pub app_version: Option<String>,
+    /// Developer certificate's signature of the app manifest
+    #[structopt(long, env = "LAUNCHPAD_APP_SIGNATURE")]
+    pub app_signature: Option<String>,
+    /// Directory for twin snapshots, without one every twin replays all its events on startup
+    #[structopt(long, env = "LAUNCHPAD_SNAPSHOT_DIR", parse(from_os_str))]
+    pub snapshot_dir: Option<PathBuf>,
+    #[structopt(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, StructOpt)]
+pub enum Command {
+    /// Plays a Tello drone on the given address instead of controlling one
+    TelloEmulator {
+        #[structopt(default_value = "0.0.0.0:8889")]
+        addr: String,
+        /// Port of the client the state is streamed to, until the client asks for another one
+        #[structopt(long, default_value = "8890")]
+        state_port: u16,
+    },
+}
+
+/// layout of the config file
+///
+/// ```toml
+/// name = "Launchpad-01"
+/// actyx_url = "http://localhost:4454"
+/// # "return" flies back to the start point, "land" lands in place
+/// on_abort = "return"
+/// snapshot_dir = "/var/lib/launchpad/snapshots"
+///
+/// # levels in percent, see `BatteryPolicy`
+/// [battery]
+/// min_launch = 30
+/// min_flight = 15
+///
+/// [app]
+/// id = "com.example.launchpad"
+/// name = "Drone Launchpad"
+/// version = "0.1.0"
+/// ```
+///
+/// Instead of `name`, several launchpads can be supervised by one process:
+///
+/// ```toml
+/// [[launchpad]]
+/// name = "Launchpad-01"
+/// servo_pin = 12
+/// wifi_adapter = "wlan0"
+///
+/// [[launchpad]]
+/// name = "Launchpad-02"
+/// on_abort = "land"
+/// servo_pin = 13
+/// wifi_adapter = "wlan1"
+/// drone_ip = "192.168.10.1"
+/// # the drones stream their state to 8890 unless a Tello EDU is told another port
+/// state_port = 8891
+/// command_port = 9001
+///
+/// [launchpad.battery]
+/// min_launch = 50
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FileConfig {
+    name: Option<String>,
+    actyx_url: Option<String>,
+    snapshot_dir: Option<PathBuf>,
+    /// default for all launchpads
+    on_abort: Option<AbortAction>,
+    /// default for all launchpads
+    battery: Option<BatteryPolicy>,
+    #[serde(default)]
+    app: AppConfig,
+    #[serde(default)]
+    launchpad: Vec<LaunchpadFileConfig>,
+}
+
+/// `[[launchpad]]` entry, flattening `HardwareConfig` would accept unknown fields
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LaunchpadFileConfig {
+    name: String,
+    on_abort: Option<AbortAction>,
+    battery: Option<BatteryPolicy>,
+    servo_pin: Option<u8>,
+    wifi_adapter: Option<String>,
+    drone_ip: Option<String>,
+    command_port: Option<u16>,
+    state_port: Option<u16>,
+}
+
+impl LaunchpadFileConfig {
+    fn into_config(self, on_abort: AbortAction, battery: BatteryPolicy) -> LaunchpadConfig {
+        LaunchpadConfig {
+            name: self.name,
+            on_abort: self.on_abort.unwrap_or(on_abort),
+            battery: self.battery.unwrap_or(battery),
+            hardware: HardwareConfig {
+                servo_pin: self.servo_pin,
+                wifi_adapter: self.wifi_adapter,
+                drone_ip: self.drone_ip,
+                command_port: self.command_port,
+                state_port: self.state_port,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AppConfig {
+    id: Option<String>,
+    name: Option<String>,
+    version: Option<String>,
+    signature: Option<String>,
+}
+
+impl FileConfig {
+    fn read(path: &Path) -> Result<Self, anyhow::Error> {
+        let content = std::fs::read_to_string(path).map_err(|e| {
+            anyhow::Error::msg(format!("can't read config {}: {}", path.display(), e))
+        })?;
+        toml::from_str(&content)
+            .map_err(|e| anyhow::Error::msg(format!("invalid config {}: {}", path.display(), e)))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LaunchpadConfig {
+    pub name: String,
+    pub on_abort: AbortAction,
+    pub battery: BatteryPolicy,
+    pub hardware: HardwareConfig,
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// launchpads run by this process, at least one
+    pub launchpads: Vec<LaunchpadConfig>,
+    pub actyx_url: Url,
+    pub app_manifest: AppManifest,
+    pub snapshot_dir: Option<PathBuf>,
+}
+
+impl Config {
+    pub fn load(args: &Args) -> Result<Self, anyhow::Error> {
+        let file = match &args.config {
+            Some(path) => FileConfig::read(path)?,
+            None => FileConfig::default(),
+        };
+        let pick = |arg: &Option<String>, file: Option<String>, default: &str| {
+            arg.clone().or(file).unwrap_or_else(|| default.to_string())
+        };
+
+        let on_abort = file.on_abort.unwrap_or(AbortAction::Return);
+        let battery = file.battery.unwrap_or_default();
+        let launchpads = if file.launchpad.is_empty() {
+            vec![LaunchpadConfig {
+                name: pick(&args.name, file.name, DEFAULT_NAME),
+                on_abort,
+                battery,
+                hardware: HardwareConfig::default(),
+            }]
+        } else if args.name.is_some() || file.name.is_some() {
+            return Err(anyhow::Error::msg(
+                "use either a launchpad name or a list of [[launchpad]]s",
+            ));
+        } else {
+            file.launchpad
+                .into_iter()
+                .map(|l| l.into_config(on_abort, battery))
+                .collect()
+        };
+        validate_launchpads(&launchpads)?;
+
+        let actyx_url = pick(&args.actyx_url, file.actyx_url, DEFAULT_ACTYX_URL);
+        let actyx_url = Url::parse(&actyx_url)
+            .map_err(|e| anyhow::Error::msg(format!("invalid actyx url {}: {}", actyx_url, e)))?;
+        if !["http", "https"].contains(&actyx_url.scheme()) {
+            return Err(anyhow::Error::msg(format!(
+                "actyx url {} has to be http(s)",
+                actyx_url
+            )));
+        }
+
+        let app_id = pick(&args.app_id, file.app.id, DEFAULT_APP_ID);
+        let signature = args.app_signature.clone().or(file.app.signature);
+        if signature.is_none() && !app_id.starts_with("com.example.") {
+            return Err(anyhow::Error::msg(format!(
+                "app id {} needs a signature, only com.example.* apps run in trial mode",
+                app_id
+            )));
+        }
+        let app_id = AppId::try_from(app_id.as_str())
+            .map_err(|e| anyhow::Error::msg(format!("invalid app id {}: {}", app_id, e)))?;
+        let version = pick(&args.app_version, file.app.version, DEFAULT_APP_VERSION);
+        if version.is_empty() {
+            return Err(anyhow::Error::msg("app version must not be empty"));
+        }
+        let app_manifest = AppManifest::new(
+            app_id,
+            pick(&args.app_name, file.app.name, DEFAULT_APP_NAME),
+            version,
+            signature,
+        );
+
+        Ok(Self {
+            launchpads,
+            actyx_url,
+            app_manifest,
+            snapshot_dir: args.snapshot_dir.clone().or(file.snapshot_dir),
+        })
+    }
+}
+
+fn validate_launchpads(launchpads: &[LaunchpadConfig]) -> Result<(), anyhow::Error> {
+    for (idx, launchpad) in launchpads.iter().enumerate() {
+        validate_name(&launchpad.name)?;
+        launchpad
+            .battery
+            .validate()
+            .map_err(|e| anyhow::Error::msg(format!("launchpad {}: {}", launchpad.name, e)))?;
+        let others = &launchpads[..idx];
+        let hw = &launchpad.hardware;
+        if others.iter().any(|o| o.name == launchpad.name) {
+            return Err(anyhow::Error::msg(format!(
+                "launchpad {} is defined twice",
+                launchpad.name
+            )));
+        }
+        if hw.servo_pin.is_some() && others.iter().any(|o| o.hardware.servo_pin == hw.servo_pin) {
+            return Err(anyhow::Error::msg(format!(
+                "launchpad {} uses the servo pin of another launchpad",
+                launchpad.name
+            )));
+        }
+        if hw.wifi_adapter.is_some()
+            && others
+                .iter()
+                .any(|o| o.hardware.wifi_adapter == hw.wifi_adapter)
+        {
+            return Err(anyhow::Error::msg(format!(
+                "launchpad {} uses the wifi adapter of another launchpad",
+                launchpad.name
+            )));
+        }
+        let ports = [("command", hw.command_port), ("state", hw.state_port)];
+        for (port, number) in ports.iter().filter(|(_, number)| number.is_some()) {
+            let used = |o: &LaunchpadConfig| {
+                [o.hardware.command_port, o.hardware.state_port].contains(number)
+            };
+            if others.iter().any(used) {
+                return Err(anyhow::Error::msg(format!(
+                    "launchpad {} uses its {} port on another launchpad",
+                    launchpad.name, port
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// the name ends up in tags and AQL queries (`FROM 'launchpad:<name>'`)
+fn validate_name(name: &str) -> Result<(), anyhow::Error> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.');
+    if valid {
+        Ok(())
+    } else {
+        Err(anyhow::Error::msg(format!(
+            "invalid launchpad name '{}', use letters, digits, '-', '_' and '.'",
+            name
+        )))
+    }
+}
+
+#[test]
+fn command_line_overrides_config_file() {
+    let path = std::env::temp_dir().join("launchpad-config-test.toml");
+    std::fs::write(
+        &path,
+        "name = \"Pad-File\"\nactyx_url = \"http://10.0.0.2:4454\"\n[app]\nversion = \"1.2.3\"\n",
+    )
+    .unwrap();
+
+    let config = Config::load(&Args {
+        config: Some(path.clone()),
+        name: Some("Pad-Cli".to_string()),
+        ..Default::default()
+    })
+    .unwrap();
+    assert_eq!(config.launchpads[0].name, "Pad-Cli");
+    assert_eq!(config.actyx_url.as_str(), "http://10.0.0.2:4454/");
+    assert_eq!(config.app_manifest.version, "1.2.3");
+    assert_eq!(config.app_manifest.app_id.as_str(), DEFAULT_APP_ID);
+    assert_eq!(config.snapshot_dir, None);
+
+    std::fs::write(&path, "name = \"Pad-File\"\nunknown = 1\n").unwrap();
+    assert!(Config::load(&Args {
+        config: Some(path),
+        ..Default::default()
+    })
+    .is_err());
+}
+
+#[test]
+fn rejects_invalid_config() {
+    let load = |args: Args| Config::load(&args).map(|c| c.launchpads[0].name.clone());
+    assert_eq!(load(Args::default()).unwrap(), DEFAULT_NAME);
+    assert!(load(Args {
+        name: Some("pad' | 'drone".to_string()),
+        ..Default::default()
+    })
+    .is_err());
+    assert!(load(Args {
+        actyx_url: Some("localhost".to_string()),
+        ..Default::default()
+    })
+    .is_err());
+    assert!(load(Args {
+        app_id: Some("com.acme.launchpad".to_string()),
+        ..Default::default()
+    })
+    .is_err());
+    assert!(load(Args {
+        app_id: Some("com.acme.launchpad".to_string()),
+        app_signature: Some("signed".to_string()),
+        ..Default::default()
+    })
+    .is_ok());
+}
+
+#[test]
+fn reads_a_list_of_launchpads() {
+    let path = std::env::temp_dir().join("launchpad-config-list-test.toml");
+    let launchpads = "on_abort = \"land\"\n[battery]\nmin_launch = 40\n\
+                      [[launchpad]]\nname = \"Pad-1\"\nservo_pin = 12\nwifi_adapter = \"wlan0\"\n\
+                      [[launchpad]]\nname = \"Pad-2\"\non_abort = \"return\"\nservo_pin = 13\n\
+                      drone_ip = \"192.168.10.1\"\nstate_port = 8891\n\
+                      [launchpad.battery]\nmin_launch = 50\nmin_flight = 20\n";
+    std::fs::write(&path, launchpads).unwrap();
+    let load = |name: Option<&str>| {
+        Config::load(&Args {
+            config: Some(path.clone()),
+            name: name.map(|n| n.to_string()),
+            ..Default::default()
+        })
+    };
+
+    let config = load(None).unwrap();
+    assert_eq!(
+        config.launchpads,
+        vec![
+            LaunchpadConfig {
+                name: "Pad-1".to_string(),
+                on_abort: AbortAction::Land,
+                battery: BatteryPolicy {
+                    min_launch: 40,
+                    ..Default::default()
+                },
+                hardware: HardwareConfig {
+                    servo_pin: Some(12),
+                    wifi_adapter: Some("wlan0".to_string()),
+                    drone_ip: None,
+                    command_port: None,
+                    state_port: None,
+                },
+            },
+            LaunchpadConfig {
+                name: "Pad-2".to_string(),
+                on_abort: AbortAction::Return,
+                battery: BatteryPolicy {
+                    min_launch: 50,
+                    min_flight: 20,
+                    ..Default::default()
+                },
+                hardware: HardwareConfig {
+                    servo_pin: Some(13),
+                    wifi_adapter: None,
+                    drone_ip: Some("192.168.10.1".to_string()),
+                    command_port: None,
+                    state_port: Some(8891),
+                },
+            },
+        ]
+    );
+    assert!(load(Some("Pad-3")).is_err());
+
+    std::fs::write(&path, launchpads.replace("13", "12")).unwrap();
+    assert!(load(None).is_err());
+    // the drone of Pad-1 answers to the port Pad-2 receives the state on
+    std::fs::write(
+        &path,
+        launchpads.replace("servo_pin = 12", "command_port = 8891"),
+    )
+    .unwrap();
+    assert!(load(None).is_err());
+    std::fs::write(&path, launchpads.replace("Pad-2", "Pad-1")).unwrap();
+    assert!(load(None).is_err());
+    std::fs::write(&path, launchpads.replace("servo_pin = 13", "servo = 13")).unwrap();
+    assert!(load(None).is_err());
+    std::fs::write(
+        &path,
+        launchpads.replace("min_flight = 20", "min_flight = 60"),
+    )
+    .unwrap();
+    assert!(load(None).is_err());
+}
@@ -1,80 +1,230 @@
 use crate::{
-    hardware::Hardware,
-    twin::{self, resolve_relation},
+    battery::BatteryPolicy,
+    clock::{Clock, SystemClock},
+    fleet,
+    flight::{Alert, Flight},
+    geofence,
+    hardware::{drone_control::emergency, Hardware, HardwareConfig},
+    snapshot::SnapshotStore,
+    twin::{self, resolve_registry, resolve_relation, ConnectionStatus},
     twins::{
         drone_twin::{
-            states::{DroneTwinState, LaunchedState, ReadyState},
-            DroneTwin,
+            events::{
+                BatteryCheckedEvent, BatteryDecision, DroneActivatedEvent, DroneEvent,
+                DroneMissionCompletedEvent, DroneReadyEvent, DroneStatsUpdatedEvent,
+                MissionOutcome,
+            },
+            states::{
+                ChargingState, ConnectedState, DisconnectedState, DroneTwinState, EnabledState,
+                FaultState, LaunchedState, ReadyState, UsedState,
+            },
+            DroneRegistryTwin, DroneTwin,
+        },
+        geofence_twin::{
+            events::{GeofenceEvent, GeofenceViolatedEvent},
+            GeofenceRegistryTwin, GeofenceTwin, GeofenceTwinState,
+        },
+        launchpad_twin::{
+            events::{
+                DroneMountedEvent, LaunchPadEvent, LaunchPadRegisteredEvent, MissionActivatedEvent,
+                MissionDequeuedEvent,
+            },
+            schedule::unix_millis,
+            LaunchpadRegistryTwin, LaunchpadTwin, LaunchpadTwinState,
         },
-        launchpad_twin::{LaunchpadTwin, LaunchpadTwinState},
         mission_twin::{MissionTwin, MissionTwinState},
     },
 };
-use actyx_sdk::HttpClient;
+use actyx_sdk::service::EventService;
 use futures::Stream;
-use std::time::Duration;
-use tokio::{select, sync::mpsc, time::interval};
+use serde::Deserialize;
+use std::{collections::HashSet, sync::Arc, time::Duration};
+use tokio::{
+    select,
+    sync::{mpsc, watch},
+    time::interval,
+};
 use tokio_stream::wrappers::ReceiverStream;
 use tokio_stream::StreamExt;
 use tokio_stream_ext::StreamOpsExt;
 
-pub struct Controller {
+/// what the drone does when its mission is aborted
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AbortAction {
+    /// fly straight back and land at the start point
+    Return,
+    /// land where the drone is
+    Land,
+}
+
+pub struct Controller<S: EventService> {
     name: String,
-    service: HttpClient,
-    hardware: Hardware,
+    service: S,
+    hardware: Hardware<S>,
+    on_abort: AbortAction,
+    battery: BatteryPolicy,
+    snapshots: Option<SnapshotStore>,
+    clock: Arc<dyn Clock>,
+    /// expired missions this launchpad dequeued, until they are gone from the queue
+    dequeued: HashSet<String>,
 }
 #[derive(Clone, Debug)]
 struct AppState {
     pub launchpad: LaunchpadTwinState,
     pub drone: Option<DroneTwinState>,
     pub mission: Option<MissionTwinState>,
+    /// all defined drones
+    pub fleet: Vec<DroneTwinState>,
+    /// the other launchpads, `None` until they are loaded
+    pub launchpads: Option<Vec<LaunchpadTwinState>>,
+    /// `None` until the geofences are loaded
+    pub geofences: Option<Vec<GeofenceTwinState>>,
 }
 
-impl Controller {
-    pub fn new(name: String, service: HttpClient) -> Self {
+impl<S> Controller<S>
+where
+    S: EventService + Sync + 'static,
+{
+    #[allow(dead_code)]
+    pub fn new(name: String, service: S) -> Self {
+        let hardware = Hardware::new(name.clone(), service.clone(), HardwareConfig::default());
+        Self::with_hardware(name, service, hardware)
+    }
+    pub fn with_hardware(name: String, service: S, hardware: Hardware<S>) -> Self {
         Self {
             name,
-            hardware: Hardware::new(service.clone()),
             service,
+            hardware,
+            on_abort: AbortAction::Return,
+            battery: BatteryPolicy::default(),
+            snapshots: None,
+            clock: Arc::new(SystemClock),
+            dequeued: HashSet::new(),
         }
     }
-    fn service(&self) -> HttpClient {
+    pub fn on_abort(mut self, action: AbortAction) -> Self {
+        self.on_abort = action;
+        self
+    }
+    pub fn battery_policy(mut self, policy: BatteryPolicy) -> Self {
+        self.battery = policy;
+        self
+    }
+    /// the twins resume from the snapshots in `store` instead of replaying all events
+    pub fn snapshots(mut self, store: Option<SnapshotStore>) -> Self {
+        self.snapshots = store;
+        self
+    }
+    /// time of the decisions about the twin states, like whether the drone is still enabled
+    #[allow(dead_code)]
+    pub fn clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+    fn service(&self) -> S {
         self.service.clone()
     }
     fn name(&self) -> String {
         self.name.clone()
     }
     pub async fn start(&mut self) -> Result<(), anyhow::Error> {
-        LaunchpadTwin::emit_launchpad_registered(self.service(), self.name()).await?;
+        LaunchPadEvent::emit_launch_pad_registered(
+            self.service(),
+            LaunchPadRegisteredEvent { id: self.name() },
+        )
+        .await?;
+        // emergency stops bypass the twins, the watch ends with this function
+        let _emergency_watch =
+            emergency::spawn_watch(self.service(), self.hardware.emergency_stop());
 
         let launchpad_twin = LaunchpadTwin::new(self.name());
 
-        let launchpad_stream =
-            twin::execute_twin(self.service(), launchpad_twin.clone()).as_stream();
+        let launchpad_executer = twin::execute_twin(
+            self.service(),
+            launchpad_twin.clone(),
+            self.snapshots.clone(),
+        );
+        let connection = launchpad_executer.status();
+        let launchpad_stream = launchpad_executer.as_stream();
 
-        let current_mission = resolve_relation(self.service(), launchpad_twin.clone(), |s| {
-            s.current_mission.map(|id| MissionTwin { id })
-        });
-        let assigned_drone = resolve_relation(self.service(), launchpad_twin.clone(), |s| {
-            s.attached_drone.map(|id| DroneTwin { id })
-        });
+        let current_mission = resolve_relation(
+            self.service(),
+            launchpad_twin.clone(),
+            |s| s.current_mission.map(|id| MissionTwin { id }),
+            self.snapshots.clone(),
+        );
+        let assigned_drone = resolve_relation(
+            self.service(),
+            launchpad_twin.clone(),
+            |s| s.attached_drone.map(|id| DroneTwin { id }),
+            self.snapshots.clone(),
+        );
+        let fleet = resolve_registry(
+            self.service(),
+            DroneRegistryTwin,
+            |ids| ids.into_iter().map(|id| DroneTwin { id }).collect(),
+            self.snapshots.clone(),
+        );
+        let launchpads = resolve_registry(
+            self.service(),
+            LaunchpadRegistryTwin,
+            |ids| ids.into_iter().map(LaunchpadTwin::new).collect(),
+            self.snapshots.clone(),
+        );
+        let geofences = resolve_registry(
+            self.service(),
+            GeofenceRegistryTwin,
+            |ids| ids.into_iter().map(|id| GeofenceTwin { id }).collect(),
+            self.snapshots.clone(),
+        );
 
         let res = self
-            .logic(launchpad_stream, current_mission, assigned_drone)
+            .logic(
+                launchpad_stream,
+                connection,
+                current_mission,
+                assigned_drone,
+                fleet,
+                launchpads,
+                geofences,
+            )
             .await;
         println!("Program terminated with {:#?}", res);
-        Ok(())
+        res
     }
 
+    #[allow(clippy::too_many_arguments)]
     async fn logic(
         &mut self,
         mut launchpad_stream: impl Stream<Item = LaunchpadTwinState> + Unpin,
+        mut connection: watch::Receiver<ConnectionStatus>,
         mut current_mission: impl Stream<Item = MissionTwinState> + Unpin,
         mut assigned_drone: impl Stream<Item = DroneTwinState> + Unpin,
+        mut fleet: impl Stream<Item = Vec<DroneTwinState>> + Unpin,
+        mut launchpads: impl Stream<Item = Vec<LaunchpadTwinState>> + Unpin,
+        mut geofences: impl Stream<Item = Vec<GeofenceTwinState>> + Unpin,
     ) -> Result<(), anyhow::Error> {
-        let mut launchpad_state = None;
+        let mut launchpad_state: Option<LaunchpadTwinState> = None;
         let mut drone_state = None;
         let mut mission_state = None;
+        let mut drones = Vec::new();
+        let mut other_launchpads = None;
+        let mut fleet_state = Vec::new();
+        let mut geofence_state = None;
+        // the latest state of every twin for the handler
+        macro_rules! app_state {
+            ($launchpad:expr) => {
+                AppState {
+                    drone: drone_state.clone(),
+                    launchpad: $launchpad,
+                    mission: mission_state.clone(),
+                    fleet: fleet_state.clone(),
+                    launchpads: other_launchpads.clone(),
+                    geofences: geofence_state.clone(),
+                }
+            };
+        }
 
         let mut state_read = interval(Duration::from_millis(1000));
         let (tx, rx) = mpsc::channel::<AppState>(3);
@@ -85,36 +235,67 @@ impl Controller {
         loop {
             select! {
                 _ = state_read.tick() => {
-                    drone_state.as_ref().map(|s| self.update_states(s));
+                    if let Some(s) = drone_state.as_ref() {
+                        if let Err(e) = self.update_states(s).await {
+                            println!("failed to update the drone stats {:?}", e);
+                        }
+                    }
+                    // scheduled missions get due without a new event
+                    if let Some(launchpad) = launchpad_state.clone() {
+                        if launchpad.current_mission.is_none() && !launchpad.mission_queue.is_empty() {
+                            let _ = tx.send(app_state!(launchpad)).await;
+                        }
+                    }
+                },
+                Ok(()) = connection.changed() => {
+                    let status = *connection.borrow();
+                    println!("{}: connection {:?}", self.name, status);
+                    if status == ConnectionStatus::Failed {
+                        return Err(anyhow::Error::msg("lost the connection to Actyx"));
+                    }
                 },
                 new_launchpad = launchpad_stream.next() => {
-                    launchpad_state = new_launchpad;
-                    let _ = tx.send(AppState {
-                        drone: drone_state.clone(),
-                        launchpad: launchpad_state.clone().unwrap(),
-                        mission: mission_state.clone(),
-                    }).await;
+                    let launchpad = new_launchpad
+                        .ok_or_else(|| anyhow::Error::msg("launchpad twin terminated"))?;
+                    // the relations don't emit when they end
+                    if launchpad.attached_drone.is_none() {
+                        drone_state = None;
+                    }
+                    if launchpad.current_mission.is_none() {
+                        mission_state = None;
+                    }
+                    launchpad_state = Some(launchpad.clone());
+                    let _ = tx.send(app_state!(launchpad)).await;
                 },
                 new_drone = assigned_drone.next() =>{
                     drone_state = new_drone;
                     if let Some(launchpad) = launchpad_state.clone() {
-                        let _ = tx.send(AppState {
-                            drone: drone_state.clone(),
-                            launchpad,
-                            mission: mission_state.clone(),
-                        }).await;
+                        let _ = tx.send(app_state!(launchpad)).await;
                     }
                 },
                 new_mission = current_mission.next() => {
                     mission_state = new_mission;
                     if let Some(launchpad) = launchpad_state.clone() {
-                        let _ = tx.send(AppState {
-                            drone: drone_state.clone(),
-                            launchpad,
-                            mission: mission_state.clone(),
-                        }).await;
+                        let _ = tx.send(app_state!(launchpad)).await;
+                    }
+                },
+                Some(new_drones) = fleet.next() => {
+                    drones = new_drones;
+                    fleet_state = fleet::unmounted(&drones, other_launchpads.as_deref().unwrap_or_default());
+                    // a drone may be available now
+                    if let Some(launchpad) = launchpad_state.clone().filter(|l| l.attached_drone.is_none()) {
+                        let _ = tx.send(app_state!(launchpad)).await;
                     }
                 },
+                Some(new_launchpads) = launchpads.next() => {
+                    let mut new_launchpads = new_launchpads;
+                    new_launchpads.retain(|l| l.id != self.name);
+                    fleet_state = fleet::unmounted(&drones, &new_launchpads);
+                    other_launchpads = Some(new_launchpads);
+                },
+                Some(new_geofences) = geofences.next() => {
+                    geofence_state = Some(new_geofences);
+                },
                 Some(app_state) = state_update.next() => {
                     if let Err(e) = self.handler(app_state.clone()).await {
                         println!("Something is wrong {:?}", e);
@@ -127,87 +308,385 @@ impl Controller {
     async fn update_states(&mut self, drone_state: &DroneTwinState) -> Result<(), anyhow::Error> {
         println!("update_states");
         if let Ok(s) = self.hardware.get_state() {
-            let (battery, id) = match drone_state {
-                DroneTwinState::Undefined(_) => return Ok(()),
-                DroneTwinState::Ready(e) => (e.battery, e.id.to_owned()),
-                DroneTwinState::Launched(e) => (e.battery, e.id.to_owned()),
-                DroneTwinState::Used(e) => (e.battery, e.id.to_owned()),
+            let (battery, id) = match drone_state.battery() {
+                Some(battery) => (battery, drone_state.id()),
+                None => return Ok(()),
             };
 
             println!("battery {} <-> new {}", battery, s.bat);
 
             if (battery as i8 - s.bat).abs() >= 5 {
                 println!("update battery values {:?}", s.bat);
-                DroneTwin::emit_drone_stats_updated(self.service(), id, s.bat as u8).await?;
+                DroneEvent::emit_drone_stats_updated(
+                    self.service(),
+                    DroneStatsUpdatedEvent {
+                        id,
+                        battery: s.bat as u8,
+                    },
+                )
+                .await?;
             }
         };
         Ok(())
     }
 
+    async fn enable_drone(&mut self, id: String) -> Result<(), anyhow::Error> {
+        println!("enable drone");
+        self.hardware.enable_drone().await;
+        DroneEvent::emit_drone_activated(
+            self.service(),
+            DroneActivatedEvent {
+                id,
+                launchpad_id: self.name(),
+            },
+        )
+        .await
+        .map(|_| ())
+    }
+
+    /// Checks the battery before take off and records the decision. A refused mission is completed
+    /// right away, so the launchpad moves on to the next one.
+    async fn launch_approved(
+        &mut self,
+        id: &str,
+        mission: &MissionTwinState,
+        battery: u8,
+    ) -> Result<bool, anyhow::Error> {
+        let battery = self.hardware.battery().unwrap_or(battery);
+        // waypoint 0 is the start point
+        let waypoints = mission.waypoints.get(1..).unwrap_or_default();
+        let mission_drain = self.battery.required(waypoints);
+        let decision = self.battery.check_launch(battery, mission_drain);
+        let required = match decision {
+            BatteryDecision::LaunchRefused => self.battery.min_launch,
+            _ => self.battery.min_flight.saturating_add(mission_drain),
+        };
+        println!(
+            "battery {}% required {}% for mission {}: {:?}",
+            battery, required, mission.id, decision
+        );
+        DroneEvent::emit_battery_checked(
+            self.service(),
+            BatteryCheckedEvent {
+                id: id.to_owned(),
+                mission_id: mission.id.clone(),
+                battery,
+                required,
+                decision,
+            },
+        )
+        .await?;
+
+        if decision == BatteryDecision::LaunchApproved {
+            Ok(true)
+        } else {
+            DroneEvent::emit_drone_mission_completed(
+                self.service(),
+                DroneMissionCompletedEvent {
+                    id: id.to_owned(),
+                    mission_id: mission.id.clone(),
+                    outcome: MissionOutcome::Refused,
+                },
+            )
+            .await?;
+            Ok(false)
+        }
+    }
+
+    /// Checks the route of the version the drone takes off with, the mission may have changed
+    /// since it was activated. A crossing mission is completed without take off.
+    async fn route_approved(
+        &self,
+        id: &str,
+        mission: &MissionTwinState,
+        geofences: &[GeofenceTwinState],
+    ) -> Result<bool, anyhow::Error> {
+        let violation = match geofence::check_route(&mission.waypoints, geofences) {
+            Some(violation) => violation,
+            None => return Ok(true),
+        };
+        println!(
+            "Mission {} version {} {} {}, refuse the launch",
+            mission.id, mission.version, violation.reason, violation.geofence_id
+        );
+        GeofenceEvent::emit_geofence_violated(
+            self.service(),
+            GeofenceViolatedEvent {
+                id: violation.geofence_id,
+                mission_id: mission.id.clone(),
+                drone_id: Some(id.to_owned()),
+                waypoint_id: violation.waypoint_id,
+                reason: violation.reason,
+            },
+        )
+        .await?;
+        DroneEvent::emit_drone_mission_completed(
+            self.service(),
+            DroneMissionCompletedEvent {
+                id: id.to_owned(),
+                mission_id: mission.id.clone(),
+                outcome: MissionOutcome::Refused,
+            },
+        )
+        .await?;
+        Ok(false)
+    }
+
+    /// Mounts the best drone of the fleet for `mission`. A used drone is made ready first.
+    async fn mount_drone(
+        &mut self,
+        mission: &MissionTwinState,
+        fleet: &[DroneTwinState],
+    ) -> Result<(), anyhow::Error> {
+        let drone = match fleet::select_drone(fleet, mission, &self.battery) {
+            Some(drone) => drone,
+            None => {
+                println!("no drone of the fleet can fly mission {}", mission.id);
+                return Ok(());
+            }
+        };
+        println!("mount drone {} for mission {}", drone.id(), mission.id);
+        if let DroneTwinState::Used(UsedState { id, .. }) = drone {
+            DroneEvent::emit_drone_ready(self.service(), DroneReadyEvent { id: id.clone() })
+                .await?;
+        }
+        LaunchPadEvent::emit_drone_mounted(
+            self.service(),
+            DroneMountedEvent {
+                id: self.name(),
+                drone: drone.id(),
+            },
+        )
+        .await
+        .map(|_| ())
+    }
+
+    /// the first geofence the planned route of the mission `id` crosses
+    async fn route_violation(
+        &self,
+        id: &str,
+        geofences: &[GeofenceTwinState],
+    ) -> Result<Option<geofence::Violation>, anyhow::Error> {
+        if geofences.is_empty() {
+            return Ok(None);
+        }
+        let mission = MissionTwin { id: id.to_owned() };
+        let mission = twin::current_state(self.service(), mission, self.snapshots.as_ref()).await;
+        let mission = (*mission)?;
+        Ok(geofence::check_route(&mission.waypoints, geofences))
+    }
+
+    /// Records why the flying drone stops its mission and brings it down. With a low battery it
+    /// lands in place.
+    async fn stop_mission(
+        &mut self,
+        id: String,
+        mission: &MissionTwinState,
+        alert: Alert,
+    ) -> Result<(), anyhow::Error> {
+        let mut return_home = self.on_abort == AbortAction::Return;
+        match alert {
+            Alert::Aborted => println!("mission {} aborted", mission.id),
+            Alert::BatteryLow(battery) => {
+                println!("battery low {}%, land now", battery);
+                DroneEvent::emit_battery_checked(
+                    self.service(),
+                    BatteryCheckedEvent {
+                        id: id.clone(),
+                        mission_id: mission.id.clone(),
+                        battery,
+                        required: self.battery.min_flight,
+                        decision: BatteryDecision::Landing,
+                    },
+                )
+                .await?;
+                return_home = false;
+            }
+            Alert::Violation(violation) => {
+                println!(
+                    "drone {} {} of geofence {}",
+                    id, violation.reason, violation.geofence_id
+                );
+                GeofenceEvent::emit_geofence_violated(
+                    self.service(),
+                    GeofenceViolatedEvent {
+                        id: violation.geofence_id,
+                        mission_id: mission.id.clone(),
+                        drone_id: Some(id.clone()),
+                        waypoint_id: violation.waypoint_id,
+                        reason: violation.reason,
+                    },
+                )
+                .await?;
+            }
+        }
+        self.hardware.abort_mission(id, mission, return_home).await
+    }
+
+    async fn complete_mission(
+        &mut self,
+        id: String,
+        mission: &MissionTwinState,
+    ) -> Result<(), anyhow::Error> {
+        self.hardware.land_now(id.clone()).await?;
+        DroneEvent::emit_drone_mission_completed(
+            self.service(),
+            DroneMissionCompletedEvent {
+                id,
+                mission_id: mission.id.clone(),
+                outcome: MissionOutcome::Completed,
+            },
+        )
+        .await
+        .map(|_| ())
+    }
+
     async fn handler(&mut self, app_state: AppState) -> Result<(), anyhow::Error> {
         let launchpad_state = app_state.launchpad;
         let mission_state = app_state.mission;
         let drone_state = app_state.drone;
+        let geofences = app_state.geofences;
 
-        if let (Some(drone_state), Some(mission)) = (drone_state, mission_state) {
+        if let (Some(drone_state), Some(mission)) = (drone_state, mission_state.clone()) {
+            // a flying drone executes the version it took off with
+            let mission = match &drone_state {
+                DroneTwinState::Launched(s) => match mission.clone().pinned(s.mission_version) {
+                    Ok(pinned) => pinned,
+                    Err(e) => {
+                        // the waypoints the drone took off with are unknown, land in place
+                        println!("{}, land drone {}", e, s.id);
+                        return self
+                            .hardware
+                            .abort_mission(s.id.clone(), &mission, false)
+                            .await;
+                    }
+                },
+                _ => mission,
+            };
+            // another launchpad may have picked the drone at the same time
+            let on_the_ground = matches!(
+                drone_state,
+                DroneTwinState::Ready(_)
+                    | DroneTwinState::Enabled(_)
+                    | DroneTwinState::Disconnected(_)
+                    | DroneTwinState::Connected(_)
+            );
+            if on_the_ground {
+                match app_state.launchpads.as_deref() {
+                    Some(others) if fleet::holds_drone(&launchpad_state, others) => (),
+                    Some(_) => {
+                        println!(
+                            "drone {} was mounted on another launchpad first, wait for its release",
+                            drone_state.id()
+                        );
+                        return Ok(());
+                    }
+                    None => {
+                        println!("Wait for the launchpads to start {}", mission.id);
+                        return Ok(());
+                    }
+                }
+            }
             match drone_state {
                 DroneTwinState::Undefined(_) => {
                     println!("FU: Starting an undefined drone!? NO!")
                 }
                 // drone is defined
-                DroneTwinState::Ready(ref d @ ReadyState { .. }) if !d.is_enabled() => {
-                    println!("enable drone");
-                    self.hardware.enable_drone().await;
-                    DroneTwin::emit_drone_activated(self.service(), d.id.to_owned(), self.name())
-                        .await?;
+                DroneTwinState::Ready(ReadyState { id, .. }) => self.enable_drone(id).await?,
+                // the connect window passed
+                DroneTwinState::Enabled(ref d) if !d.is_enabled(self.clock.now()) => {
+                    self.enable_drone(d.id.clone()).await?
                 }
-                // drone is enabled
-                DroneTwinState::Ready(ReadyState {
-                    id,
-                    ssid,
-                    ip,
-                    connected: false,
-                    ..
-                }) => {
+                // drone is enabled, or lost the connection on the ground
+                DroneTwinState::Enabled(EnabledState { id, ssid, ip, .. })
+                | DroneTwinState::Disconnected(DisconnectedState { id, ssid, ip, .. }) => {
                     println!("connect to drone now");
-                    self.hardware
-                        .connect_now(id.clone(), ssid.clone(), ip.clone())
-                        .await?
+                    self.hardware.connect_now(id, ssid, ip).await?
                 }
                 // drone is enabled / and connected
-                DroneTwinState::Ready(ReadyState {
+                DroneTwinState::Connected(ConnectedState {
                     id,
                     ssid,
                     ip,
-                    connected: true,
-                    ..
+                    battery,
                 }) => {
-                    self.hardware
-                        .take_off_now(id.clone(), ssid.clone(), ip.clone(), mission.id.to_owned())
-                        .await?
+                    let geofences = match geofences.as_deref() {
+                        Some(geofences) => geofences,
+                        None => {
+                            println!("Wait for the geofences to launch {}", mission.id);
+                            return Ok(());
+                        }
+                    };
+                    if self.route_approved(&id, &mission, geofences).await?
+                        && self.launch_approved(&id, &mission, battery).await?
+                    {
+                        self.hardware
+                            .take_off_now(
+                                id.clone(),
+                                ssid.clone(),
+                                ip.clone(),
+                                mission.id.to_owned(),
+                                mission.version,
+                            )
+                            .await?
+                    }
                 }
+                // mission aborted, stop executing waypoints and bring the drone down
+                DroneTwinState::Launched(LaunchedState {
+                    id, aborted: true, ..
+                }) => self.stop_mission(id, &mission, Alert::Aborted).await?,
                 // drone is in the air and the current mission is *not* completed an currently not moving to the next waypoint
                 DroneTwinState::Launched(LaunchedState {
                     id,
                     at_waypoint_id,
                     target_waypoint_id: None,
                     completed: false,
+                    battery,
                     ..
                 }) => {
-                    self.hardware
-                        .exec_waypoint(id.to_owned(), at_waypoint_id as usize, &mission)
-                        .await?;
+                    let flight = Flight {
+                        drone_id: &id,
+                        mission: &mission,
+                        at_waypoint: at_waypoint_id as usize,
+                        geofences: geofences.as_deref().unwrap_or_default(),
+                        policy: &self.battery,
+                        battery,
+                    };
+                    let tracker = self.hardware.tracker();
+                    if let Some(alert) = flight.check(tracker.as_ref()) {
+                        self.stop_mission(id, &mission, alert).await?;
+                    } else if mission.waypoints.len() <= at_waypoint_id as usize + 1 {
+                        // the launchpad releases the drone with the completed mission, land first
+                        self.complete_mission(id, &mission).await?;
+                    } else {
+                        let service = self.service();
+                        let waypoint = self.hardware.exec_waypoint(
+                            id.to_owned(),
+                            at_waypoint_id as usize,
+                            &mission,
+                        );
+                        // the waypoint ends early with an alert
+                        let alert = select! {
+                            res = waypoint => {
+                                res?;
+                                None
+                            }
+                            alert = flight.watch(service, tracker) => Some(alert),
+                        };
+                        if let Some(alert) = alert {
+                            if let Err(e) = self.hardware.hover() {
+                                println!("failed to stop the drone {}", e);
+                            }
+                            self.stop_mission(id, &mission, alert).await?;
+                        }
+                    }
                 }
                 // Mission completed land now!
                 DroneTwinState::Launched(LaunchedState {
                     id,
                     completed: true,
                     ..
-                }) => {
-                    self.hardware.land_now(id.clone()).await?;
-                    DroneTwin::emit_drone_mission_completed(self.service(), id, mission.id.clone())
-                        .await?;
-                }
+                }) => self.complete_mission(id, &mission).await?,
                 // drone is on the way to the next waypoint, and wait that the drone arrives on the next Waypoint
                 DroneTwinState::Launched(LaunchedState {
                     id,
@@ -224,14 +703,76 @@ impl Controller {
                 DroneTwinState::Used(_) => {
                     println!("FU do nothing when already in used state")
                 }
+                DroneTwinState::Charging(ChargingState { id, battery, .. }) => {
+                    println!("Drone {} is charging, {}%", id, battery)
+                }
+                DroneTwinState::Fault(FaultState { id, reason, .. }) => {
+                    println!("Drone {} has a fault: {}", id, reason)
+                }
             }
+        } else if let (None, Some(mission)) = (&launchpad_state.attached_drone, &mission_state) {
+            self.mount_drone(mission, &app_state.fleet).await?;
         } else {
-            if let Some(next_mission) = launchpad_state.mission_queue.first() {
+            let now = unix_millis(self.clock.now());
+            // the tick sends the same state again until the dequeue reaches the twin
+            let waiting = launchpad_state.waiting_missions();
+            self.dequeued.retain(|id| waiting.contains(id));
+            for mission_id in launchpad_state.expired_missions(now) {
+                if self.dequeued.contains(&mission_id) {
+                    continue;
+                }
+                println!("Mission {} expired", mission_id);
+                LaunchPadEvent::emit_mission_dequeued(
+                    self.service(),
+                    MissionDequeuedEvent {
+                        launchpad_id: self.name(),
+                        mission_id: mission_id.clone(),
+                    },
+                )
+                .await?;
+                self.dequeued.insert(mission_id);
+            }
+            if let Some(next_mission) = launchpad_state.next_mission(now) {
+                let geofences = match geofences {
+                    Some(geofences) => geofences,
+                    None => {
+                        println!("Wait for the geofences to activate {}", next_mission);
+                        return Ok(());
+                    }
+                };
+                if let Some(violation) = self.route_violation(&next_mission, &geofences).await? {
+                    println!(
+                        "Mission {} {} {}, reject it",
+                        next_mission, violation.reason, violation.geofence_id
+                    );
+                    GeofenceEvent::emit_geofence_violated(
+                        self.service(),
+                        GeofenceViolatedEvent {
+                            id: violation.geofence_id,
+                            mission_id: next_mission.to_owned(),
+                            drone_id: None,
+                            waypoint_id: violation.waypoint_id,
+                            reason: violation.reason,
+                        },
+                    )
+                    .await?;
+                    LaunchPadEvent::emit_mission_dequeued(
+                        self.service(),
+                        MissionDequeuedEvent {
+                            launchpad_id: self.name(),
+                            mission_id: next_mission.to_owned(),
+                        },
+                    )
+                    .await?;
+                    return Ok(());
+                }
                 println!("Activate next mission {}", next_mission);
-                LaunchpadTwin::emit_mission_activated(
+                LaunchPadEvent::emit_mission_activated(
                     self.service(),
-                    self.name(),
-                    next_mission.to_owned(),
+                    MissionActivatedEvent {
+                        launchpad_id: self.name(),
+                        mission_id: next_mission.to_owned(),
+                    },
                 )
                 .await?;
             }
@@ -241,3 +782,1093 @@ impl Controller {
         Ok(())
     }
 }
+
+/// controller of `pad-1` flying the simulated `drone`
+#[cfg(test)]
+fn simulated_controller(
+    service: &crate::memory_service::MemoryEventService,
+    drone: &crate::hardware::drone_control::simulated::SimulatedDrone,
+) -> Controller<crate::memory_service::MemoryEventService> {
+    use crate::hardware::drone_control::DroneControl;
+
+    let hardware = Hardware::with_drone(
+        "pad-1".to_string(),
+        service.clone(),
+        Default::default(),
+        DroneControl::with_driver(drone.clone()),
+    );
+    Controller::with_hardware("pad-1".to_string(), service.clone(), hardware)
+}
+
+/// Runs `controller` until `test` is done. The tests run on paused time, the minute `test`
+/// may take passes as soon as nothing is left to do.
+#[cfg(test)]
+async fn run_until<T>(
+    controller: &mut Controller<crate::memory_service::MemoryEventService>,
+    test: impl std::future::Future<Output = T>,
+) -> T {
+    select! {
+        _ = controller.start() => panic!("controller terminated"),
+        res = tokio::time::timeout(Duration::from_secs(60), test) => res.expect("test timed out"),
+    }
+}
+
+/// polls `done` every 50ms until it holds
+#[cfg(test)]
+async fn wait_for(mut done: impl FnMut() -> bool) {
+    while !done() {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+}
+
+#[cfg(test)]
+fn launchpad_events(
+    service: &crate::memory_service::MemoryEventService,
+) -> Vec<crate::twins::launchpad_twin::events::LaunchPadEvent> {
+    service
+        .events()
+        .into_iter()
+        .filter_map(|e| e.payload.extract().ok())
+        .collect()
+}
+
+#[cfg(test)]
+fn drone_events(
+    service: &crate::memory_service::MemoryEventService,
+) -> Vec<crate::twins::drone_twin::events::DroneEvent> {
+    service
+        .events()
+        .into_iter()
+        .filter_map(|e| e.payload.extract().ok())
+        .collect()
+}
+
+/// outcome of the first completed mission
+#[cfg(test)]
+fn mission_outcome(
+    service: &crate::memory_service::MemoryEventService,
+) -> Option<crate::twins::drone_twin::events::MissionOutcome> {
+    use crate::twins::drone_twin::events::DroneEvent;
+    drone_events(service).into_iter().find_map(|e| match e {
+        DroneEvent::DroneMissionCompleted(e) => Some(e.outcome),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+fn landed(drone: &crate::hardware::drone_control::simulated::SimulatedDrone) -> bool {
+    drone.commands().contains(&"land".to_string()) && !drone.is_flying()
+}
+
+#[tokio::test(start_paused = true)]
+async fn activates_the_first_queued_mission() {
+    use crate::memory_service::MemoryEventService;
+    use crate::twins::launchpad_twin::events as ev;
+
+    let service = MemoryEventService::new();
+    ev::LaunchPadEvent::emit_mission_queued(
+        service.clone(),
+        ev::MissionQueuedEvent {
+            launchpad_id: "pad-1".to_string(),
+            mission_id: "m1".to_string(),
+            schedule: None,
+        },
+    )
+    .await
+    .unwrap();
+
+    let activated = || {
+        launchpad_events(&service)
+            .into_iter()
+            .find_map(|e| match e {
+                ev::LaunchPadEvent::MissionActivated(e) => Some(e),
+                _ => None,
+            })
+    };
+    let mut controller = Controller::new("pad-1".to_string(), service.clone());
+    run_until(&mut controller, wait_for(|| activated().is_some())).await;
+    let activated = activated().unwrap();
+    assert_eq!(activated.launchpad_id, "pad-1");
+    assert_eq!(activated.mission_id, "m1");
+}
+
+#[tokio::test(start_paused = true)]
+async fn activates_scheduled_missions_when_they_are_due() {
+    use crate::clock::FakeClock;
+    use crate::memory_service::MemoryEventService;
+    use crate::twins::launchpad_twin::{events as ev, schedule::Schedule};
+    use std::time::SystemTime;
+
+    let now = SystemTime::now();
+    let service = MemoryEventService::new();
+    for (mission_id, schedule) in [
+        (
+            "later",
+            Schedule {
+                not_before: Some(unix_millis(now + Duration::from_secs(3600))),
+                ..Default::default()
+            },
+        ),
+        (
+            "expired",
+            Schedule {
+                expires: Some(unix_millis(now - Duration::from_secs(60))),
+                ..Default::default()
+            },
+        ),
+    ] {
+        ev::LaunchPadEvent::emit_mission_queued(
+            service.clone(),
+            ev::MissionQueuedEvent {
+                launchpad_id: "pad-1".to_string(),
+                mission_id: mission_id.to_string(),
+                schedule: Some(schedule),
+            },
+        )
+        .await
+        .unwrap();
+    }
+
+    let clock = FakeClock::new(now);
+    let mut controller = Controller::new("pad-1".to_string(), service.clone()).clock(clock.clone());
+    let activated = || {
+        launchpad_events(&service)
+            .into_iter()
+            .find_map(|e| match e {
+                ev::LaunchPadEvent::MissionActivated(e) => Some(e),
+                _ => None,
+            })
+    };
+    run_until(&mut controller, async {
+        wait_for(|| {
+            launchpad_events(&service).iter().any(
+                |e| matches!(e, ev::LaunchPadEvent::MissionDequeued(e) if e.mission_id == "expired"),
+            )
+        })
+        .await;
+        // a tick of the controller passes without a due mission
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        assert!(activated().is_none());
+        let dequeued = launchpad_events(&service)
+            .into_iter()
+            .filter(|e| matches!(e, ev::LaunchPadEvent::MissionDequeued(_)))
+            .count();
+        assert_eq!(dequeued, 1);
+
+        clock.advance(Duration::from_secs(7200));
+        wait_for(|| activated().is_some()).await;
+    })
+    .await;
+    assert_eq!(activated().unwrap().mission_id, "later");
+}
+/// defines drone `d1` and mission `m1`, mounts the drone on `pad-1` and queues the mission
+#[cfg(test)]
+async fn queue_mission(
+    service: &crate::memory_service::MemoryEventService,
+    waypoints: Vec<crate::twins::mission_twin::types::Waypoint>,
+) {
+    use crate::twins::{
+        drone_twin::events as drone_ev, launchpad_twin::events as ev,
+        mission_twin::events as mission_ev,
+    };
+
+    drone_ev::DroneEvent::emit_drone_defined(
+        service.clone(),
+        drone_ev::DroneDefinedEvent {
+            id: "d1".to_string(),
+            ssid: "TELLO-D1".to_string(),
+            ip: "127.0.0.1".to_string(),
+        },
+    )
+    .await
+    .unwrap();
+    mission_ev::MissionEvent::emit_define_mission(
+        service.clone(),
+        mission_ev::DefineMissionEvent {
+            id: "m1".to_string(),
+            name: "square".to_string(),
+            waypoints,
+            version: Some(1),
+        },
+    )
+    .await
+    .unwrap();
+    ev::LaunchPadEvent::emit_drone_mounted(
+        service.clone(),
+        ev::DroneMountedEvent {
+            id: "pad-1".to_string(),
+            drone: "d1".to_string(),
+        },
+    )
+    .await
+    .unwrap();
+    ev::LaunchPadEvent::emit_mission_queued(
+        service.clone(),
+        ev::MissionQueuedEvent {
+            launchpad_id: "pad-1".to_string(),
+            mission_id: "m1".to_string(),
+            schedule: None,
+        },
+    )
+    .await
+    .unwrap();
+}
+
+#[cfg(test)]
+fn goto(distance: f32) -> crate::twins::mission_twin::types::Waypoint {
+    use crate::twins::mission_twin::types::*;
+    Waypoint::Goto(GoToWaypoint {
+        map_x: 0.0,
+        map_y: 0.0,
+        height: 100,
+        angle: None,
+        distance,
+        duration: 0.0,
+    })
+}
+
+#[tokio::test(start_paused = true)]
+async fn mounts_the_drone_of_the_fleet_with_the_most_battery() {
+    use crate::memory_service::MemoryEventService;
+    use crate::twins::{
+        drone_twin::events as drone_ev, launchpad_twin::events as ev,
+        mission_twin::events as mission_ev,
+    };
+
+    let service = MemoryEventService::new();
+    for (id, battery) in [("d1", 40), ("d2", 90)] {
+        drone_ev::DroneEvent::emit_drone_defined(
+            service.clone(),
+            drone_ev::DroneDefinedEvent {
+                id: id.to_string(),
+                ssid: format!("TELLO-{}", id),
+                ip: "127.0.0.1".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+        drone_ev::DroneEvent::emit_drone_stats_updated(
+            service.clone(),
+            drone_ev::DroneStatsUpdatedEvent {
+                id: id.to_string(),
+                battery,
+            },
+        )
+        .await
+        .unwrap();
+    }
+    mission_ev::MissionEvent::emit_define_mission(
+        service.clone(),
+        mission_ev::DefineMissionEvent {
+            id: "m1".to_string(),
+            name: "square".to_string(),
+            waypoints: vec![goto(0.0), goto(2.0)],
+            version: Some(1),
+        },
+    )
+    .await
+    .unwrap();
+    ev::LaunchPadEvent::emit_mission_queued(
+        service.clone(),
+        ev::MissionQueuedEvent {
+            launchpad_id: "pad-1".to_string(),
+            mission_id: "m1".to_string(),
+            schedule: None,
+        },
+    )
+    .await
+    .unwrap();
+
+    let mounted = || {
+        launchpad_events(&service)
+            .into_iter()
+            .find_map(|e| match e {
+                ev::LaunchPadEvent::DroneMounted(e) => Some(e),
+                _ => None,
+            })
+    };
+    let mut controller = Controller::new("pad-1".to_string(), service.clone());
+    run_until(&mut controller, wait_for(|| mounted().is_some())).await;
+    let mounted = mounted().unwrap();
+    assert_eq!(mounted.id, "pad-1");
+    assert_eq!(mounted.drone, "d2");
+}
+
+#[tokio::test(start_paused = true)]
+async fn only_one_launchpad_activates_a_drone_they_picked_together() {
+    use crate::memory_service::MemoryEventService;
+    use crate::twins::{
+        drone_twin::events as drone_ev, launchpad_twin::events as ev,
+        mission_twin::events as mission_ev,
+    };
+
+    let service = MemoryEventService::new();
+    drone_ev::DroneEvent::emit_drone_defined(
+        service.clone(),
+        drone_ev::DroneDefinedEvent {
+            id: "d1".to_string(),
+            ssid: "TELLO-d1".to_string(),
+            ip: "127.0.0.1".to_string(),
+        },
+    )
+    .await
+    .unwrap();
+    drone_ev::DroneEvent::emit_drone_stats_updated(
+        service.clone(),
+        drone_ev::DroneStatsUpdatedEvent {
+            id: "d1".to_string(),
+            battery: 90,
+        },
+    )
+    .await
+    .unwrap();
+    for (pad, mission) in [("pad-1", "m1"), ("pad-2", "m2")] {
+        mission_ev::MissionEvent::emit_define_mission(
+            service.clone(),
+            mission_ev::DefineMissionEvent {
+                id: mission.to_string(),
+                name: "square".to_string(),
+                waypoints: vec![goto(0.0), goto(2.0)],
+                version: Some(1),
+            },
+        )
+        .await
+        .unwrap();
+        ev::LaunchPadEvent::emit_mission_queued(
+            service.clone(),
+            ev::MissionQueuedEvent {
+                launchpad_id: pad.to_string(),
+                mission_id: mission.to_string(),
+                schedule: None,
+            },
+        )
+        .await
+        .unwrap();
+    }
+
+    let activations = || {
+        drone_events(&service)
+            .into_iter()
+            .filter_map(|e| match e {
+                drone_ev::DroneEvent::DroneActivated(e) => Some(e.launchpad_id),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+    };
+    let mut pad_1 = Controller::new("pad-1".to_string(), service.clone());
+    let mut pad_2 = Controller::new("pad-2".to_string(), service.clone());
+    // pad-2 runs along while pad-1 runs the test
+    run_until(&mut pad_1, async {
+        select! {
+            _ = pad_2.start() => panic!("controller terminated"),
+            _ = async {
+                // enabling the drone takes 5s
+                wait_for(|| !activations().is_empty()).await;
+                tokio::time::sleep(Duration::from_millis(1000)).await;
+            } => (),
+        }
+    })
+    .await;
+    assert_eq!(activations().len(), 1, "{:?}", activations());
+}
+
+#[tokio::test(start_paused = true)]
+async fn flies_a_mission_with_a_simulated_drone() {
+    use crate::hardware::drone_control::simulated::{SimulatedDrone, SimulationConfig};
+    use crate::memory_service::MemoryEventService;
+    use crate::twins::{drone_twin::events as drone_ev, mission_twin::types::*};
+
+    let service = MemoryEventService::new();
+    queue_mission(
+        &service,
+        vec![
+            goto(0.0),
+            goto(2.0),
+            Waypoint::Turn(TurnWaypoint {
+                deg: 90,
+                duration: 0.0,
+            }),
+            goto(1.0),
+        ],
+    )
+    .await;
+
+    let drone = SimulatedDrone::new(SimulationConfig {
+        time_scale: 0.0,
+        ..Default::default()
+    });
+    let mut controller = simulated_controller(&service, &drone);
+    run_until(&mut controller, wait_for(|| landed(&drone))).await;
+    assert_eq!(
+        drone.commands()[..6],
+        [
+            "command",
+            "takeoff",
+            "go 200 0 0 100",
+            "cw 90",
+            "go 100 0 0 100",
+            "land"
+        ]
+    );
+    let (x, y, ..) = drone.pose();
+    assert_eq!((x.round(), y.round()), (200.0, -100.0));
+    assert_eq!(
+        mission_outcome(&service),
+        Some(drone_ev::MissionOutcome::Completed)
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn a_failed_command_aborts_the_mission() {
+    use crate::hardware::drone_control::simulated::{
+        SimulatedDrone, SimulatedFailure, SimulationConfig,
+    };
+    use crate::memory_service::MemoryEventService;
+    use crate::twins::drone_twin::events as drone_ev;
+
+    let service = MemoryEventService::new();
+    queue_mission(&service, vec![goto(0.0), goto(2.0), goto(1.0)]).await;
+
+    let drone = SimulatedDrone::new(SimulationConfig {
+        time_scale: 0.0,
+        ..Default::default()
+    });
+    drone.inject_failure("go", SimulatedFailure::Error("error".to_string()));
+    let mut controller = simulated_controller(&service, &drone);
+    run_until(
+        &mut controller,
+        wait_for(|| mission_outcome(&service).is_some()),
+    )
+    .await;
+    assert_eq!(
+        mission_outcome(&service),
+        Some(drone_ev::MissionOutcome::Aborted)
+    );
+    assert_eq!(drone.commands()[2..], ["go 200 0 0 100", "land"]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn flies_the_mission_version_it_took_off_with() {
+    use crate::hardware::drone_control::simulated::{SimulatedDrone, SimulationConfig};
+    use crate::memory_service::MemoryEventService;
+    use crate::twins::{
+        drone_twin::events as drone_ev,
+        mission_twin::{events as mission_ev, types::*},
+    };
+
+    let service = MemoryEventService::new();
+    queue_mission(&service, vec![goto(0.0), goto(2.0), goto(1.0)]).await;
+
+    let drone = SimulatedDrone::new(SimulationConfig {
+        time_scale: 0.0,
+        ..Default::default()
+    });
+    let mut controller = simulated_controller(&service, &drone);
+    // the mission is changed right after take off
+    run_until(&mut controller, async {
+        wait_for(|| {
+            drone_events(&service)
+                .iter()
+                .any(|e| matches!(e, drone_ev::DroneEvent::DroneLaunched(_)))
+        })
+        .await;
+        mission_ev::MissionEvent::emit_define_mission(
+            service.clone(),
+            mission_ev::DefineMissionEvent {
+                id: "m1".to_string(),
+                name: "square".to_string(),
+                waypoints: vec![
+                    goto(0.0),
+                    Waypoint::Turn(TurnWaypoint {
+                        deg: 90,
+                        duration: 0.0,
+                    }),
+                    goto(3.0),
+                ],
+                version: Some(2),
+            },
+        )
+        .await
+        .unwrap();
+        wait_for(|| landed(&drone)).await;
+    })
+    .await;
+    assert_eq!(
+        drone.commands()[..5],
+        [
+            "command",
+            "takeoff",
+            "go 200 0 0 100",
+            "go 100 0 0 100",
+            "land"
+        ]
+    );
+    assert!(drone_events(&service).iter().any(|e| matches!(
+        e,
+        drone_ev::DroneEvent::DroneLaunched(drone_ev::DroneLaunchedEvent {
+            mission_version: Some(1),
+            ..
+        })
+    )));
+}
+
+#[tokio::test(start_paused = true)]
+async fn aborted_mission_returns_to_the_start_point() {
+    use crate::hardware::drone_control::simulated::{SimulatedDrone, SimulationConfig};
+    use crate::memory_service::MemoryEventService;
+    use crate::twins::{drone_twin::events as drone_ev, mission_twin::types::*};
+
+    let service = MemoryEventService::new();
+    queue_mission(
+        &service,
+        vec![
+            goto(0.0),
+            goto(2.0),
+            Waypoint::Turn(TurnWaypoint {
+                deg: 90,
+                duration: 0.0,
+            }),
+            Waypoint::Delay(DelayWaypoint { duration: 1000.0 }),
+            goto(1.0),
+        ],
+    )
+    .await;
+
+    let drone = SimulatedDrone::new(SimulationConfig {
+        time_scale: 0.0,
+        ..Default::default()
+    });
+    let mut controller = simulated_controller(&service, &drone);
+    run_until(&mut controller, async {
+        // abort while the drone waits at the delay waypoint
+        wait_for(|| {
+            drone_events(&service).iter().any(|e| {
+                matches!(e, drone_ev::DroneEvent::DroneStartedToNextWaypoint(e) if e.waypoint_id == 3)
+            })
+        })
+        .await;
+        drone_ev::DroneEvent::emit_mission_aborted(
+            service.clone(),
+            drone_ev::MissionAbortedEvent {
+                id: "d1".into(),
+                mission_id: "m1".into(),
+                reason: None,
+            },
+        )
+        .await
+        .unwrap();
+        wait_for(|| landed(&drone)).await;
+    })
+    .await;
+    assert_eq!(
+        drone.commands()[2..],
+        [
+            "go 200 0 0 100",
+            "cw 90",
+            // the abort ends the delay
+            "stop",
+            // straight back from the tracked pose
+            "cw 90",
+            "go 200 0 0 100",
+            "ccw 180",
+            "land"
+        ]
+    );
+    let (x, y, _, heading) = drone.pose();
+    assert_eq!((x.round(), y.round(), heading), (0.0, 0.0, 0.0));
+    assert_eq!(
+        mission_outcome(&service),
+        Some(drone_ev::MissionOutcome::Aborted)
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn an_abort_stops_the_waypoint_in_flight() {
+    use crate::hardware::drone_control::simulated::{SimulatedDrone, SimulationConfig};
+    use crate::memory_service::MemoryEventService;
+    use crate::twins::drone_twin::events as drone_ev;
+
+    let service = MemoryEventService::new();
+    queue_mission(&service, vec![goto(0.0), goto(4.0)]).await;
+
+    // the go takes 2s
+    let drone = SimulatedDrone::new(SimulationConfig {
+        time_scale: 0.5,
+        ..Default::default()
+    });
+    let mut controller = simulated_controller(&service, &drone);
+    run_until(&mut controller, async {
+        wait_for(|| drone.commands().last().map(String::as_str) == Some("go 400 0 0 100")).await;
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        drone_ev::DroneEvent::emit_mission_aborted(
+            service.clone(),
+            drone_ev::MissionAbortedEvent {
+                id: "d1".into(),
+                mission_id: "m1".into(),
+                reason: None,
+            },
+        )
+        .await
+        .unwrap();
+        wait_for(|| landed(&drone)).await;
+    })
+    .await;
+    // back from about 1m, without flying the other 3m first
+    let commands = drone.commands();
+    assert_eq!(commands[2..5], ["go 400 0 0 100", "stop", "cw 180"]);
+    assert_eq!(commands[6..], ["ccw 180", "land"]);
+    let (x, y, _, heading) = drone.pose();
+    assert!(x.hypot(y) < 5.0, "{} {}", x, y);
+    assert_eq!(heading, 0.0);
+}
+
+#[tokio::test(start_paused = true)]
+async fn refuses_to_launch_with_a_low_battery() {
+    use crate::hardware::drone_control::simulated::{SimulatedDrone, SimulationConfig};
+    use crate::memory_service::MemoryEventService;
+    use crate::twins::drone_twin::events as drone_ev;
+
+    let service = MemoryEventService::new();
+    queue_mission(&service, vec![goto(0.0), goto(2.0)]).await;
+
+    let drone = SimulatedDrone::new(SimulationConfig {
+        time_scale: 0.0,
+        ..Default::default()
+    });
+    drone.set_battery(25);
+    let mut controller = simulated_controller(&service, &drone);
+    run_until(
+        &mut controller,
+        wait_for(|| mission_outcome(&service).is_some()),
+    )
+    .await;
+    assert_eq!(
+        mission_outcome(&service),
+        Some(drone_ev::MissionOutcome::Refused)
+    );
+    assert_eq!(drone.commands(), ["command"]);
+    let checked: Vec<_> = drone_events(&service)
+        .into_iter()
+        .filter_map(|e| match e {
+            drone_ev::DroneEvent::BatteryChecked(e) => Some(e),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(checked.len(), 1);
+    assert_eq!(
+        (checked[0].battery, checked[0].required, checked[0].decision),
+        (25, 30, drone_ev::BatteryDecision::LaunchRefused)
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn enables_the_drone_again_when_the_clock_passed_the_enable_window() {
+    use crate::clock::FakeClock;
+    use crate::hardware::drone_control::simulated::{SimulatedDrone, SimulationConfig};
+    use crate::memory_service::MemoryEventService;
+    use crate::twins::drone_twin::events as drone_ev;
+    use std::time::SystemTime;
+
+    let service = MemoryEventService::new();
+    queue_mission(&service, vec![goto(0.0), goto(2.0)]).await;
+
+    let drone = SimulatedDrone::new(SimulationConfig {
+        time_scale: 0.0,
+        ..Default::default()
+    });
+    // every activation is already out of date for the controller
+    let clock = FakeClock::new(SystemTime::now() + Duration::from_secs(3600));
+    let mut controller = simulated_controller(&service, &drone).clock(clock);
+    let activations = || {
+        drone_events(&service)
+            .iter()
+            .filter(|e| matches!(e, drone_ev::DroneEvent::DroneActivated(_)))
+            .count()
+    };
+    run_until(&mut controller, wait_for(|| activations() >= 2)).await;
+    assert!(drone.commands().is_empty());
+}
+
+#[tokio::test(start_paused = true)]
+async fn lands_in_place_when_the_battery_runs_low() {
+    use crate::hardware::drone_control::simulated::{SimulatedDrone, SimulationConfig};
+    use crate::memory_service::MemoryEventService;
+    use crate::twins::drone_twin::events as drone_ev;
+
+    let service = MemoryEventService::new();
+    queue_mission(
+        &service,
+        vec![goto(0.0), goto(1.0), goto(1.0), goto(1.0), goto(1.0)],
+    )
+    .await;
+
+    // 6% per goto, the battery drops below 15% after the third one
+    let drone = SimulatedDrone::new(SimulationConfig {
+        time_scale: 0.0,
+        battery_drain: 6.0,
+        ..Default::default()
+    });
+    drone.set_battery(30);
+    let mut controller = simulated_controller(&service, &drone).on_abort(AbortAction::Return);
+    run_until(&mut controller, wait_for(|| landed(&drone))).await;
+    assert_eq!(
+        drone.commands(),
+        [
+            "command",
+            "takeoff",
+            "go 100 0 0 100",
+            "go 100 0 0 100",
+            "go 100 0 0 100",
+            "land"
+        ]
+    );
+    let decisions: Vec<_> = drone_events(&service)
+        .into_iter()
+        .filter_map(|e| match e {
+            drone_ev::DroneEvent::BatteryChecked(e) => Some((e.battery, e.decision)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        decisions,
+        [
+            (30, drone_ev::BatteryDecision::LaunchApproved),
+            (12, drone_ev::BatteryDecision::Landing)
+        ]
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn lands_when_the_battery_runs_low_during_a_waypoint() {
+    use crate::hardware::drone_control::simulated::{SimulatedDrone, SimulationConfig};
+    use crate::memory_service::MemoryEventService;
+    use crate::twins::drone_twin::events as drone_ev;
+
+    let service = MemoryEventService::new();
+    queue_mission(&service, vec![goto(0.0), goto(4.0)]).await;
+
+    // the go takes 2s
+    let drone = SimulatedDrone::new(SimulationConfig {
+        time_scale: 0.5,
+        ..Default::default()
+    });
+    let mut controller = simulated_controller(&service, &drone).on_abort(AbortAction::Return);
+    run_until(&mut controller, async {
+        wait_for(|| drone.commands().last().map(String::as_str) == Some("go 400 0 0 100")).await;
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        drone.set_battery(10);
+        wait_for(|| landed(&drone)).await;
+    })
+    .await;
+    // lands where it stopped, even when aborts fly back
+    assert_eq!(drone.commands()[2..], ["go 400 0 0 100", "stop", "land"]);
+    let (x, _, _, _) = drone.pose();
+    assert!(x > 50.0 && x < 200.0, "{}", x);
+    let decisions: Vec<_> = drone_events(&service)
+        .into_iter()
+        .filter_map(|e| match e {
+            drone_ev::DroneEvent::BatteryChecked(e) => Some((e.battery, e.decision)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        decisions.last(),
+        Some(&(10, drone_ev::BatteryDecision::Landing))
+    );
+}
+
+#[cfg(test)]
+async fn define_geofence(
+    service: &crate::memory_service::MemoryEventService,
+    id: &str,
+    kind: crate::twins::geofence_twin::events::GeofenceKind,
+    polygon: Vec<[f64; 2]>,
+) {
+    use crate::twins::geofence_twin::events::*;
+    GeofenceEvent::emit_define_geofence(
+        service.clone(),
+        DefineGeofenceEvent {
+            id: id.to_string(),
+            name: id.to_string(),
+            kind,
+            polygon,
+            min_height: 0,
+            max_height: 300,
+        },
+    )
+    .await
+    .unwrap();
+}
+
+/// goto to a map point, at the equator a map unit is a meter
+#[cfg(test)]
+fn planned(map_x: f64, map_y: f64, distance: f32) -> crate::twins::mission_twin::types::Waypoint {
+    use crate::twins::mission_twin::types::*;
+    Waypoint::Goto(GoToWaypoint {
+        map_x,
+        map_y,
+        height: 100,
+        angle: None,
+        distance,
+        duration: 0.0,
+    })
+}
+
+#[tokio::test(start_paused = true)]
+async fn rejects_missions_that_cross_a_geofence() {
+    use crate::memory_service::MemoryEventService;
+    use crate::twins::{geofence_twin::events as geofence_ev, launchpad_twin::events as ev};
+
+    let service = MemoryEventService::new();
+    define_geofence(
+        &service,
+        "shed",
+        geofence_ev::GeofenceKind::NoFlyZone,
+        vec![[1002.0, 0.0], [1004.0, 0.0], [1004.0, 2.0], [1002.0, 2.0]],
+    )
+    .await;
+    queue_mission(
+        &service,
+        vec![planned(1000.0, 1.0, 0.0), planned(1006.0, 1.0, 6.0)],
+    )
+    .await;
+
+    let mut controller = Controller::new("pad-1".to_string(), service.clone());
+    run_until(
+        &mut controller,
+        wait_for(|| {
+            launchpad_events(&service)
+                .iter()
+                .any(|e| matches!(e, ev::LaunchPadEvent::MissionDequeued(_)))
+        }),
+    )
+    .await;
+    let violation = service
+        .events()
+        .into_iter()
+        .find_map(
+            |e| match e.payload.extract::<geofence_ev::GeofenceEvent>() {
+                Ok(geofence_ev::GeofenceEvent::GeofenceViolated(v)) => Some(v),
+                _ => None,
+            },
+        )
+        .unwrap();
+    assert_eq!(
+        (
+            violation.id.as_str(),
+            violation.drone_id,
+            violation.waypoint_id
+        ),
+        ("shed", None, 1)
+    );
+    assert!(!launchpad_events(&service)
+        .iter()
+        .any(|e| matches!(e, ev::LaunchPadEvent::MissionActivated(_))));
+}
+
+#[tokio::test(start_paused = true)]
+async fn refuses_to_launch_a_version_that_crosses_a_geofence() {
+    use crate::hardware::drone_control::simulated::{SimulatedDrone, SimulationConfig};
+    use crate::memory_service::MemoryEventService;
+    use crate::twins::{
+        drone_twin::events as drone_ev, geofence_twin::events as geofence_ev,
+        launchpad_twin::events as ev, mission_twin::events as mission_ev,
+    };
+
+    let service = MemoryEventService::new();
+    define_geofence(
+        &service,
+        "shed",
+        geofence_ev::GeofenceKind::NoFlyZone,
+        vec![[1002.0, 0.0], [1004.0, 0.0], [1004.0, 2.0], [1002.0, 2.0]],
+    )
+    .await;
+    queue_mission(
+        &service,
+        vec![planned(1000.0, 3.0, 0.0), planned(1006.0, 3.0, 6.0)],
+    )
+    .await;
+
+    let drone = SimulatedDrone::new(SimulationConfig {
+        time_scale: 0.0,
+        ..Default::default()
+    });
+    let mut controller = simulated_controller(&service, &drone);
+    // the mission is moved through the shed after its activation
+    run_until(&mut controller, async {
+        wait_for(|| {
+            launchpad_events(&service)
+                .iter()
+                .any(|e| matches!(e, ev::LaunchPadEvent::MissionActivated(_)))
+        })
+        .await;
+        mission_ev::MissionEvent::emit_define_mission(
+            service.clone(),
+            mission_ev::DefineMissionEvent {
+                id: "m1".to_string(),
+                name: "square".to_string(),
+                waypoints: vec![planned(1000.0, 1.0, 0.0), planned(1006.0, 1.0, 6.0)],
+                version: Some(2),
+            },
+        )
+        .await
+        .unwrap();
+        wait_for(|| mission_outcome(&service).is_some()).await;
+    })
+    .await;
+    assert_eq!(
+        mission_outcome(&service),
+        Some(drone_ev::MissionOutcome::Refused)
+    );
+    assert!(!drone.commands().contains(&"takeoff".to_string()));
+    let violation = service
+        .events()
+        .into_iter()
+        .find_map(
+            |e| match e.payload.extract::<geofence_ev::GeofenceEvent>() {
+                Ok(geofence_ev::GeofenceEvent::GeofenceViolated(v)) => Some(v),
+                _ => None,
+            },
+        )
+        .unwrap();
+    assert_eq!(
+        (violation.id.as_str(), violation.drone_id.as_deref()),
+        ("shed", Some("d1"))
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn returns_when_the_drone_leaves_the_fence() {
+    use crate::hardware::drone_control::simulated::{SimulatedDrone, SimulationConfig};
+    use crate::memory_service::MemoryEventService;
+    use crate::twins::{
+        drone_twin::events as drone_ev, geofence_twin::events as geofence_ev,
+        mission_twin::types::*,
+    };
+
+    let service = MemoryEventService::new();
+    define_geofence(
+        &service,
+        "yard",
+        geofence_ev::GeofenceKind::Fence,
+        vec![[999.0, 0.0], [1010.0, 0.0], [1010.0, 3.0], [999.0, 3.0]],
+    )
+    .await;
+    // curves aren't planned on the map, this one ends 1m beyond the fence
+    queue_mission(
+        &service,
+        vec![
+            planned(1000.0, 1.0, 0.0),
+            planned(1003.0, 1.0, 3.0),
+            Waypoint::Curve(CurveWaypoint {
+                x1: 100,
+                y1: 100,
+                z1: 0,
+                x2: 200,
+                y2: 300,
+                z2: 0,
+                speed: 50,
+            }),
+            planned(1008.0, 1.0, 5.0),
+        ],
+    )
+    .await;
+
+    let drone = SimulatedDrone::new(SimulationConfig {
+        time_scale: 0.0,
+        ..Default::default()
+    });
+    let mut controller = simulated_controller(&service, &drone);
+    run_until(
+        &mut controller,
+        wait_for(|| mission_outcome(&service).is_some()),
+    )
+    .await;
+    assert_eq!(
+        mission_outcome(&service),
+        Some(drone_ev::MissionOutcome::Aborted)
+    );
+    let violation = service
+        .events()
+        .into_iter()
+        .find_map(
+            |e| match e.payload.extract::<geofence_ev::GeofenceEvent>() {
+                Ok(geofence_ev::GeofenceEvent::GeofenceViolated(v)) => Some(v),
+                _ => None,
+            },
+        )
+        .unwrap();
+    assert_eq!(
+        (
+            violation.id.as_str(),
+            violation.drone_id.as_deref(),
+            violation.waypoint_id
+        ),
+        ("yard", Some("d1"), 2)
+    );
+    // back at the launchpad without flying to the last goto
+    assert!(!drone.is_flying());
+    let (x, y, ..) = drone.pose();
+    assert!(x.hypot(y) < 5.0, "{} {}", x, y);
+}
+
+#[tokio::test(start_paused = true)]
+async fn stops_the_go_when_the_drone_drifts_out_of_the_fence() {
+    use crate::hardware::drone_control::simulated::{SimulatedDrone, SimulationConfig};
+    use crate::memory_service::MemoryEventService;
+    use crate::twins::{drone_twin::events as drone_ev, geofence_twin::events as geofence_ev};
+
+    let service = MemoryEventService::new();
+    define_geofence(
+        &service,
+        "yard",
+        geofence_ev::GeofenceKind::Fence,
+        vec![[999.0, 0.0], [1010.0, 0.0], [1010.0, 3.0], [999.0, 3.0]],
+    )
+    .await;
+    // the planned route stays 1m within the fence
+    queue_mission(
+        &service,
+        vec![planned(1000.0, 1.0, 0.0), planned(1008.0, 1.0, 8.0)],
+    )
+    .await;
+
+    // the wind pushes the drone north by 1m/s, it leaves the fence 2s into the first go
+    let drone = SimulatedDrone::new(SimulationConfig {
+        time_scale: 0.2,
+        drift: (0.0, 100.0),
+        ..Default::default()
+    });
+    let mut controller = simulated_controller(&service, &drone).on_abort(AbortAction::Land);
+    run_until(
+        &mut controller,
+        wait_for(|| mission_outcome(&service).is_some()),
+    )
+    .await;
+    assert_eq!(
+        mission_outcome(&service),
+        Some(drone_ev::MissionOutcome::Aborted)
+    );
+    assert_eq!(drone.commands()[2..], ["go 300 0 0 100", "stop", "land"]);
+    let violation = service
+        .events()
+        .into_iter()
+        .find_map(
+            |e| match e.payload.extract::<geofence_ev::GeofenceEvent>() {
+                Ok(geofence_ev::GeofenceEvent::GeofenceViolated(v)) => Some(v),
+                _ => None,
+            },
+        )
+        .unwrap();
+    assert_eq!(
+        (
+            violation.id.as_str(),
+            violation.drone_id.as_deref(),
+            violation.waypoint_id
+        ),
+        ("yard", Some("d1"), 0)
+    );
+    // stopped within a check of the fence, 2m north of the start point
+    let (x, y, ..) = drone.pose();
+    assert!(x < 260.0 && (200.0..260.0).contains(&y), "{} {}", x, y);
+}
@@ -0,0 +1,153 @@
+use crate::battery::BatteryPolicy;
+use crate::twins::{
+    drone_twin::{events::BatteryDecision, states::DroneTwinState},
+    launchpad_twin::LaunchpadTwinState,
+    mission_twin::MissionTwinState,
+};
+
+/// the drones of `fleet` that none of the `launchpads` has mounted
+pub fn unmounted(
+    fleet: &[DroneTwinState],
+    launchpads: &[LaunchpadTwinState],
+) -> Vec<DroneTwinState> {
+    fleet
+        .iter()
+        .filter(|drone| {
+            !launchpads
+                .iter()
+                .any(|l| l.attached_drone.as_deref() == Some(drone.id().as_str()))
+        })
+        .cloned()
+        .collect()
+}
+
+/// Whether `launchpad` keeps its drone when other launchpads mounted it as well, because they
+/// picked it at the same time. The earliest mount wins, ties go to the lower launchpad id.
+pub fn holds_drone(launchpad: &LaunchpadTwinState, others: &[LaunchpadTwinState]) -> bool {
+    let claim = |l: &LaunchpadTwinState| (l.mounted_at, l.id.clone());
+    others
+        .iter()
+        .filter(|other| other.id != launchpad.id && other.attached_drone.is_some())
+        .filter(|other| other.attached_drone == launchpad.attached_drone)
+        .all(|other| claim(launchpad) < claim(other))
+}
+
+/// A drone of the fleet for `mission`. It is on the ground, ready or used, and its battery lasts
+/// for the mission. The one with the most battery is the best, ties go to the lower id.
+pub fn select_drone<'a>(
+    fleet: &'a [DroneTwinState],
+    mission: &MissionTwinState,
+    battery: &BatteryPolicy,
+) -> Option<&'a DroneTwinState> {
+    // waypoint 0 is the start point
+    let waypoints = mission.waypoints.get(1..).unwrap_or_default();
+    let required = battery.required(waypoints);
+    fleet
+        .iter()
+        .filter(|drone| matches!(drone, DroneTwinState::Ready(_) | DroneTwinState::Used(_)))
+        .filter_map(|drone| drone.battery().map(|level| (drone, level)))
+        .filter(|(_, level)| {
+            battery.check_launch(*level, required) == BatteryDecision::LaunchApproved
+        })
+        .min_by(|(a, a_level), (b, b_level)| b_level.cmp(a_level).then_with(|| a.id().cmp(&b.id())))
+        .map(|(drone, _)| drone)
+}
+
+#[test]
+fn selects_the_available_drone_with_the_most_battery() {
+    use crate::twins::drone_twin::states::*;
+    let ready = |id: &str, battery| {
+        DroneTwinState::Ready(ReadyState {
+            id: id.to_string(),
+            ip: "192.168.10.1".to_string(),
+            ssid: format!("TELLO-{}", id),
+            battery,
+        })
+    };
+    let used = |id: &str, battery| {
+        DroneTwinState::Used(UsedState {
+            id: id.to_string(),
+            ip: "192.168.10.1".to_string(),
+            ssid: format!("TELLO-{}", id),
+            last_mission_id: "m0".to_string(),
+            battery,
+        })
+    };
+    let charging = |id: &str, battery| {
+        DroneTwinState::Charging(ChargingState {
+            id: id.to_string(),
+            ip: "192.168.10.1".to_string(),
+            ssid: format!("TELLO-{}", id),
+            battery,
+        })
+    };
+    let mission = MissionTwinState {
+        id: "m1".to_string(),
+        ..Default::default()
+    };
+    let policy = BatteryPolicy::default();
+    let selected =
+        |fleet: &[DroneTwinState]| select_drone(fleet, &mission, &policy).map(|drone| drone.id());
+
+    assert_eq!(
+        selected(&[ready("d1", 50), used("d2", 80), charging("d3", 100)]),
+        Some("d2".to_string())
+    );
+    assert_eq!(
+        selected(&[ready("d2", 50), ready("d1", 50)]),
+        Some("d1".to_string())
+    );
+    // below the launch minimum
+    assert_eq!(selected(&[ready("d1", 29), charging("d2", 100)]), None);
+    assert_eq!(selected(&[]), None);
+}
+
+#[test]
+fn drones_mounted_on_other_launchpads_are_not_available() {
+    use crate::twins::drone_twin::states::*;
+    let ready = |id: &str| {
+        DroneTwinState::Ready(ReadyState {
+            id: id.to_string(),
+            ip: "192.168.10.1".to_string(),
+            ssid: format!("TELLO-{}", id),
+            battery: 100,
+        })
+    };
+    let launchpad = |id: &str, drone: Option<&str>| LaunchpadTwinState {
+        id: id.to_string(),
+        attached_drone: drone.map(String::from),
+        ..Default::default()
+    };
+
+    let fleet = [ready("d1"), ready("d2"), ready("d3")];
+    let available = unmounted(
+        &fleet,
+        &[launchpad("pad-2", Some("d2")), launchpad("pad-3", None)],
+    );
+    assert_eq!(available, [ready("d1"), ready("d3")]);
+}
+
+#[test]
+fn the_first_mount_of_a_drone_wins() {
+    let launchpad = |id: &str, drone: &str, mounted_at| LaunchpadTwinState {
+        id: id.to_string(),
+        attached_drone: Some(drone.to_string()),
+        mounted_at: Some(mounted_at),
+        ..Default::default()
+    };
+    let (pad_1, pad_2, pad_3) = (
+        launchpad("pad-1", "d1", 20),
+        launchpad("pad-2", "d1", 10),
+        launchpad("pad-3", "d2", 0),
+    );
+    let all = [pad_1.clone(), pad_2.clone(), pad_3.clone()];
+    assert!(!holds_drone(&pad_1, &all));
+    assert!(holds_drone(&pad_2, &all));
+    assert!(holds_drone(&pad_3, &all));
+
+    // mounted in the same ms
+    let pad_1 = launchpad("pad-1", "d1", 10);
+    let all = [pad_1.clone(), pad_2.clone()];
+    assert!(holds_drone(&pad_1, &all));
+    assert!(!holds_drone(&pad_2, &all));
+}
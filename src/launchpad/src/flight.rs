@@ -0,0 +1,108 @@
+use crate::{
+    battery::BatteryPolicy,
+    geofence::{self, Violation},
+    hardware::drone_control::Tracker,
+    twins::{
+        drone_twin::events::DroneEvent,
+        geofence_twin::GeofenceTwinState,
+        mission_twin::{types::Waypoint, MissionTwinState},
+    },
+};
+use actyx_sdk::service::{EventService, SubscribeRequest, SubscribeResponse};
+use futures::{future::pending, StreamExt};
+use std::time::Duration;
+use tokio::{select, time::interval};
+
+/// how often the drone is checked while it executes a waypoint
+const CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// why a flying drone stops its mission
+#[derive(Clone, Debug, PartialEq)]
+pub enum Alert {
+    /// the operator aborted the mission
+    Aborted,
+    /// battery in percent
+    BatteryLow(u8),
+    Violation(Violation),
+}
+
+/// The mission a drone flies and what it is checked against.
+pub struct Flight<'a> {
+    pub drone_id: &'a str,
+    pub mission: &'a MissionTwinState,
+    /// the last waypoint the drone reached
+    pub at_waypoint: usize,
+    pub geofences: &'a [GeofenceTwinState],
+    pub policy: &'a BatteryPolicy,
+    /// in percent, until the drone reports its own
+    pub battery: u8,
+}
+
+impl Flight<'_> {
+    /// the first reason to stop the mission, the position is unknown without `tracker`
+    pub fn check(&self, tracker: Option<&Tracker>) -> Option<Alert> {
+        let battery = tracker.and_then(Tracker::battery).unwrap_or(self.battery);
+        if self.policy.must_land(battery) {
+            return Some(Alert::BatteryLow(battery));
+        }
+        let (point, height) = tracker?.map_position(&self.mission.waypoints)?;
+        geofence::check_position(point, height, self.at_waypoint, self.geofences)
+            .map(Alert::Violation)
+    }
+
+    /// Checks the drone while it executes the next waypoint and returns the first alert, the
+    /// caller stops the command in flight. Aborts come from an own subscription, like the
+    /// emergency stops.
+    pub async fn watch(&self, service: impl EventService, tracker: Option<Tracker>) -> Alert {
+        let aborted = async {
+            if let Err(e) = aborted(service, self.drone_id, &self.mission.id).await {
+                println!("failed to watch for aborts {:?}", e);
+                pending::<()>().await;
+            }
+        };
+        // the drone is below every fence while it lands on purpose
+        let landing = matches!(
+            self.mission.waypoints.get(self.at_waypoint + 1),
+            Some(Waypoint::Land(_))
+        );
+        let tracker = tracker.filter(|_| !landing);
+        let checks = async {
+            let mut checks = interval(CHECK_INTERVAL);
+            loop {
+                checks.tick().await;
+                if let Some(alert) = self.check(tracker.as_ref()) {
+                    break alert;
+                }
+            }
+        };
+        select! {
+            _ = aborted => Alert::Aborted,
+            alert = checks => alert,
+        }
+    }
+}
+
+/// resolves with the abort of the mission published from now on
+async fn aborted(
+    service: impl EventService,
+    drone_id: &str,
+    mission_id: &str,
+) -> Result<(), anyhow::Error> {
+    let present = service.offsets().await?.present;
+    let mut events = service
+        .subscribe(SubscribeRequest {
+            lower_bound: Some(present),
+            query: format!("FROM 'drone:{}'", drone_id).parse()?,
+        })
+        .await?;
+    while let Some(response) = events.next().await {
+        if let SubscribeResponse::Event(e) = response {
+            if let Ok(DroneEvent::MissionAborted(e)) = e.payload.extract::<DroneEvent>() {
+                if e.mission_id == mission_id {
+                    return Ok(());
+                }
+            }
+        }
+    }
+    Err(anyhow::Error::msg("abort subscription ended"))
+}
@@ -0,0 +1,218 @@
+use crate::hardware::drone_control::pose::{map_point, MapFrame};
+use crate::twins::{
+    geofence_twin::{events::GeofenceKind, GeofenceTwinState},
+    mission_twin::types::{GoToWaypoint, Waypoint},
+};
+
+/// a geofence that a mission or a drone violates
+#[derive(Clone, Debug, PartialEq)]
+pub struct Violation {
+    pub geofence_id: String,
+    /// the planned waypoint, or the last one the drone reached
+    pub waypoint_id: usize,
+    pub reason: String,
+}
+
+/// The first geofence the planned route crosses. The route connects the map points of the gotos,
+/// waypoint 0 is the start point on the ground. Missions without map coordinates can't be checked
+/// and violate every geofence.
+pub fn check_route(waypoints: &[Waypoint], geofences: &[GeofenceTwinState]) -> Option<Violation> {
+    if MapFrame::new(waypoints).is_none() {
+        return active(geofences).next().map(|geofence| Violation {
+            geofence_id: geofence.id.clone(),
+            waypoint_id: 0,
+            reason: "has no map coordinates for".to_string(),
+        });
+    }
+    let route: Vec<(usize, [f64; 2], i16)> = waypoints
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, wp)| {
+            let (x, y) = map_point(wp)?;
+            let height = match wp {
+                Waypoint::Goto(GoToWaypoint { height, .. }) if idx > 0 => *height,
+                _ => 0,
+            };
+            Some((idx, [x, y], height))
+        })
+        .collect();
+    active(geofences).find_map(|geofence| {
+        let polygon = &geofence.polygon;
+        let heights = geofence.min_height..=geofence.max_height;
+        route.iter().enumerate().find_map(|(i, (idx, to, height))| {
+            let from = i.checked_sub(1).map(|i| route[i]);
+            let reason = match geofence.kind {
+                GeofenceKind::Fence => {
+                    if !contains(polygon, *to) {
+                        Some("leaves the fence".to_string())
+                    } else if *idx > 0 && !heights.contains(height) {
+                        Some(format!(
+                            "flies at {}cm, not within {}-{}cm",
+                            height, geofence.min_height, geofence.max_height
+                        ))
+                    } else if from.is_some_and(|(_, from, _)| crosses(polygon, from, *to)) {
+                        Some("crosses the fence".to_string())
+                    } else {
+                        None
+                    }
+                }
+                GeofenceKind::NoFlyZone => {
+                    // the leg climbs or descends from the height of the previous waypoint
+                    let (low, high) = from.map_or((*height, *height), |(_, _, from)| {
+                        (from.min(*height), from.max(*height))
+                    });
+                    let enters = contains(polygon, *to)
+                        || from.is_some_and(|(_, from, _)| {
+                            contains(polygon, from) || crosses(polygon, from, *to)
+                        });
+                    (enters && low <= geofence.max_height && high >= geofence.min_height)
+                        .then(|| "enters the no-fly zone".to_string())
+                }
+            };
+            reason.map(|reason| Violation {
+                geofence_id: geofence.id.clone(),
+                waypoint_id: *idx,
+                reason,
+            })
+        })
+    })
+}
+
+/// the first geofence the drone at `point` on the map and `height` cm violates
+pub fn check_position(
+    point: [f64; 2],
+    height: i16,
+    waypoint_id: usize,
+    geofences: &[GeofenceTwinState],
+) -> Option<Violation> {
+    active(geofences).find_map(|geofence| {
+        let inside = contains(&geofence.polygon, point);
+        let within = (geofence.min_height..=geofence.max_height).contains(&height);
+        let reason = match geofence.kind {
+            GeofenceKind::Fence if !inside => "left the fence",
+            GeofenceKind::Fence if !within => "left the heights of the fence",
+            GeofenceKind::NoFlyZone if inside && within => "entered the no-fly zone",
+            _ => return None,
+        };
+        Some(Violation {
+            geofence_id: geofence.id.clone(),
+            waypoint_id,
+            reason: format!("{} at {}cm", reason, height),
+        })
+    })
+}
+
+/// geofences with an area
+fn active(geofences: &[GeofenceTwinState]) -> impl Iterator<Item = &GeofenceTwinState> {
+    geofences.iter().filter(|g| g.polygon.len() >= 3)
+}
+
+fn edges(polygon: &[[f64; 2]]) -> impl Iterator<Item = ([f64; 2], [f64; 2])> + '_ {
+    polygon
+        .iter()
+        .copied()
+        .zip(polygon.iter().copied().cycle().skip(1))
+}
+
+/// even-odd rule, the polygon may be concave
+fn contains(polygon: &[[f64; 2]], [x, y]: [f64; 2]) -> bool {
+    edges(polygon)
+        .filter(|(a, b)| {
+            (a[1] > y) != (b[1] > y) && x < a[0] + (y - a[1]) * (b[0] - a[0]) / (b[1] - a[1])
+        })
+        .count()
+        % 2
+        == 1
+}
+
+/// the leg from `a` to `b` crosses an edge of the polygon
+fn crosses(polygon: &[[f64; 2]], a: [f64; 2], b: [f64; 2]) -> bool {
+    // > 0 if `r` is left of `p` to `q`
+    let side = |p: [f64; 2], q: [f64; 2], r: [f64; 2]| {
+        (q[0] - p[0]) * (r[1] - p[1]) - (q[1] - p[1]) * (r[0] - p[0])
+    };
+    edges(polygon)
+        .any(|(c, d)| side(c, d, a) * side(c, d, b) < 0.0 && side(a, b, c) * side(a, b, d) < 0.0)
+}
+
+#[test]
+fn rejects_routes_through_walls_and_no_fly_zones() {
+    let goto = |map_x, map_y, height| {
+        Waypoint::Goto(GoToWaypoint {
+            map_x,
+            map_y,
+            height,
+            angle: None,
+            distance: 0.0,
+            duration: 0.0,
+        })
+    };
+    let geofence = |id: &str, kind, polygon: &[[f64; 2]], max_height| GeofenceTwinState {
+        id: id.to_string(),
+        kind,
+        polygon: polygon.to_vec(),
+        min_height: 0,
+        max_height,
+        ..Default::default()
+    };
+    // a U shaped yard, the wall between its arms goes up to 10m
+    let yard = geofence(
+        "yard",
+        GeofenceKind::Fence,
+        &[
+            [0.0, 0.0],
+            [30.0, 0.0],
+            [30.0, 20.0],
+            [20.0, 20.0],
+            [20.0, 10.0],
+            [10.0, 10.0],
+            [10.0, 20.0],
+            [0.0, 20.0],
+        ],
+        300,
+    );
+    let shed = geofence(
+        "shed",
+        GeofenceKind::NoFlyZone,
+        &[[2.0, 2.0], [8.0, 2.0], [8.0, 8.0], [2.0, 8.0]],
+        250,
+    );
+    let geofences = [yard, shed];
+    let violation = |waypoints: &[Waypoint]| {
+        check_route(waypoints, &geofences).map(|v| (v.geofence_id, v.waypoint_id))
+    };
+
+    let around_the_shed = [
+        goto(9.0, 1.0, 0),
+        goto(25.0, 1.0, 100),
+        goto(25.0, 18.0, 100),
+    ];
+    assert_eq!(violation(&around_the_shed), None);
+    // both ends are in the yard
+    let over_the_wall = [
+        goto(25.0, 1.0, 0),
+        goto(25.0, 18.0, 100),
+        goto(5.0, 18.0, 100),
+    ];
+    assert_eq!(violation(&over_the_wall), Some(("yard".to_string(), 2)));
+    let too_high = [goto(9.0, 1.0, 0), goto(25.0, 1.0, 400)];
+    assert_eq!(violation(&too_high), Some(("yard".to_string(), 1)));
+    let through_the_shed = [goto(1.0, 5.0, 0), goto(9.0, 5.0, 100)];
+    assert_eq!(violation(&through_the_shed), Some(("shed".to_string(), 1)));
+    let above_the_shed = [goto(1.0, 5.0, 0), goto(1.0, 1.0, 280), goto(9.0, 5.0, 280)];
+    assert_eq!(violation(&above_the_shed), None);
+    // unplanned missions can't be checked
+    assert_eq!(
+        violation(&[goto(0.0, 0.0, 0), goto(0.0, 0.0, 900)]),
+        Some(("yard".to_string(), 0))
+    );
+    assert_eq!(check_route(&[goto(0.0, 0.0, 0)], &[]), None);
+
+    assert_eq!(check_position([25.0, 5.0], 100, 3, &geofences), None);
+    let drifted = check_position([15.0, 15.0], 100, 3, &geofences).unwrap();
+    assert_eq!(
+        (drifted.geofence_id.as_str(), drifted.waypoint_id),
+        ("yard", 3)
+    );
+    assert!(check_position([5.0, 5.0], 100, 3, &geofences).is_some());
+}
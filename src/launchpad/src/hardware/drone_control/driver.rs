@@ -0,0 +1,629 @@
+use crate::twins::{
+    drone_twin::events::StopCommand,
+    mission_twin::types::{CameraAction, FlipDirection},
+};
+use async_trait::async_trait;
+use std::{
+    net::UdpSocket,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::TryRecvError,
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+use tello::{command_mode::CommandModeState, odometry::Odometry};
+use tokio::{
+    task::JoinHandle,
+    time::{timeout, Instant},
+};
+
+/// the Tello streams its state to this port unless it is told another one with `port`
+pub const STATE_PORT: u16 = 8890;
+/// the launchpad doesn't receive the video, the port is only announced with `port`
+const VIDEO_PORT: u16 = 11111;
+/// like the tello crate, a `go` over 500cm at 20cm/s takes 25s
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
+/// the answer of a stopped command follows the `stop` right away
+const LATE_ANSWER: Duration = Duration::from_secs(1);
+
+/// Low level drone commands used by `DroneControl`.
+///
+/// The methods follow the Tello SDK. Errors are the raw answers of the drone (e.g. `timeout`
+/// or `error Not joystick`).
+#[async_trait(?Send)]
+pub trait DroneDriver {
+    fn is_connected(&self) -> bool;
+    async fn connect(&mut self, ip: &str) -> Result<(), String>;
+    async fn take_off(&mut self) -> Result<(), String>;
+    async fn land(&mut self) -> Result<(), String>;
+    async fn go_to(&mut self, x: i32, y: i32, z: i32, speed: u8) -> Result<(), String>;
+    async fn cw(&mut self, deg: u32) -> Result<(), String>;
+    async fn ccw(&mut self, deg: u32) -> Result<(), String>;
+    /// speed of `go` and `curve`, in cm/s
+    async fn speed(&mut self, _speed: u8) -> Result<(), String> {
+        Err("error speed is not supported".to_string())
+    }
+    #[allow(clippy::too_many_arguments)]
+    async fn curve(
+        &mut self,
+        _x1: i32,
+        _y1: i32,
+        _z1: i32,
+        _x2: i32,
+        _y2: i32,
+        _z2: i32,
+        _speed: u8,
+    ) -> Result<(), String> {
+        Err("error curve is not supported".to_string())
+    }
+    async fn flip(&mut self, _direction: FlipDirection) -> Result<(), String> {
+        Err("error flip is not supported".to_string())
+    }
+    async fn camera(&mut self, _action: CameraAction) -> Result<(), String> {
+        Err("error camera is not supported".to_string())
+    }
+    /// `go` relative to a mission pad
+    async fn go_to_pad(
+        &mut self,
+        _x: i32,
+        _y: i32,
+        _z: i32,
+        _speed: u8,
+        _pad: u8,
+    ) -> Result<(), String> {
+        Err("error mission pads are not supported".to_string())
+    }
+    fn odometry(&self) -> Odometry;
+    /// latest state the drone reported since the last call
+    fn try_recv_state(&mut self) -> Result<CommandModeState, TryRecvError>;
+    /// second way to the drone next to the command path, `None` until connected
+    fn stop_line(&self) -> Option<Arc<dyn StopLine>>;
+    /// the odometry while a command is in flight, `None` until connected
+    fn telemetry_line(&self) -> Option<Arc<dyn TelemetryLine>>;
+}
+
+/// Sends stop commands without waiting for the command in flight.
+///
+/// Can be used from any thread, while the command path waits for the answer of the command in
+/// flight.
+pub trait StopLine: Send + Sync {
+    fn send(&self, command: StopCommand) -> Result<(), String>;
+    /// ends the command in flight, the drone hovers where it is
+    fn hover(&self) -> Result<(), String>;
+}
+
+/// Reads the latest state the drone streamed, from any task while the command path waits for
+/// the answer of the command in flight.
+pub trait TelemetryLine: Send + Sync {
+    fn odometry(&self) -> Odometry;
+    /// in percent, `None` until the drone reported it
+    fn battery(&self) -> Option<u8>;
+}
+
+/// Own socket to the Tello, the answer is ignored
+struct UdpStopLine {
+    socket: UdpSocket,
+    drone: String,
+}
+
+impl StopLine for UdpStopLine {
+    fn send(&self, command: StopCommand) -> Result<(), String> {
+        let command: &[u8] = match command {
+            StopCommand::Emergency => b"emergency",
+            StopCommand::Land => b"land",
+        };
+        self.socket
+            .send_to(command, &self.drone)
+            .map(|_| ())
+            .map_err(|e| format!("failed to send stop command {:?}", e))
+    }
+
+    fn hover(&self) -> Result<(), String> {
+        self.socket
+            .send_to(b"stop", &self.drone)
+            .map(|_| ())
+            .map_err(|e| format!("failed to send stop command {:?}", e))
+    }
+}
+
+/// Local UDP ports of a `TelloDriver`, 0 picks a free one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TelloPorts {
+    /// the commands are sent from and answered to this port
+    pub command: u16,
+    /// Other ports than `STATE_PORT` are announced to the drone with `port`, which needs the SDK
+    /// 2.0 of a Tello EDU. A taken port falls back to a free one.
+    pub state: u16,
+}
+
+impl Default for TelloPorts {
+    fn default() -> Self {
+        Self {
+            command: 0,
+            state: STATE_PORT,
+        }
+    }
+}
+
+/// latest state the drone streamed
+#[derive(Default)]
+struct Telemetry {
+    /// not read by `try_recv_state` yet
+    unread: Option<CommandModeState>,
+    /// `x` and `y` are summed up from the speeds since the connect
+    odometry: Odometry,
+    /// arrival of the last state, to sum up the speeds
+    received: Option<Instant>,
+    battery: Option<u8>,
+}
+
+impl TelemetryLine for Mutex<Telemetry> {
+    fn odometry(&self) -> Odometry {
+        self.lock().unwrap().odometry.clone()
+    }
+
+    fn battery(&self) -> Option<u8> {
+        self.lock().unwrap().battery
+    }
+}
+
+struct Connection {
+    socket: tokio::net::UdpSocket,
+    telemetry: Arc<Mutex<Telemetry>>,
+    receiver: JoinHandle<()>,
+    /// `mon` was sent, the drone looks for mission pads
+    detects_pads: bool,
+    /// the answer of the last command wasn't read, it timed out or was stopped
+    unanswered: AtomicBool,
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.receiver.abort();
+    }
+}
+
+/// Real Tello drone, controlled with the text commands of the Tello SDK over UDP.
+///
+/// The tello crate binds the fixed ports 8889 and 8890 and panics if they are taken, so only one
+/// drone per process could be flown with it. The ports of `TelloDriver` are the launchpad's own,
+/// so several launchpads can run in one process. The emulator speaks the same SDK, `connect`
+/// reaches it like a real drone.
+#[derive(Default)]
+pub struct TelloDriver {
+    ports: TelloPorts,
+    connection: Option<Connection>,
+    stop_line: Option<Arc<UdpStopLine>>,
+}
+
+impl TelloDriver {
+    pub fn new(ports: TelloPorts) -> Self {
+        Self {
+            ports,
+            ..Default::default()
+        }
+    }
+
+    /// Opens the sockets and starts to receive the state. The drone learns the ports with the
+    /// first command.
+    async fn open(&self, ip: &str) -> Result<(Connection, u16), String> {
+        let socket = tokio::net::UdpSocket::bind(("0.0.0.0", self.ports.command))
+            .await
+            .map_err(|e| {
+                format!(
+                    "failed to bind command port {}: {:?}",
+                    self.ports.command, e
+                )
+            })?;
+        socket
+            .connect(ip)
+            .await
+            .map_err(|e| format!("failed to connect to {}: {:?}", ip, e))?;
+        let state_socket = match tokio::net::UdpSocket::bind(("0.0.0.0", self.ports.state)).await {
+            Ok(socket) => socket,
+            Err(e) => {
+                println!(
+                    "state port {} is taken, use a free one {:?}",
+                    self.ports.state, e
+                );
+                tokio::net::UdpSocket::bind("0.0.0.0:0")
+                    .await
+                    .map_err(|e| format!("failed to bind a state port {:?}", e))?
+            }
+        };
+        let state_port = state_socket
+            .local_addr()
+            .map_err(|e| format!("failed to bind a state port {:?}", e))?
+            .port();
+        let telemetry = Arc::new(Mutex::new(Telemetry::default()));
+        let receiver = tokio::spawn(receive_state(state_socket, telemetry.clone()));
+        let connection = Connection {
+            socket,
+            telemetry,
+            receiver,
+            detects_pads: false,
+            unanswered: AtomicBool::new(false),
+        };
+        Ok((connection, state_port))
+    }
+
+    /// Sends an SDK command and waits for its answer. The answers have no id, so the answer of a
+    /// command that timed out or was stopped is awaited and dropped before the next command.
+    async fn send(&self, command: &str) -> Result<(), String> {
+        let connection = self
+            .connection
+            .as_ref()
+            .ok_or_else(|| "no drone connected".to_string())?;
+        let socket = &connection.socket;
+        let mut buf = [0u8; 1024];
+        if connection.unanswered.load(Ordering::SeqCst) {
+            let _ = timeout(LATE_ANSWER, socket.recv(&mut buf)).await;
+        }
+        while socket.try_recv(&mut buf).is_ok() {}
+        // stays set if this future is dropped before the answer arrives
+        connection.unanswered.store(true, Ordering::SeqCst);
+        socket
+            .send(command.as_bytes())
+            .await
+            .map_err(|e| format!("failed to send command to drone: {:?}", e))?;
+        let len = timeout(COMMAND_TIMEOUT, socket.recv(&mut buf))
+            .await
+            .map_err(|_| "timeout".to_string())?
+            .map_err(|e| format!("socket error {:?}", e))?;
+        connection.unanswered.store(false, Ordering::SeqCst);
+        let answer = String::from_utf8_lossy(&buf[..len]).trim().to_string();
+        if answer.starts_with("ok") {
+            Ok(())
+        } else if answer.starts_with("error") {
+            Err(answer)
+        } else {
+            Err(format!("unknown answer {:?}", answer))
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl DroneDriver for TelloDriver {
+    fn is_connected(&self) -> bool {
+        self.connection.is_some()
+    }
+
+    async fn connect(&mut self, ip: &str) -> Result<(), String> {
+        let mut announce = None;
+        if self.connection.is_none() {
+            let (connection, state_port) = self.open(ip).await?;
+            self.connection = Some(connection);
+            announce = Some(state_port).filter(|port| *port != STATE_PORT);
+            let socket = UdpSocket::bind("0.0.0.0:0")
+                .map_err(|e| format!("failed to open stop line {:?}", e))?;
+            self.stop_line = Some(Arc::new(UdpStopLine {
+                socket,
+                drone: ip.to_string(),
+            }));
+        }
+        self.send("command").await?;
+        if let Some(state_port) = announce {
+            let command = format!("port {} {}", state_port, VIDEO_PORT);
+            if let Err(e) = self.send(&command).await {
+                println!(
+                    "the drone doesn't stream its state to port {} {}",
+                    state_port, e
+                );
+            }
+        }
+        Ok(())
+    }
+
+    async fn take_off(&mut self) -> Result<(), String> {
+        self.send("takeoff").await
+    }
+
+    async fn land(&mut self) -> Result<(), String> {
+        self.send("land").await
+    }
+
+    async fn go_to(&mut self, x: i32, y: i32, z: i32, speed: u8) -> Result<(), String> {
+        self.send(&format!("go {} {} {} {}", x, y, z, speed)).await
+    }
+
+    async fn cw(&mut self, deg: u32) -> Result<(), String> {
+        self.send(&format!("cw {}", deg)).await
+    }
+
+    async fn ccw(&mut self, deg: u32) -> Result<(), String> {
+        self.send(&format!("ccw {}", deg)).await
+    }
+
+    async fn speed(&mut self, speed: u8) -> Result<(), String> {
+        self.send(&format!("speed {}", speed)).await
+    }
+
+    async fn curve(
+        &mut self,
+        x1: i32,
+        y1: i32,
+        z1: i32,
+        x2: i32,
+        y2: i32,
+        z2: i32,
+        speed: u8,
+    ) -> Result<(), String> {
+        let command = format!("curve {} {} {} {} {} {} {}", x1, y1, z1, x2, y2, z2, speed);
+        self.send(&command).await
+    }
+
+    async fn flip(&mut self, direction: FlipDirection) -> Result<(), String> {
+        let direction = match direction {
+            FlipDirection::Left => "l",
+            FlipDirection::Right => "r",
+            FlipDirection::Forward => "f",
+            FlipDirection::Back => "b",
+        };
+        self.send(&format!("flip {}", direction)).await
+    }
+
+    async fn camera(&mut self, action: CameraAction) -> Result<(), String> {
+        match action {
+            CameraAction::StartVideo => self.send("streamon").await,
+            CameraAction::StopVideo => self.send("streamoff").await,
+        }
+    }
+
+    /// the pad detection is switched on with the first move to a pad
+    async fn go_to_pad(
+        &mut self,
+        x: i32,
+        y: i32,
+        z: i32,
+        speed: u8,
+        pad: u8,
+    ) -> Result<(), String> {
+        let detects_pads = self.connection.as_ref().map_or(false, |c| c.detects_pads);
+        if !detects_pads {
+            self.send("mon").await?;
+            if let Some(connection) = self.connection.as_mut() {
+                connection.detects_pads = true;
+            }
+        }
+        let command = format!("go {} {} {} {} m{}", x, y, z, speed, pad);
+        self.send(&command).await
+    }
+
+    fn odometry(&self) -> Odometry {
+        self.connection
+            .as_ref()
+            .map(|c| c.telemetry.odometry())
+            .unwrap_or_default()
+    }
+
+    fn try_recv_state(&mut self) -> Result<CommandModeState, TryRecvError> {
+        let connection = self.connection.as_ref().ok_or(TryRecvError::Disconnected)?;
+        let last = connection.telemetry.lock().unwrap().unread.take();
+        last.ok_or(TryRecvError::Empty)
+    }
+
+    fn stop_line(&self) -> Option<Arc<dyn StopLine>> {
+        self.stop_line.clone().map(|line| line as Arc<dyn StopLine>)
+    }
+
+    fn telemetry_line(&self) -> Option<Arc<dyn TelemetryLine>> {
+        let telemetry = self.connection.as_ref()?.telemetry.clone();
+        Some(telemetry as Arc<dyn TelemetryLine>)
+    }
+}
+
+/// Keeps the latest state the drone streams to `socket`. The speeds `vgx` and `vgy` are in dm/s
+/// along `x` (ahead) and `y` (left) at yaw 0, like the axes of `go`.
+async fn receive_state(socket: tokio::net::UdpSocket, telemetry: Arc<Mutex<Telemetry>>) {
+    let mut buf = [0u8; 1024];
+    loop {
+        match socket.recv(&mut buf).await {
+            Ok(len) => {
+                let state = parse_state(&String::from_utf8_lossy(&buf[..len]));
+                let mut telemetry = telemetry.lock().unwrap();
+                let now = Instant::now();
+                // after a gap in the stream the drone may have moved any way
+                let elapsed = telemetry
+                    .received
+                    .replace(now)
+                    .map(|last| (now - last).as_secs_f64())
+                    .filter(|secs| *secs < 1.0)
+                    .unwrap_or_default();
+                telemetry.odometry.x += state.vgx as f64 * 10.0 * elapsed;
+                telemetry.odometry.y += state.vgy as f64 * 10.0 * elapsed;
+                telemetry.odometry.z = state.h as f64;
+                telemetry.battery = Some(state.bat.max(0) as u8);
+                telemetry.odometry.rot = -(state.yaw as f64).to_radians();
+                telemetry.unread = Some(state);
+            }
+            Err(e) => {
+                println!("failed to receive the drone state {:?}", e);
+                return;
+            }
+        }
+    }
+}
+
+/// Reads `pitch:0;roll:0;yaw:-45;...`, unknown and malformed values are skipped.
+pub fn parse_state(state: &str) -> CommandModeState {
+    fn set<T: FromStr>(field: &mut T, value: &str) {
+        if let Ok(value) = value.trim().parse() {
+            *field = value;
+        }
+    }
+    let mut s = CommandModeState::default();
+    for (key, value) in state.split(';').filter_map(|field| field.split_once(':')) {
+        match key.trim() {
+            "pitch" => set(&mut s.pitch, value),
+            "roll" => set(&mut s.roll, value),
+            "yaw" => set(&mut s.yaw, value),
+            "vgx" => set(&mut s.vgx, value),
+            "vgy" => set(&mut s.vgy, value),
+            "vgz" => set(&mut s.vgz, value),
+            "templ" => set(&mut s.templ, value),
+            "temph" => set(&mut s.temph, value),
+            "tof" => set(&mut s.tof, value),
+            "h" => set(&mut s.h, value),
+            "bat" => set(&mut s.bat, value),
+            "baro" => set(&mut s.baro, value),
+            "time" => set(&mut s.time, value),
+            "agx" => set(&mut s.agx, value),
+            "agy" => set(&mut s.agy, value),
+            "agz" => set(&mut s.agz, value),
+            _ => (),
+        }
+    }
+    s
+}
+
+#[test]
+fn reads_the_state_of_a_tello_edu() {
+    let state = parse_state(
+        "mid:-1;x:0;y:0;z:0;mpry:0,0,0;pitch:1;roll:-2;yaw:-45;vgx:3;vgy:0;vgz:0;templ:69;\
+         temph:70;tof:10;h:80;bat:92;baro:548.55;time:12;agx:-5.00;agy:0.00;agz:-998.00;\r\n",
+    );
+    assert_eq!((state.pitch, state.roll, state.yaw), (1, -2, -45));
+    assert_eq!(
+        (state.vgx, state.h, state.bat, state.time),
+        (3, 80, 92, 12.0)
+    );
+    assert_eq!(parse_state("bat:x;h:20").h, 20);
+}
+
+#[tokio::test]
+async fn launchpads_in_one_process_drive_their_own_tello() {
+    use super::{emulator::TelloEmulator, simulated::*};
+
+    let drones: Vec<SimulatedDrone> = (0..2)
+        .map(|_| {
+            SimulatedDrone::new(SimulationConfig {
+                time_scale: 0.0,
+                ..Default::default()
+            })
+        })
+        .collect();
+    let mut drivers = vec![];
+    for drone in &drones {
+        let addr = TelloEmulator::spawn("127.0.0.1:0", drone.clone()).unwrap();
+        let mut driver = TelloDriver::new(TelloPorts {
+            command: 0,
+            state: 0,
+        });
+        driver.connect(&addr.to_string()).await.unwrap();
+        drivers.push(driver);
+    }
+    drivers[0].take_off().await.unwrap();
+    drivers[1].take_off().await.unwrap();
+    drivers[1].cw(90).await.unwrap();
+    drivers[0].land().await.unwrap();
+
+    assert!(!drones[0].is_flying());
+    assert!(drones[1].is_flying());
+    assert_eq!(drones[1].pose().3, 90.0);
+}
+
+#[tokio::test]
+async fn sends_flips_curves_and_mission_pads_to_the_tello() {
+    use super::{emulator::TelloEmulator, simulated::*};
+
+    let drone = SimulatedDrone::new(SimulationConfig {
+        time_scale: 0.0,
+        mission_pads: vec![(1, 300.0, 100.0)],
+        ..Default::default()
+    });
+    let addr = TelloEmulator::spawn("127.0.0.1:0", drone.clone()).unwrap();
+    let mut driver = TelloDriver::new(TelloPorts {
+        command: 0,
+        state: 0,
+    });
+    driver.connect(&addr.to_string()).await.unwrap();
+    driver.take_off().await.unwrap();
+    driver.curve(100, -100, 0, 200, -50, 0, 30).await.unwrap();
+    driver.flip(FlipDirection::Left).await.unwrap();
+    driver.go_to_pad(0, -50, 80, 50, 1).await.unwrap();
+    driver.go_to_pad(50, 0, 80, 50, 1).await.unwrap();
+
+    assert_eq!(
+        drone.commands()[2..],
+        [
+            "curve 100 -100 0 200 -50 0 30",
+            "flip l",
+            "go 0 -50 80 50 m1",
+            "go 50 0 80 50 m1"
+        ]
+    );
+    let (x, y, z, _) = drone.pose();
+    assert_eq!((x.round(), y.round(), z.round()), (350.0, 100.0, 80.0));
+}
+
+#[tokio::test]
+async fn sums_up_the_odometry_from_the_speeds() {
+    use super::{emulator::TelloEmulator, simulated::*};
+
+    // 2m ahead in 1s, then 1m to the left
+    let drone = SimulatedDrone::new(SimulationConfig {
+        time_scale: 0.5,
+        ..Default::default()
+    });
+    let addr = TelloEmulator::spawn("127.0.0.1:0", drone.clone()).unwrap();
+    let mut driver = TelloDriver::new(TelloPorts {
+        command: 0,
+        state: 0,
+    });
+    driver.connect(&addr.to_string()).await.unwrap();
+    driver.take_off().await.unwrap();
+    driver.go_to(200, 0, 0, 100).await.unwrap();
+    driver.ccw(90).await.unwrap();
+    driver.go_to(100, 0, 0, 100).await.unwrap();
+    tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+
+    let odometry = driver.odometry();
+    assert!(
+        (odometry.x - 200.0).abs() < 30.0 && (odometry.y - 100.0).abs() < 30.0,
+        "{:?}",
+        odometry
+    );
+    assert_eq!(odometry.rot.to_degrees().round(), 90.0);
+    assert_eq!(odometry.z, 100.0);
+}
+
+#[tokio::test]
+async fn the_late_answer_of_a_stopped_command_is_not_taken_for_the_next_one() {
+    let drone = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let mut driver = TelloDriver::new(TelloPorts {
+        command: 0,
+        state: 0,
+    });
+    let tello = async {
+        let mut buf = [0u8; 64];
+        loop {
+            let (len, peer) = drone.recv_from(&mut buf).await.unwrap();
+            let command = String::from_utf8_lossy(&buf[..len]).to_string();
+            // the go is answered once it was stopped, after the driver gave up on it
+            if command.starts_with("go") {
+                tokio::time::sleep(Duration::from_millis(200)).await;
+            }
+            let answer = match command.as_str() {
+                "cw 90" => "error Not joystick",
+                _ => "ok",
+            };
+            drone.send_to(answer.as_bytes(), peer).await.unwrap();
+        }
+    };
+    let client = async {
+        driver
+            .connect(&drone.local_addr().unwrap().to_string())
+            .await
+            .unwrap();
+        let go = timeout(Duration::from_millis(50), driver.go_to(100, 0, 0, 100)).await;
+        assert!(go.is_err());
+        driver.cw(90).await
+    };
+    let cw = tokio::select! {
+        _ = tello => unreachable!(),
+        cw = client => cw,
+    };
+    assert_eq!(cw, Err("error Not joystick".to_string()));
+}
@@ -0,0 +1,129 @@
+use super::driver::StopLine;
+use crate::twins::drone_twin::events::{DroneEvent, StopCommand};
+use actyx_sdk::service::{EventService, SubscribeRequest, SubscribeResponse};
+use futures::StreamExt;
+use std::sync::{Arc, Mutex};
+use tokio::{select, sync::oneshot};
+
+/// connected drone and the line to stop it
+type Attached = Option<(String, Arc<dyn StopLine>)>;
+
+/// Stops the drone of a `DroneControl`, from any thread and while a command is in flight.
+#[derive(Clone, Default)]
+pub struct EmergencyStop {
+    line: Arc<Mutex<Attached>>,
+}
+
+impl EmergencyStop {
+    pub(super) fn attach(&self, drone_id: String, line: Arc<dyn StopLine>) {
+        *self.line.lock().unwrap() = Some((drone_id, line));
+    }
+
+    /// sends `command` if `drone_id` is the connected drone
+    pub fn trigger(&self, drone_id: &str, command: StopCommand) -> Result<(), String> {
+        match &*self.line.lock().unwrap() {
+            Some((id, line)) if id == drone_id => {
+                println!("emergency stop {} with {:?}", id, command);
+                line.send(command)
+            }
+            _ => Err(format!("drone {} is not connected", drone_id)),
+        }
+    }
+}
+
+/// Executes the `EmergencyStop` events published from now on.
+///
+/// The events come from an own subscription, without the debounce of the twins and the controller.
+pub async fn watch(service: impl EventService, stop: EmergencyStop) -> Result<(), anyhow::Error> {
+    let present = service.offsets().await?.present;
+    let mut events = service
+        .subscribe(SubscribeRequest {
+            lower_bound: Some(present),
+            query: "FROM 'drone.emergency'".parse()?,
+        })
+        .await?;
+    while let Some(response) = events.next().await {
+        if let SubscribeResponse::Event(e) = response {
+            if let Ok(DroneEvent::EmergencyStop(e)) = e.payload.extract::<DroneEvent>() {
+                // other launchpads stop their own drones
+                let _ = stop.trigger(&e.id, e.command);
+            }
+        }
+    }
+    Err(anyhow::Error::msg("emergency subscription ended"))
+}
+
+/// Runs `watch` on its own thread and runtime, so a stop doesn't wait for a busy controller. The
+/// watch ends when the returned sender is dropped.
+pub fn spawn_watch<S>(service: S, stop: EmergencyStop) -> oneshot::Sender<()>
+where
+    S: EventService + Send + Sync + 'static,
+{
+    let (tx, closed) = oneshot::channel::<()>();
+    std::thread::spawn(move || {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("emergency stop: failed to create runtime");
+        runtime.block_on(async move {
+            select! {
+                res = watch(service, stop) => println!("emergency stop watch stopped {:?}", res),
+                _ = closed => (),
+            }
+        })
+    });
+    tx
+}
+
+#[tokio::test]
+async fn emergency_stop_interrupts_the_command_in_flight() {
+    use super::{simulated::SimulatedDrone, DroneControl};
+    use crate::memory_service::MemoryEventService;
+    use crate::twins::drone_twin::events::EmergencyStopEvent;
+    use crate::twins::mission_twin::types::{GoToWaypoint, Waypoint};
+    use std::time::{Duration, Instant};
+
+    let service = MemoryEventService::new();
+    let drone = SimulatedDrone::default();
+    let mut control = DroneControl::with_driver(drone.clone());
+    let _watch = spawn_watch(service.clone(), control.emergency_stop());
+    control
+        .connect("d1", "127.0.0.1:8889".into())
+        .await
+        .unwrap();
+    control.take_off().await.unwrap();
+
+    // 4m with 1m/s, the emergency stop reaches the drone after 300ms
+    let wp = [Waypoint::Goto(GoToWaypoint {
+        map_x: 0.0,
+        map_y: 0.0,
+        height: 100,
+        angle: None,
+        distance: 4.0,
+        duration: 0.0,
+    })];
+    let started = Instant::now();
+    let (res, _) = tokio::join!(
+        control.exec_waypoint(service.clone(), "d1".into(), "m1".into(), &wp, 0),
+        async {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            let stop = |id: &str, command| EmergencyStopEvent {
+                id: id.into(),
+                command,
+            };
+            DroneEvent::emit_emergency_stop(service.clone(), stop("d2", StopCommand::Land))
+                .await
+                .unwrap();
+            DroneEvent::emit_emergency_stop(service.clone(), stop("d1", StopCommand::Emergency))
+                .await
+                .unwrap();
+        }
+    );
+    assert!(res.is_err());
+    assert!(started.elapsed() < Duration::from_secs(2));
+    assert!(!drone.is_flying());
+    assert_eq!(
+        drone.commands(),
+        ["command", "takeoff", "go 400 0 0 100", "emergency"]
+    );
+}
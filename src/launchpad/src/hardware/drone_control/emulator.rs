@@ -0,0 +1,410 @@
+use super::{
+    driver::{DroneDriver, StopLine, STATE_PORT},
+    simulated::SimulatedDrone,
+};
+use crate::twins::{
+    drone_twin::events::StopCommand,
+    mission_twin::types::{CameraAction, FlipDirection},
+};
+use futures::future::LocalBoxFuture;
+use futures::FutureExt;
+use std::{net::SocketAddr, time::Duration};
+use tello::command_mode::CommandModeState;
+use tokio::{net::UdpSocket, select, time::interval};
+
+/// Speaks the Tello SDK (text commands over UDP) in front of a `SimulatedDrone`.
+///
+/// Commands are answered on the socket they came in, with `ok`, `error ...` or the value for
+/// read commands. While a command is executed, only `emergency`, `land` and `stop` are
+/// accepted. The state is streamed to the last client, to the port it asked for with `port` or
+/// 8890.
+pub struct TelloEmulator {
+    socket: UdpSocket,
+    drone: SimulatedDrone,
+    client: Option<SocketAddr>,
+    state_port: u16,
+    /// `go` to a mission pad needs `mon` first
+    detects_pads: bool,
+}
+
+impl TelloEmulator {
+    /// the state goes to `STATE_PORT` until the client asks for another port
+    pub async fn bind(addr: &str, drone: SimulatedDrone) -> Result<Self, anyhow::Error> {
+        let socket = UdpSocket::bind(addr).await?;
+        println!("tello emulator listening on {}", socket.local_addr()?);
+        Ok(Self {
+            socket,
+            drone,
+            client: None,
+            state_port: STATE_PORT,
+            detects_pads: false,
+        })
+    }
+
+    pub fn state_port(mut self, port: u16) -> Self {
+        self.state_port = port;
+        self
+    }
+
+    #[cfg(test)]
+    /// Runs the emulator on its own thread and runtime, so it keeps answering while the blocking
+    /// tello crate waits for a response in the same process.
+    pub fn spawn(addr: &str, drone: SimulatedDrone) -> Result<SocketAddr, anyhow::Error> {
+        let addr = addr.to_string();
+        let (tx, rx) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .expect("tello emulator: failed to create runtime");
+            runtime.block_on(async move {
+                match TelloEmulator::bind(&addr, drone).await {
+                    Ok(emulator) => {
+                        let _ = tx.send(emulator.local_addr().map_err(anyhow::Error::from));
+                        if let Err(e) = emulator.run().await {
+                            println!("tello emulator stopped {:?}", e);
+                        }
+                    }
+                    Err(e) => {
+                        let _ = tx.send(Err(e));
+                    }
+                }
+            })
+        });
+        rx.recv()?
+    }
+
+    #[cfg(test)]
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    pub async fn run(mut self) -> Result<(), anyhow::Error> {
+        let state_socket = UdpSocket::bind("0.0.0.0:0").await?;
+        let mut state_tick = interval(Duration::from_millis(100));
+        let mut buf = [0u8; 1024];
+        let mut in_flight: Option<(LocalBoxFuture<'static, Option<String>>, SocketAddr)> = None;
+        loop {
+            select! {
+                res = self.socket.recv_from(&mut buf) => {
+                    let (len, peer) = res?;
+                    let command = String::from_utf8_lossy(&buf[..len]).trim().to_string();
+                    self.client = Some(peer);
+                    if let Some(answer) = self.configure(&command) {
+                        self.socket.send_to(answer.as_bytes(), peer).await?;
+                    } else if in_flight.is_some() {
+                        let answer = self.interrupt(&command);
+                        self.socket.send_to(answer.as_bytes(), peer).await?;
+                        // the stopped command ends before the next one arrives
+                        if command == "stop" {
+                            if let Some((command, peer)) = in_flight.take() {
+                                if let Some(answer) = command.await {
+                                    self.socket.send_to(answer.as_bytes(), peer).await?;
+                                }
+                            }
+                        }
+                    } else {
+                        let drone = self.drone.clone();
+                        let command = async move { execute(drone, &command).await }.boxed_local();
+                        in_flight = Some((command, peer));
+                    }
+                }
+                answer = async { in_flight.as_mut().unwrap().0.as_mut().await }, if in_flight.is_some() => {
+                    let (_, peer) = in_flight.take().unwrap();
+                    if let Some(answer) = answer {
+                        self.socket.send_to(answer.as_bytes(), peer).await?;
+                    }
+                }
+                _ = state_tick.tick() => {
+                    if let (Some(client), Ok(state)) = (self.client, self.drone.try_recv_state()) {
+                        let target = SocketAddr::new(client.ip(), self.state_port);
+                        let _ = state_socket.send_to(format_state(&state).as_bytes(), target).await;
+                    }
+                }
+            }
+        }
+    }
+
+    /// `port <state> <video>` changes the ports of the client, the video isn't streamed.
+    /// `mon` / `moff` switch the mission pad detection.
+    fn configure(&mut self, command: &str) -> Option<String> {
+        let args: Vec<&str> = command.split_whitespace().collect();
+        match args.as_slice() {
+            ["mon"] | ["moff"] => {
+                self.detects_pads = args[0] == "mon";
+                Some("ok".to_string())
+            }
+            ["go", .., pad] if pad.starts_with('m') && !self.detects_pads => {
+                Some("error mission pad detection is off".to_string())
+            }
+            ["port", state, video] => match (state.parse(), video.parse::<u16>()) {
+                (Ok(state), Ok(_)) => {
+                    self.state_port = state;
+                    Some("ok".to_string())
+                }
+                _ => Some("error".to_string()),
+            },
+            _ => None,
+        }
+    }
+
+    /// command received while another one is executed
+    fn interrupt(&self, command: &str) -> String {
+        let res = match command {
+            "emergency" => self.drone.send(StopCommand::Emergency),
+            "land" => self.drone.send(StopCommand::Land),
+            "stop" => self.drone.hover(),
+            _ => Err("error Not joystick".to_string()),
+        };
+        answer(res).unwrap_or_default()
+    }
+
+    #[cfg(test)]
+    /// Executes a single SDK command. `None` means the drone doesn't answer at all.
+    pub async fn handle(&mut self, command: &str) -> Option<String> {
+        execute(self.drone.clone(), command).await
+    }
+}
+
+async fn execute(mut drone: SimulatedDrone, command: &str) -> Option<String> {
+    let args: Vec<&str> = command.split_whitespace().collect();
+    let res = match args.as_slice() {
+        ["command"] => drone.connect("").await,
+        ["takeoff"] => drone.take_off().await,
+        ["land"] => drone.land().await,
+        ["emergency"] => drone.send(StopCommand::Emergency),
+        ["stop"] => drone.hover(),
+        ["go", x, y, z, speed] => match (x.parse(), y.parse(), z.parse(), speed.parse()) {
+            (Ok(x), Ok(y), Ok(z), Ok(speed)) => drone.go_to(x, y, z, speed).await,
+            _ => Err("error".to_string()),
+        },
+        ["go", x, y, z, speed, pad] => {
+            let pad = pad.strip_prefix('m').map(str::parse);
+            match (x.parse(), y.parse(), z.parse(), speed.parse(), pad) {
+                (Ok(x), Ok(y), Ok(z), Ok(speed), Some(Ok(pad))) => {
+                    drone.go_to_pad(x, y, z, speed, pad).await
+                }
+                _ => Err("error".to_string()),
+            }
+        }
+        ["curve", x1, y1, z1, x2, y2, z2, speed] => {
+            let points: Result<Vec<i32>, _> =
+                [x1, y1, z1, x2, y2, z2].iter().map(|v| v.parse()).collect();
+            match (points.as_deref(), speed.parse()) {
+                (Ok(&[x1, y1, z1, x2, y2, z2]), Ok(speed)) => {
+                    drone.curve(x1, y1, z1, x2, y2, z2, speed).await
+                }
+                _ => Err("error".to_string()),
+            }
+        }
+        ["speed", speed] => match speed.parse() {
+            Ok(speed) => drone.speed(speed).await,
+            _ => Err("error".to_string()),
+        },
+        ["flip", direction] => match *direction {
+            "l" => drone.flip(FlipDirection::Left).await,
+            "r" => drone.flip(FlipDirection::Right).await,
+            "f" => drone.flip(FlipDirection::Forward).await,
+            "b" => drone.flip(FlipDirection::Back).await,
+            _ => Err("error".to_string()),
+        },
+        ["streamon"] => drone.camera(CameraAction::StartVideo).await,
+        ["streamoff"] => drone.camera(CameraAction::StopVideo).await,
+        ["cw", deg] => match deg.parse() {
+            Ok(deg) => drone.cw(deg).await,
+            _ => Err("error".to_string()),
+        },
+        ["ccw", deg] => match deg.parse() {
+            Ok(deg) => drone.ccw(deg).await,
+            _ => Err("error".to_string()),
+        },
+        ["battery?"] => return Some(drone.battery().to_string()),
+        _ => Err("error".to_string()),
+    };
+    answer(res)
+}
+
+fn answer(res: Result<(), String>) -> Option<String> {
+    match res {
+        Ok(()) => Some("ok".to_string()),
+        Err(e) if e == "timeout" => None,
+        Err(e) if e.starts_with("error") => Some(e),
+        Err(e) => Some(format!("error {}", e)),
+    }
+}
+
+/// State string like the Tello sends it. The tello crate reads 150 bytes, so it is kept short.
+pub fn format_state(s: &CommandModeState) -> String {
+    format!(
+        "pitch:{};roll:{};yaw:{};vgx:{};vgy:{};vgz:{};templ:{};temph:{};tof:{};h:{};bat:{};baro:{:.2};time:{};agx:{:.2};agy:{:.2};agz:{:.2};\r\n",
+        s.pitch, s.roll, s.yaw, s.vgx, s.vgy, s.vgz, s.templ, s.temph, s.tof, s.h, s.bat, s.baro,
+        s.time.round(), s.agx, s.agy, s.agz
+    )
+}
+
+#[tokio::test]
+async fn emulator_answers_sdk_commands() {
+    use super::simulated::{SimulatedFailure, SimulationConfig};
+
+    let drone = SimulatedDrone::new(SimulationConfig {
+        time_scale: 0.0,
+        ..Default::default()
+    });
+    let mut emulator = TelloEmulator::bind("127.0.0.1:0", drone.clone())
+        .await
+        .unwrap();
+
+    assert_eq!(
+        emulator.handle("takeoff").await.unwrap(),
+        "error no drone connected"
+    );
+    assert_eq!(emulator.handle("command").await.unwrap(), "ok");
+    assert_eq!(emulator.handle("takeoff").await.unwrap(), "ok");
+    assert_eq!(emulator.handle("go 100 0 20 50").await.unwrap(), "ok");
+    assert_eq!(
+        emulator.handle("go 1000 0 0 50").await.unwrap(),
+        "error out of range"
+    );
+    assert_eq!(emulator.handle("cw 90").await.unwrap(), "ok");
+    assert_eq!(emulator.handle("ccw 45").await.unwrap(), "ok");
+    assert_eq!(emulator.handle("flip x").await.unwrap(), "error");
+    drone.set_battery(42);
+    assert_eq!(emulator.handle("battery?").await.unwrap(), "42");
+    drone.inject_failure("land", SimulatedFailure::Timeout);
+    assert_eq!(emulator.handle("land").await, None);
+    assert_eq!(emulator.handle("land").await.unwrap(), "ok");
+    assert_eq!(emulator.handle("emergency").await.unwrap(), "ok");
+
+    let (x, _, z, heading) = drone.pose();
+    assert_eq!((x, z, heading), (100.0, 0.0, 45.0));
+
+    let state = format_state(&drone.clone().try_recv_state().unwrap());
+    assert!(state.len() <= 150, "state too long: {}", state);
+    assert!(state.starts_with("pitch:0;roll:0;yaw:45;"));
+}
+
+#[tokio::test]
+async fn emulator_accepts_stop_commands_while_busy() {
+    use super::simulated::SimulationConfig;
+
+    let drone = SimulatedDrone::new(SimulationConfig {
+        time_scale: 0.1,
+        ..Default::default()
+    });
+    let emulator = TelloEmulator::bind("127.0.0.1:0", drone.clone())
+        .await
+        .unwrap();
+    let addr = emulator.local_addr().unwrap();
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    socket.connect(addr).await.unwrap();
+    let client = async {
+        let mut buf = [0u8; 64];
+        let mut answers = vec![];
+        for command in [
+            "command",
+            "port 0 11111",
+            "takeoff",
+            "go 500 0 0 10",
+            "cw 90",
+            "emergency",
+        ] {
+            socket.send(command.as_bytes()).await.unwrap();
+            // the go takes 5s, the following commands are sent while it is executed
+            if !command.starts_with("go") {
+                let len = socket.recv(&mut buf).await.unwrap();
+                answers.push(String::from_utf8_lossy(&buf[..len]).to_string());
+            }
+        }
+        let len = socket.recv(&mut buf).await.unwrap();
+        answers.push(String::from_utf8_lossy(&buf[..len]).to_string());
+        answers
+    };
+    let answers = select! {
+        res = emulator.run() => panic!("emulator stopped {:?}", res),
+        answers = tokio::time::timeout(Duration::from_secs(2), client) => {
+            answers.expect("emulator did not answer")
+        }
+    };
+    assert_eq!(
+        answers,
+        [
+            "ok",
+            "ok",
+            "ok",
+            "error Not joystick",
+            "ok",
+            "error Motor stop"
+        ]
+    );
+    assert!(!drone.is_flying());
+}
+
+/// Runs the `TelloDriver` (via `Hardware`) against the emulator, on free ports only.
+#[tokio::test]
+async fn hardware_controls_the_emulated_tello() {
+    use super::{
+        driver::{TelloDriver, TelloPorts},
+        simulated::SimulationConfig,
+        DroneControl,
+    };
+    use crate::hardware::Hardware;
+    use crate::memory_service::MemoryEventService;
+    use crate::twins::mission_twin::{types::*, MissionTwinState};
+
+    let drone = SimulatedDrone::new(SimulationConfig {
+        time_scale: 0.0,
+        ..Default::default()
+    });
+    let addr = TelloEmulator::spawn("127.0.0.1:0", drone.clone()).unwrap();
+
+    let service = MemoryEventService::new();
+    let mut hardware = Hardware::with_drone(
+        "pad-1".to_string(),
+        service.clone(),
+        Default::default(),
+        DroneControl::with_driver(TelloDriver::new(TelloPorts {
+            command: 0,
+            state: 0,
+        })),
+    );
+    hardware
+        .connect_now("d1".into(), "TELLO".into(), addr.to_string())
+        .await
+        .unwrap();
+    assert!(drone.is_connected());
+    hardware
+        .take_off_now(
+            "d1".into(),
+            "TELLO".into(),
+            addr.to_string(),
+            "m1".into(),
+            1,
+        )
+        .await
+        .unwrap();
+
+    let mission = MissionTwinState {
+        id: "m1".to_string(),
+        waypoints: vec![
+            Waypoint::Delay(DelayWaypoint { duration: 0.0 }),
+            Waypoint::Turn(TurnWaypoint {
+                deg: -90,
+                duration: 0.0,
+            }),
+        ],
+        ..Default::default()
+    };
+    hardware
+        .exec_waypoint("d1".to_string(), 0, &mission)
+        .await
+        .unwrap();
+    assert_eq!(drone.pose().3, 270.0);
+
+    // state is streamed every 100ms
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert_eq!(hardware.get_state().map(|s| (s.h, s.yaw)), Ok((100, -90)));
+
+    hardware.land_now("d1".to_string()).await.unwrap();
+    assert_eq!(drone.commands(), ["command", "takeoff", "ccw 90", "land"]);
+    assert_eq!(service.events().len(), 5);
+}
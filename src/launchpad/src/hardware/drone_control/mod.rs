@@ -1,191 +1,478 @@
-use std::{borrow::BorrowMut, sync::mpsc::TryRecvError, time::Duration};
+use std::{
+    cmp::Ordering,
+    sync::{mpsc::TryRecvError, Arc},
+    time::Duration,
+};
 
 use actyx_sdk::service::EventService;
-use tello::{command_mode::CommandModeState, odometry::Odometry, CommandMode, Drone};
+use tello::{command_mode::CommandModeState, odometry::Odometry};
 use tokio::time::sleep;
 
 use crate::twins::{
-    drone_twin::DroneTwin,
-    mission_twin::types::{DelayWaypoint, GoToWaypoint, TurnWaypoint, Waypoint},
+    drone_twin::events::{
+        DroneArrivedAtWaypointEvent, DroneEvent, DroneStartedToNextWaypointEvent,
+    },
+    mission_twin::types::*,
 };
 
+pub mod driver;
+pub mod emergency;
+pub mod emulator;
+pub mod pose;
+pub mod simulated;
+use self::driver::{DroneDriver, TelemetryLine, TelloDriver, TelloPorts};
+use self::emergency::EmergencyStop;
+use self::pose::{bearing, map_point, MapFrame, Pose};
+
 pub struct DroneControl {
-    drone: Option<CommandMode>,
+    drone: Box<dyn DroneDriver>,
+    emergency: EmergencyStop,
+    /// speed of the `go` commands, in cm/s
+    speed: u8,
+    /// tracked from the commands since the take off, lost after a mission pad move
+    pose: Option<Pose>,
+    /// odometry at the take off, the frame of the pose
+    origin: Option<Odometry>,
+}
+
+/// Where the drone is since its take off, readable while `DroneControl` executes a command.
+#[derive(Clone)]
+pub struct Tracker {
+    telemetry: Arc<dyn TelemetryLine>,
+    origin: Odometry,
+}
+
+impl Tracker {
+    pub fn battery(&self) -> Option<u8> {
+        self.telemetry.battery()
+    }
+
+    pub fn pose(&self) -> Pose {
+        Pose::from_odometry(&self.origin, &self.telemetry.odometry())
+    }
+
+    /// position on the map of the mission and height in cm, `None` without a map
+    pub fn map_position(&self, waypoints: &[Waypoint]) -> Option<([f64; 2], i16)> {
+        let frame = MapFrame::new(waypoints)?;
+        let odometry = self.telemetry.odometry();
+        let pose = Pose::from_odometry(&self.origin, &odometry);
+        let (x, y) = frame.map((pose.x, pose.y));
+        Some(([x, y], odometry.z.round() as i16))
+    }
 }
 
 impl DroneControl {
-    pub fn new() -> Self {
-        Self { drone: None }
+    #[allow(dead_code)]
+    pub fn new(ports: TelloPorts) -> Self {
+        Self::with_driver(TelloDriver::new(ports))
+    }
+
+    pub fn with_driver(driver: impl DroneDriver + 'static) -> Self {
+        Self {
+            drone: Box::new(driver),
+            emergency: EmergencyStop::default(),
+            speed: 100,
+            pose: None,
+            origin: None,
+        }
     }
 
     #[allow(dead_code)]
     pub fn try_recv_state(&mut self) -> Result<CommandModeState, TryRecvError> {
-        if let Some(d) = self.drone.borrow_mut() {
-            let mut last: Option<CommandModeState> = None;
-            while let Ok(s) = d.state_receiver.try_recv() {
-                last = Some(s);
-            }
-            println!("new State {:?}", last);
-            last.map(|s| Ok(s)).unwrap_or(Err(TryRecvError::Empty))
-        } else {
-            Err(TryRecvError::Disconnected)
-        }
+        self.drone.try_recv_state()
     }
 
     pub fn is_drone_connected(&self) -> bool {
-        self.drone.as_ref().is_some()
+        self.drone.is_connected()
     }
 
-    pub async fn connect(&mut self, ip: String) -> Result<(), String> {
-        if let None = self.drone.as_ref() {
-            let drone = Drone::new(&*ip).command_mode();
-            self.drone = Some(drone);
+    pub async fn connect(&mut self, drone_id: &str, ip: String) -> Result<(), String> {
+        self.drone.connect(&ip).await?;
+        if let Some(line) = self.drone.stop_line() {
+            self.emergency.attach(drone_id.to_string(), line);
         }
-        self.drone.as_mut().unwrap().enable().await
+        Ok(())
+    }
+
+    /// handle to stop the drone while this `DroneControl` is busy
+    pub fn emergency_stop(&self) -> EmergencyStop {
+        self.emergency.clone()
     }
     pub async fn take_off(&mut self) -> Result<(), String> {
-        if let Some(drone) = self.drone.as_mut() {
-            drone.take_off().await
-        } else {
-            Err("no drone connected".to_string())
+        self.drone.take_off().await?;
+        self.pose = Some(Pose::default());
+        self.origin = Some(self.drone.odometry());
+        Ok(())
+    }
+
+    /// `None` until the drone took off
+    pub fn tracker(&self) -> Option<Tracker> {
+        Some(Tracker {
+            telemetry: self.drone.telemetry_line()?,
+            origin: self.origin.clone()?,
+        })
+    }
+
+    /// Ends the command in flight, e.g. after its future was dropped. The command didn't end
+    /// where it was planned, the tracked pose is taken from the odometry.
+    pub fn hover(&mut self) -> Result<(), String> {
+        let line = self
+            .drone
+            .stop_line()
+            .ok_or_else(|| "no drone connected".to_string())?;
+        line.hover()?;
+        let tracked = self.tracker().map(|tracker| tracker.pose());
+        if let (Some(pose), Some(tracked)) = (&mut self.pose, tracked) {
+            *pose = tracked;
         }
+        Ok(())
     }
+    /// Flies waypoint `waypoint_idx` of the mission. Gotos with map coordinates fly to the
+    /// planned point from the tracked pose, the other ones `distance` ahead.
     pub async fn exec_waypoint(
         &mut self,
         service: impl EventService,
         drone_id: String,
         mission_id: String,
-        wp: &Waypoint,
-        waypoint_idx: i32,
+        waypoints: &[Waypoint],
+        waypoint_idx: usize,
     ) -> Result<(), anyhow::Error> {
+        let wp = waypoints
+            .get(waypoint_idx)
+            .ok_or_else(|| anyhow::Error::msg(format!("no waypoint {}", waypoint_idx)))?;
         println!("execute waypoint: {:?}", wp);
+        // a delay waits without the drone
+        if !matches!(wp, Waypoint::Delay(_)) && !self.drone.is_connected() {
+            return Err(anyhow::Error::msg("no drone connected".to_string()));
+        }
+        DroneEvent::emit_drone_started_to_next_waypoint(
+            service.clone(),
+            DroneStartedToNextWaypointEvent {
+                id: drone_id.clone(),
+                mission_id: mission_id.clone(),
+                waypoint_id: waypoint_idx as i32,
+            },
+        )
+        .await?;
+
+        self.fly_waypoint(waypoints, waypoint_idx).await?;
+
+        DroneEvent::emit_drone_arrived_at_waypoint(
+            service.clone(),
+            DroneArrivedAtWaypointEvent {
+                id: drone_id,
+                mission_id,
+                waypoint_id: waypoint_idx as i32,
+            },
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn fly_waypoint(
+        &mut self,
+        waypoints: &[Waypoint],
+        idx: usize,
+    ) -> Result<(), anyhow::Error> {
+        let wp = &waypoints[idx];
+        let d = &mut self.drone;
         match wp {
             Waypoint::Goto(GoToWaypoint {
-                distance, height, ..
+                distance,
+                height,
+                angle,
+                ..
             }) => {
-                if let Some(d) = self.drone.borrow_mut() {
-                    DroneTwin::emit_drone_started_to_next_waypoint(
-                        service.clone(),
-                        drone_id.clone(),
-                        mission_id.clone(),
-                        waypoint_idx,
-                    )
-                    .await?;
-
-                    let target_height = *height as i32;
-                    let z = target_height - d.odometry.z.round() as i32;
-                    let distance = (distance * 100.0).round();
-                    let mut fife_meters = ((distance / 500.0).floor() as i32).max(0);
-                    let mut rest = (distance % 500.0) as i32;
-                    let mut do_480_step = false;
-
-                    // if the last movement would be smaller than
-                    if rest < 20 && fife_meters > 0 {
-                        fife_meters -= 1;
-                        do_480_step = true;
-                        rest += 20;
-                    }
-
-                    // only the last segment will move in the Z direction to avoid < 20 cm movements
-                    let rest_z = (!do_480_step && fife_meters == 0)
-                        .then(|| z)
-                        .unwrap_or_default();
-                    d.go_to(rest, 0, rest_z, 100)
-                        .await
-                        .map_err(anyhow::Error::msg)?;
-
-                    if do_480_step {
-                        let d_z = (fife_meters == 0).then(|| z).unwrap_or_default();
-                        d.go_to(480, 0, d_z, 100)
-                            .await
-                            .map_err(anyhow::Error::msg)?;
-                    }
-                    for i in 0..fife_meters {
-                        let d_z = (i == fife_meters - 1).then(|| z).unwrap_or_default();
-                        d.go_to(500, 0, d_z, 100)
-                            .await
-                            .map_err(anyhow::Error::msg)?;
-                    }
-
-                    // d.forward(0).await.map_err(anyhow::Error::msg)?;
-                } else {
-                    return Err(anyhow::Error::msg("no drone connected".to_string()));
+                let target_height = *height as i32;
+                let z = target_height - d.odometry().z.round() as i32;
+                let frame = MapFrame::new(waypoints);
+                let target = map_point(wp).zip(frame).map(|(p, f)| f.local(p));
+                let (pose, target) = match self.pose.zip(target) {
+                    Some(course) => course,
+                    None => return self.fly_forward(*distance, z).await,
+                };
+                let (turn, distance) = pose.course_to(target);
+                // the Tello doesn't move less than 20cm, there is no direction to turn to
+                if distance >= 20.0 {
+                    self.turn(turn).await?;
                 }
+                self.fly_forward((distance / 100.0) as f32, z).await?;
 
-                DroneTwin::emit_drone_arrived_at_waypoint(
-                    service.clone(),
-                    drone_id,
-                    mission_id,
-                    waypoint_idx,
-                )
-                .await?;
-                Ok(())
-            }
-            Waypoint::Turn(TurnWaypoint { deg, .. }) => {
-                if let Some(d) = self.drone.borrow_mut() {
-                    DroneTwin::emit_drone_started_to_next_waypoint(
-                        service.clone(),
-                        drone_id.clone(),
-                        mission_id.clone(),
-                        waypoint_idx,
-                    )
-                    .await?;
-
-                    let deg = *deg;
-                    if deg > 0 {
-                        d.cw(deg as u32).await.map_err(anyhow::Error::msg)?
-                    } else {
-                        d.ccw((-deg) as u32).await.map_err(anyhow::Error::msg)?
-                    }
-                } else {
-                    return Err(anyhow::Error::msg("no drone connected".to_string()));
+                // `angle` turns clockwise from the planned leg to the next one
+                let from = waypoints[..idx].iter().rev().find_map(map_point);
+                if let (Some(angle), Some(from), Some(frame), Some(pose)) =
+                    (angle, from, frame, self.pose)
+                {
+                    let leg = bearing(frame.local(from), target);
+                    self.turn(pose::normalize(leg + *angle as f64 - pose.yaw))
+                        .await?;
                 }
-
-                DroneTwin::emit_drone_arrived_at_waypoint(
-                    service.clone(),
-                    drone_id,
-                    mission_id,
-                    waypoint_idx,
-                )
-                .await?;
                 Ok(())
             }
+            Waypoint::Turn(TurnWaypoint { deg, .. }) => self.turn(*deg as f64).await,
             Waypoint::Delay(DelayWaypoint { duration, .. }) => {
-                DroneTwin::emit_drone_started_to_next_waypoint(
-                    service.clone(),
-                    drone_id.clone(),
-                    mission_id.clone(),
-                    waypoint_idx,
-                )
-                .await?;
-
                 sleep(Duration::from_millis(*duration as u64)).await;
-
-                DroneTwin::emit_drone_arrived_at_waypoint(
-                    service.clone(),
-                    drone_id,
-                    mission_id,
-                    waypoint_idx,
+                Ok(())
+            }
+            Waypoint::SetSpeed(SetSpeedWaypoint { speed }) => {
+                d.speed(*speed).await.map_err(anyhow::Error::msg)?;
+                self.speed = *speed;
+                Ok(())
+            }
+            Waypoint::Curve(CurveWaypoint {
+                x1,
+                y1,
+                z1,
+                x2,
+                y2,
+                z2,
+                speed,
+            }) => {
+                d.curve(
+                    *x1 as i32, *y1 as i32, *z1 as i32, *x2 as i32, *y2 as i32, *z2 as i32, *speed,
                 )
-                .await?;
+                .await
+                .map_err(anyhow::Error::msg)?;
+                if let Some(pose) = &mut self.pose {
+                    pose.move_by(*x2 as f64, *y2 as f64);
+                }
                 Ok(())
             }
+            Waypoint::Flip(FlipWaypoint { direction }) => {
+                d.flip(*direction).await.map_err(anyhow::Error::msg)
+            }
+            Waypoint::Camera(CameraWaypoint { action }) => {
+                d.camera(*action).await.map_err(anyhow::Error::msg)
+            }
+            Waypoint::Land(LandWaypoint { duration }) => {
+                d.land().await.map_err(anyhow::Error::msg)?;
+                sleep(Duration::from_millis(*duration as u64)).await;
+                d.take_off().await.map_err(anyhow::Error::msg)
+            }
+            Waypoint::MissionPad(MissionPadWaypoint {
+                pad,
+                x,
+                y,
+                z,
+                speed,
+            }) => {
+                // the pad isn't on the map
+                self.pose = None;
+                d.go_to_pad(*x as i32, *y as i32, *z as i32, *speed, *pad)
+                    .await
+                    .map_err(anyhow::Error::msg)
+            }
+        }
+    }
+
+    /// turns by `deg` rounded to whole degrees, clockwise if it is positive
+    async fn turn(&mut self, deg: f64) -> Result<(), anyhow::Error> {
+        let deg = deg.round() as i32;
+        let turned = match deg.cmp(&0) {
+            Ordering::Greater => self.drone.cw(deg as u32).await,
+            Ordering::Less => self.drone.ccw((-deg) as u32).await,
+            Ordering::Equal => return Ok(()),
+        };
+        turned.map_err(anyhow::Error::msg)?;
+        if let Some(pose) = &mut self.pose {
+            pose.turn(deg as f64);
+        }
+        Ok(())
+    }
+
+    /// Flies `distance` m ahead and changes the height by `z` cm on the way. The Tello moves at
+    /// most 500 cm per command and at least 20 cm, shorter moves are skipped.
+    async fn fly_forward(&mut self, distance: f32, z: i32) -> Result<(), anyhow::Error> {
+        let d = &mut self.drone;
+        let speed = self.speed;
+        let distance = (distance * 100.0).round();
+        if distance < 20.0 && z.abs() < 20 {
+            return Ok(());
+        }
+        let mut fife_meters = ((distance / 500.0).floor() as i32).max(0);
+        let mut rest = (distance % 500.0) as i32;
+        let mut do_480_step = false;
+
+        // if the last movement would be smaller than
+        if rest < 20 && fife_meters > 0 {
+            fife_meters -= 1;
+            do_480_step = true;
+            rest += 20;
         }
+
+        // only the last segment will move in the Z direction to avoid < 20 cm movements
+        let rest_z = if !do_480_step && fife_meters == 0 {
+            z
+        } else {
+            Default::default()
+        };
+        d.go_to(rest, 0, rest_z, speed)
+            .await
+            .map_err(anyhow::Error::msg)?;
+
+        if do_480_step {
+            let d_z = if fife_meters == 0 {
+                z
+            } else {
+                Default::default()
+            };
+            d.go_to(480, 0, d_z, speed)
+                .await
+                .map_err(anyhow::Error::msg)?;
+        }
+        for i in 0..fife_meters {
+            let d_z = if i == fife_meters - 1 {
+                z
+            } else {
+                Default::default()
+            };
+            d.go_to(500, 0, d_z, speed)
+                .await
+                .map_err(anyhow::Error::msg)?;
+        }
+        if let Some(pose) = &mut self.pose {
+            pose.move_by(distance as f64, 0.0);
+        }
+        Ok(())
+    }
+
+    /// Flies straight back to the start point of the mission from the tracked pose and turns to
+    /// the heading at take off. Fails if the pose was lost, e.g. after a mission pad waypoint.
+    pub async fn return_home(&mut self) -> Result<(), anyhow::Error> {
+        if !self.drone.is_connected() {
+            return Err(anyhow::Error::msg("no drone connected".to_string()));
+        }
+        let pose = self.pose.ok_or_else(|| {
+            anyhow::Error::msg("can't return without the position since the take off".to_string())
+        })?;
+        let (turn, distance) = pose.course_to((0.0, 0.0));
+        println!("return home {}cm", distance.round());
+        // the Tello doesn't move less than 20cm
+        if distance >= 20.0 {
+            self.turn(turn).await?;
+            self.fly_forward((distance / 100.0) as f32, 0).await?;
+        }
+        let yaw = self.pose.map_or(0.0, |pose| pose.yaw);
+        self.turn(-yaw).await
     }
 
     pub async fn land(&mut self) -> Result<(), String> {
-        if let Some(drone) = self.drone.as_ref() {
-            drone.land().await?;
-            Ok(())
+        if self.drone.is_connected() {
+            self.drone.land().await
         } else {
             Err("can't land !?".to_string())
         }
     }
 
     pub fn pos(&self) -> Odometry {
-        self.drone
-            .as_ref()
-            .map(|d| d.odometry.clone())
-            .unwrap_or_default()
+        self.drone.odometry()
+    }
+}
+
+#[tokio::test]
+async fn flies_to_the_planned_map_points() {
+    use crate::memory_service::MemoryEventService;
+    use simulated::{SimulatedDrone, SimulationConfig};
+
+    let drone = SimulatedDrone::new(SimulationConfig {
+        time_scale: 0.0,
+        ..Default::default()
+    });
+    let mut control = DroneControl::with_driver(drone.clone());
+    control
+        .connect("d1", "127.0.0.1:8889".into())
+        .await
+        .unwrap();
+    control.take_off().await.unwrap();
+
+    // at the equator a map unit is a meter, the route flies 2m east, 2m north and 2m west
+    let goto = |map_x, map_y, angle| {
+        Waypoint::Goto(GoToWaypoint {
+            map_x,
+            map_y,
+            height: 100,
+            angle,
+            distance: 2.0,
+            duration: 0.0,
+        })
+    };
+    let turn = |deg| Waypoint::Turn(TurnWaypoint { deg, duration: 0.0 });
+    let waypoints = [
+        goto(1000.0, 0.1, None),
+        // turns to the north by `angle`
+        goto(1002.0, 0.1, Some(-90.0)),
+        goto(1002.0, 2.1, Some(0.0)),
+        // off the plan
+        turn(45),
+        Waypoint::Curve(CurveWaypoint {
+            x1: 50,
+            y1: 50,
+            z1: 0,
+            x2: 100,
+            y2: 0,
+            z2: 0,
+            speed: 50,
+        }),
+        goto(1000.0, 2.1, Some(0.0)),
+    ];
+    let service = MemoryEventService::new();
+    for idx in 1..waypoints.len() {
+        control
+            .exec_waypoint(service.clone(), "d1".into(), "m1".into(), &waypoints, idx)
+            .await
+            .unwrap();
+    }
+    assert_eq!(
+        drone.commands()[2..6],
+        ["go 200 0 0 100", "ccw 90", "go 200 0 0 100", "cw 45"]
+    );
+    // the Tello turns by whole degrees, that is up to 2cm on 2m
+    let (x, y, z, heading) = drone.pose();
+    assert!(x.hypot(y - 200.0) < 4.0, "{} {}", x, y);
+    assert_eq!(z.round(), 100.0);
+    // back on the planned leg to the west
+    assert_eq!(heading.round(), 180.0);
+}
+
+#[tokio::test]
+async fn skips_moves_the_tello_cant_make() {
+    use crate::memory_service::MemoryEventService;
+    use simulated::{SimulatedDrone, SimulationConfig};
+
+    let drone = SimulatedDrone::new(SimulationConfig {
+        time_scale: 0.0,
+        ..Default::default()
+    });
+    let mut control = DroneControl::with_driver(drone.clone());
+    control
+        .connect("d1", "127.0.0.1:8889".into())
+        .await
+        .unwrap();
+    control.take_off().await.unwrap();
+
+    let goto = |map_x, height| {
+        Waypoint::Goto(GoToWaypoint {
+            map_x,
+            map_y: 0.1,
+            height,
+            angle: None,
+            distance: 0.0,
+            duration: 0.0,
+        })
+    };
+    let waypoints = [
+        goto(1000.0, 100),
+        goto(1000.0, 100),
+        goto(1000.1, 105),
+        goto(1000.1, 130),
+    ];
+    let service = MemoryEventService::new();
+    for idx in 1..waypoints.len() {
+        control
+            .exec_waypoint(service.clone(), "d1".into(), "m1".into(), &waypoints, idx)
+            .await
+            .unwrap();
     }
+    // only the last one climbs far enough
+    assert_eq!(drone.commands()[2..], ["go 10 0 30 100"]);
 }
@@ -0,0 +1,178 @@
+use crate::twins::mission_twin::types::{GoToWaypoint, Waypoint};
+use std::f64::consts::FRAC_PI_2;
+use tello::odometry::Odometry;
+
+/// radius of the Web Mercator sphere (EPSG:3857), in m
+const EARTH_RADIUS: f64 = 6_378_137.0;
+
+/// Position and yaw of the drone relative to its take off, `x` is ahead and `y` is left at take
+/// off. Lengths are in cm, the yaw is in deg clockwise like `cw`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Pose {
+    pub x: f64,
+    pub y: f64,
+    pub yaw: f64,
+}
+
+impl Pose {
+    /// `odometry` relative to the `origin` at take off, the odometry turns counterclockwise
+    pub fn from_odometry(origin: &Odometry, odometry: &Odometry) -> Self {
+        let (dx, dy) = (odometry.x - origin.x, odometry.y - origin.y);
+        let (sin, cos) = (-origin.rot).sin_cos();
+        Self {
+            x: dx * cos - dy * sin,
+            y: dx * sin + dy * cos,
+            yaw: normalize((origin.rot - odometry.rot).to_degrees()),
+        }
+    }
+
+    /// moves by `x y` cm relative to the yaw
+    pub fn move_by(&mut self, x: f64, y: f64) {
+        let yaw = self.yaw.to_radians();
+        self.x += x * yaw.cos() + y * yaw.sin();
+        self.y += -x * yaw.sin() + y * yaw.cos();
+    }
+
+    pub fn turn(&mut self, deg: f64) {
+        self.yaw = normalize(self.yaw + deg);
+    }
+
+    /// turn and distance in cm to fly straight to `target`
+    pub fn course_to(&self, target: (f64, f64)) -> (f64, f64) {
+        let (dx, dy) = (target.0 - self.x, target.1 - self.y);
+        let yaw = bearing((self.x, self.y), target);
+        (normalize(yaw - self.yaw), dx.hypot(dy))
+    }
+}
+
+/// yaw from `from` to `to`
+pub fn bearing(from: (f64, f64), to: (f64, f64)) -> f64 {
+    (from.1 - to.1).atan2(to.0 - from.0).to_degrees()
+}
+
+/// the angle within -180..=180 deg
+pub fn normalize(deg: f64) -> f64 {
+    let deg = deg.rem_euclid(360.0);
+    if deg > 180.0 {
+        deg - 360.0
+    } else {
+        deg
+    }
+}
+
+/// The map of the `MissionPlanner` in the frame of the `Pose`. The drone takes off at the start
+/// point facing the first goto.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MapFrame {
+    origin: (f64, f64),
+    /// unit vector of the first leg on the map
+    ahead: (f64, f64),
+    /// cm per map unit, Web Mercator stretches by 1/cos(latitude)
+    scale: f64,
+}
+
+impl MapFrame {
+    /// `None` if the start point or all gotos have no map coordinates
+    pub fn new(waypoints: &[Waypoint]) -> Option<Self> {
+        let origin = waypoints.first().and_then(map_point)?;
+        let first = waypoints
+            .iter()
+            .filter_map(map_point)
+            .find(|point| *point != origin)?;
+        let (dx, dy) = (first.0 - origin.0, first.1 - origin.1);
+        let length = dx.hypot(dy);
+        let latitude = 2.0 * (origin.1 / EARTH_RADIUS).exp().atan() - FRAC_PI_2;
+        Some(Self {
+            origin,
+            ahead: (dx / length, dy / length),
+            scale: latitude.cos() * 100.0,
+        })
+    }
+
+    /// the map point in cm relative to the take off
+    pub fn local(&self, (x, y): (f64, f64)) -> (f64, f64) {
+        let dx = (x - self.origin.0) * self.scale;
+        let dy = (y - self.origin.1) * self.scale;
+        let (ahead_x, ahead_y) = self.ahead;
+        // left is ahead turned counterclockwise
+        (dx * ahead_x + dy * ahead_y, dy * ahead_x - dx * ahead_y)
+    }
+
+    /// the point in cm relative to the take off on the map
+    pub fn map(&self, (x, y): (f64, f64)) -> (f64, f64) {
+        let (ahead_x, ahead_y) = self.ahead;
+        let dx = x * ahead_x - y * ahead_y;
+        let dy = x * ahead_y + y * ahead_x;
+        (
+            self.origin.0 + dx / self.scale,
+            self.origin.1 + dy / self.scale,
+        )
+    }
+}
+
+/// map point of a goto, the `MissionBuilder` and older missions have none
+pub fn map_point(wp: &Waypoint) -> Option<(f64, f64)> {
+    match wp {
+        Waypoint::Goto(GoToWaypoint { map_x, map_y, .. }) if (*map_x, *map_y) != (0.0, 0.0) => {
+            Some((*map_x, *map_y))
+        }
+        _ => None,
+    }
+}
+
+#[test]
+fn maps_the_planned_route_into_the_take_off_frame() {
+    let goto = |map_x, map_y| {
+        Waypoint::Goto(GoToWaypoint {
+            map_x,
+            map_y,
+            height: 100,
+            angle: None,
+            distance: 0.0,
+            duration: 0.0,
+        })
+    };
+    // first leg to the east, at the equator a map unit is a meter
+    let frame = MapFrame::new(&[goto(1000.0, 0.1), goto(1003.0, 0.1)]).unwrap();
+    let (x, y) = frame.local((1003.0, 2.1));
+    assert_eq!((x.round(), y.round()), (300.0, 200.0));
+
+    // 60° north a map unit is half a meter
+    let north = EARTH_RADIUS * 60f64.to_radians().tan().asinh();
+    let frame = MapFrame::new(&[goto(5.0, north), goto(5.0, north + 4.0)]).unwrap();
+    let (x, y) = frame.local((1.0, north + 4.0));
+    assert_eq!((x.round(), y.round()), (200.0, 200.0));
+    let (x, y) = frame.map((200.0, 200.0));
+    assert_eq!((x.round(), (y - north).round()), (1.0, 4.0));
+
+    assert_eq!(MapFrame::new(&[goto(0.0, 0.0), goto(3.0, 0.0)]), None);
+
+    let mut pose = Pose::default();
+    pose.turn(-90.0);
+    pose.move_by(200.0, 0.0);
+    assert_eq!(
+        (pose.x.round(), pose.y.round(), pose.yaw),
+        (0.0, 200.0, -90.0)
+    );
+    let (turn, distance) = pose.course_to((200.0, 200.0));
+    assert_eq!((turn.round(), distance.round()), (90.0, 200.0));
+    assert_eq!(normalize(270.0), -90.0);
+
+    // took off facing left of the odometry, flew 2m ahead and turned right
+    let origin = Odometry {
+        x: 100.0,
+        rot: 90f64.to_radians(),
+        ..Default::default()
+    };
+    let odometry = Odometry {
+        x: 100.0,
+        y: 200.0,
+        rot: 0.0,
+        ..Default::default()
+    };
+    let pose = Pose::from_odometry(&origin, &odometry);
+    assert_eq!(
+        (pose.x.round(), pose.y.round(), pose.yaw.round()),
+        (200.0, 0.0, 90.0)
+    );
+}
@@ -0,0 +1,711 @@
+use super::driver::{DroneDriver, StopLine, TelemetryLine};
+use crate::twins::{
+    drone_twin::events::StopCommand,
+    mission_twin::types::{CameraAction, FlipDirection},
+};
+use async_trait::async_trait;
+use std::{
+    sync::{mpsc::TryRecvError, Arc, Mutex},
+    time::Duration,
+};
+use tello::{command_mode::CommandModeState, odometry::Odometry};
+use tokio::{
+    select,
+    sync::Notify,
+    time::{sleep, Instant},
+};
+
+#[derive(Clone, Debug)]
+pub struct SimulationConfig {
+    /// 1.0 runs in real time, 0.0 executes every command immediately
+    pub time_scale: f32,
+    pub takeoff_height: f64,
+    /// deg per second for cw / ccw
+    pub rotation_speed: f64,
+    /// percent per second while the drone is in the air
+    pub battery_drain: f64,
+    /// the drone refuses to take off below this level
+    pub min_takeoff_battery: u8,
+    /// time until a command without answer fails (the tello crate waits 30s)
+    pub command_timeout: Duration,
+    /// mission pads on the ground, id and position in cm
+    pub mission_pads: Vec<(u8, f64, f64)>,
+    /// cm/s the wind pushes the drone along `x` and `y` during a `go`, the odometry follows it
+    pub drift: (f64, f64),
+}
+
+impl Default for SimulationConfig {
+    fn default() -> Self {
+        Self {
+            time_scale: 1.0,
+            takeoff_height: 100.0,
+            rotation_speed: 90.0,
+            battery_drain: 0.13,
+            min_takeoff_battery: 10,
+            command_timeout: Duration::from_secs(30),
+            mission_pads: vec![],
+            drift: (0.0, 0.0),
+        }
+    }
+}
+
+/// only tests inject failures so far
+#[allow(dead_code)]
+#[derive(Clone, Debug, PartialEq)]
+pub enum SimulatedFailure {
+    /// the drone never answers, the command fails with `timeout`
+    Timeout,
+    /// the drone answers with the given error
+    Error(String),
+}
+
+#[derive(Debug)]
+struct SimulatedState {
+    connected: bool,
+    flying: bool,
+    x: f64,
+    y: f64,
+    z: f64,
+    /// clockwise, in deg
+    heading: f64,
+    battery: f64,
+    flight_time: f64,
+    /// the `go` on its way
+    motion: Option<Motion>,
+    failures: Vec<(String, SimulatedFailure)>,
+    commands: Vec<String>,
+}
+
+/// straight move from `from` to `to` in cm
+#[derive(Debug)]
+struct Motion {
+    from: [f64; 3],
+    to: [f64; 3],
+    started: Instant,
+    /// in real time, `time_scale` applied
+    duration: Duration,
+}
+
+impl Motion {
+    fn progress(&self) -> f64 {
+        if self.duration.is_zero() {
+            1.0
+        } else {
+            (self.started.elapsed().as_secs_f64() / self.duration.as_secs_f64()).min(1.0)
+        }
+    }
+
+    fn position(&self) -> [f64; 3] {
+        let progress = self.progress();
+        [0, 1, 2].map(|i| self.from[i] + (self.to[i] - self.from[i]) * progress)
+    }
+
+    /// in cm per real second
+    fn velocity(&self) -> [f64; 3] {
+        if self.progress() >= 1.0 {
+            return [0.0; 3];
+        }
+        let secs = self.duration.as_secs_f64();
+        [0, 1, 2].map(|i| (self.to[i] - self.from[i]) / secs)
+    }
+}
+
+/// Simulated Tello drone
+///
+/// Tracks position (cm, `x` is the heading at take off), heading, height and battery. A `go`
+/// moves the drone on the way, the other commands when they end. Clones share the same drone,
+/// so a test can keep one to inspect it or inject failures after the drone was handed to
+/// `DroneControl`.
+#[derive(Clone)]
+pub struct SimulatedDrone {
+    config: SimulationConfig,
+    state: Arc<Mutex<SimulatedState>>,
+    /// interrupts the command in flight
+    stopped: Arc<Notify>,
+}
+
+impl Default for SimulatedDrone {
+    fn default() -> Self {
+        Self::new(SimulationConfig::default())
+    }
+}
+
+impl SimulatedDrone {
+    pub fn new(config: SimulationConfig) -> Self {
+        Self {
+            config,
+            state: Arc::new(Mutex::new(SimulatedState {
+                connected: false,
+                flying: false,
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+                heading: 0.0,
+                battery: 100.0,
+                flight_time: 0.0,
+                motion: None,
+                failures: vec![],
+                commands: vec![],
+            })),
+            stopped: Arc::new(Notify::new()),
+        }
+    }
+
+    #[cfg(test)]
+    /// Let the next command starting with `command` (e.g. `go`, `takeoff`) fail.
+    pub fn inject_failure(&self, command: &str, failure: SimulatedFailure) {
+        self.state
+            .lock()
+            .unwrap()
+            .failures
+            .push((command.to_string(), failure));
+    }
+
+    #[cfg(test)]
+    pub fn set_battery(&self, battery: u8) {
+        self.state.lock().unwrap().battery = battery as f64;
+    }
+
+    pub fn battery(&self) -> u8 {
+        self.state.lock().unwrap().battery.round() as u8
+    }
+
+    #[cfg(test)]
+    /// position in cm and heading in deg (clockwise)
+    pub fn pose(&self) -> (f64, f64, f64, f64) {
+        let mut s = self.state.lock().unwrap();
+        Self::settle(&mut s);
+        (s.x, s.y, s.z, s.heading)
+    }
+
+    #[cfg(test)]
+    pub fn is_flying(&self) -> bool {
+        self.state.lock().unwrap().flying
+    }
+
+    #[cfg(test)]
+    /// all commands sent to the drone, in SDK syntax
+    pub fn commands(&self) -> Vec<String> {
+        self.state.lock().unwrap().commands.clone()
+    }
+
+    async fn execute<F>(&self, command: String, duration: f64, apply: F) -> Result<(), String>
+    where
+        F: FnOnce(&mut SimulatedState) -> Result<(), String>,
+    {
+        self.run(command, duration, None, apply).await
+    }
+
+    /// Executes `command` for `duration` simulated seconds and applies its effect at the end. A
+    /// flying drone moves by `moves_by` cm relative to the heading on the way.
+    async fn run<F>(
+        &self,
+        command: String,
+        duration: f64,
+        moves_by: Option<[f64; 3]>,
+        apply: F,
+    ) -> Result<(), String>
+    where
+        F: FnOnce(&mut SimulatedState) -> Result<(), String>,
+    {
+        let failure = {
+            let mut state = self.state.lock().unwrap();
+            state.commands.push(command.clone());
+            let keyword = command.split(' ').next().unwrap_or_default();
+            let idx = state.failures.iter().position(|(c, _)| c == keyword);
+            idx.map(|idx| state.failures.remove(idx).1)
+        };
+        match failure {
+            Some(SimulatedFailure::Timeout) => {
+                self.wait(self.config.command_timeout.as_secs_f64()).await;
+                return Err("timeout".to_string());
+            }
+            Some(SimulatedFailure::Error(e)) => return Err(e),
+            None => (),
+        }
+
+        if let Some(by) = moves_by {
+            let mut state = self.state.lock().unwrap();
+            if state.flying {
+                Self::settle(&mut state);
+                let from = [state.x, state.y, state.z];
+                let (drift_x, drift_y) = self.config.drift;
+                let mut to = from;
+                Self::move_by(&mut to, state.heading, by);
+                to[0] += drift_x * duration;
+                to[1] += drift_y * duration;
+                state.motion = Some(Motion {
+                    from,
+                    to,
+                    started: Instant::now(),
+                    duration: self.scaled(duration),
+                });
+            }
+        }
+
+        select! {
+            _ = self.wait(duration) => (),
+            // a stopped drone fails the command with `Motor stop` below, a hovering one ends it
+            _ = self.stopped.notified() => (),
+        }
+
+        let mut state = self.state.lock().unwrap();
+        // arrived, a stop took the motion already
+        if let Some(motion) = state.motion.take() {
+            let [x, y, z] = motion.to;
+            state.x = x;
+            state.y = y;
+            state.z = z;
+        }
+        if state.flying {
+            state.flight_time += duration;
+            state.battery = (state.battery - duration * self.config.battery_drain).max(0.0);
+        }
+        apply(&mut state)?;
+        println!("simulated drone: {}", command);
+        Ok(())
+    }
+
+    async fn wait(&self, secs: f64) {
+        sleep(self.scaled(secs)).await;
+    }
+
+    fn scaled(&self, secs: f64) -> Duration {
+        Duration::from_secs_f64((secs * self.config.time_scale as f64).max(0.0))
+    }
+
+    /// the position of the motion so far, the motion ends when it arrived
+    fn settle(state: &mut SimulatedState) {
+        if let Some(motion) = &state.motion {
+            let [x, y, z] = motion.position();
+            state.x = x;
+            state.y = y;
+            state.z = z;
+            if motion.progress() >= 1.0 {
+                state.motion = None;
+            }
+        }
+    }
+
+    fn in_flight(state: &SimulatedState) -> Result<(), String> {
+        if !state.connected {
+            Err("no drone connected".to_string())
+        } else if !state.flying {
+            Err("error Motor stop".to_string())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// moves `position` by `x y z` cm relative to the heading, `x` is forward and `y` is left
+    fn move_by(position: &mut [f64; 3], heading: f64, [x, y, z]: [f64; 3]) {
+        let heading = heading.to_radians();
+        position[0] += x * heading.cos() + y * heading.sin();
+        position[1] += -x * heading.sin() + y * heading.cos();
+        position[2] = (position[2] + z).max(0.0);
+    }
+
+    async fn rotate(&self, command: &str, deg: i64) -> Result<(), String> {
+        if !(1..=3600).contains(&deg.abs()) {
+            return Err("error out of range".to_string());
+        }
+        let duration = deg.abs() as f64 / self.config.rotation_speed;
+        self.execute(format!("{} {}", command, deg.abs()), duration, |s| {
+            Self::in_flight(s)?;
+            s.heading = (s.heading + deg as f64).rem_euclid(360.0);
+            Ok(())
+        })
+        .await
+    }
+}
+
+impl StopLine for SimulatedDrone {
+    fn send(&self, command: StopCommand) -> Result<(), String> {
+        let mut state = self.state.lock().unwrap();
+        Self::settle(&mut state);
+        state.motion = None;
+        let res = match command {
+            StopCommand::Emergency => {
+                state.commands.push("emergency".to_string());
+                Ok(())
+            }
+            StopCommand::Land => {
+                state.commands.push("land".to_string());
+                Self::in_flight(&state)
+            }
+        };
+        state.flying = false;
+        state.z = 0.0;
+        self.stopped.notify_waiters();
+        println!("simulated drone: {:?}", command);
+        res
+    }
+
+    fn hover(&self) -> Result<(), String> {
+        let mut state = self.state.lock().unwrap();
+        state.commands.push("stop".to_string());
+        Self::settle(&mut state);
+        state.motion = None;
+        self.stopped.notify_waiters();
+        println!("simulated drone: stop");
+        Self::in_flight(&state)
+    }
+}
+
+impl TelemetryLine for SimulatedDrone {
+    fn odometry(&self) -> Odometry {
+        DroneDriver::odometry(self)
+    }
+
+    fn battery(&self) -> Option<u8> {
+        Some(SimulatedDrone::battery(self))
+    }
+}
+
+#[async_trait(?Send)]
+impl DroneDriver for SimulatedDrone {
+    fn is_connected(&self) -> bool {
+        self.state.lock().unwrap().connected
+    }
+
+    async fn connect(&mut self, _ip: &str) -> Result<(), String> {
+        self.execute("command".to_string(), 0.1, |s| {
+            s.connected = true;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn take_off(&mut self) -> Result<(), String> {
+        let height = self.config.takeoff_height;
+        let min_battery = self.config.min_takeoff_battery as f64;
+        self.execute("takeoff".to_string(), 3.0, |s| {
+            if !s.connected {
+                Err("no drone connected".to_string())
+            } else if s.battery < min_battery {
+                Err("error battery low".to_string())
+            } else {
+                s.flying = true;
+                s.z = height;
+                Ok(())
+            }
+        })
+        .await
+    }
+
+    async fn land(&mut self) -> Result<(), String> {
+        self.execute("land".to_string(), 3.0, |s| {
+            Self::in_flight(s)?;
+            s.flying = false;
+            s.z = 0.0;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn go_to(&mut self, x: i32, y: i32, z: i32, speed: u8) -> Result<(), String> {
+        let in_range = |v: i32| (-500..=500).contains(&v);
+        if !(in_range(x) && in_range(y) && in_range(z) && (10..=100).contains(&speed)) {
+            return Err("error out of range".to_string());
+        }
+        if x.abs() < 20 && y.abs() < 20 && z.abs() < 20 {
+            return Err("error out of range".to_string());
+        }
+        let command = format!("go {} {} {} {}", x, y, z, speed);
+        let by = [x as f64, y as f64, z as f64];
+        let distance = by.iter().map(|v| v * v).sum::<f64>().sqrt();
+        self.run(command, distance / speed as f64, Some(by), |s| {
+            Self::in_flight(s)
+        })
+        .await
+    }
+
+    async fn cw(&mut self, deg: u32) -> Result<(), String> {
+        self.rotate("cw", deg as i64).await
+    }
+
+    async fn ccw(&mut self, deg: u32) -> Result<(), String> {
+        self.rotate("ccw", -(deg as i64)).await
+    }
+
+    async fn speed(&mut self, speed: u8) -> Result<(), String> {
+        if !(10..=100).contains(&speed) {
+            return Err("error out of range".to_string());
+        }
+        self.execute(format!("speed {}", speed), 0.0, |s| {
+            if s.connected {
+                Ok(())
+            } else {
+                Err("no drone connected".to_string())
+            }
+        })
+        .await
+    }
+
+    async fn curve(
+        &mut self,
+        x1: i32,
+        y1: i32,
+        z1: i32,
+        x2: i32,
+        y2: i32,
+        z2: i32,
+        speed: u8,
+    ) -> Result<(), String> {
+        let points = [x1, y1, z1, x2, y2, z2];
+        let short = |p: &[i32]| p.iter().all(|v| v.abs() < 20);
+        if !points.iter().all(|v| (-500..=500).contains(v))
+            || short(&points[..3])
+            || short(&points[3..])
+            || !(10..=60).contains(&speed)
+        {
+            return Err("error out of range".to_string());
+        }
+        let command = format!("curve {} {} {} {} {} {} {}", x1, y1, z1, x2, y2, z2, speed);
+        let length = |x: i32, y: i32, z: i32| ((x * x + y * y + z * z) as f64).sqrt();
+        let chords = length(x1, y1, z1) + length(x2 - x1, y2 - y1, z2 - z1);
+        self.execute(command, chords / speed as f64, |s| {
+            Self::in_flight(s)?;
+            let mut position = [s.x, s.y, s.z];
+            Self::move_by(&mut position, s.heading, [x2 as f64, y2 as f64, z2 as f64]);
+            let [x, y, z] = position;
+            s.x = x;
+            s.y = y;
+            s.z = z;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn flip(&mut self, direction: FlipDirection) -> Result<(), String> {
+        let direction = match direction {
+            FlipDirection::Left => "l",
+            FlipDirection::Right => "r",
+            FlipDirection::Forward => "f",
+            FlipDirection::Back => "b",
+        };
+        self.execute(format!("flip {}", direction), 1.0, |s| {
+            Self::in_flight(s)?;
+            // the Tello flips above half of its battery only
+            if s.battery < 50.0 {
+                Err("error battery low".to_string())
+            } else {
+                Ok(())
+            }
+        })
+        .await
+    }
+
+    async fn camera(&mut self, action: CameraAction) -> Result<(), String> {
+        let command = match action {
+            CameraAction::StartVideo => "streamon",
+            CameraAction::StopVideo => "streamoff",
+        };
+        self.execute(command.to_string(), 0.0, |s| {
+            if !s.connected {
+                return Err("no drone connected".to_string());
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    async fn go_to_pad(
+        &mut self,
+        x: i32,
+        y: i32,
+        z: i32,
+        speed: u8,
+        pad: u8,
+    ) -> Result<(), String> {
+        let in_range = |v: i32| (-500..=500).contains(&v);
+        if !(in_range(x) && in_range(y) && in_range(z) && (10..=100).contains(&speed)) {
+            return Err("error out of range".to_string());
+        }
+        let command = format!("go {} {} {} {} m{}", x, y, z, speed, pad);
+        let pad = self
+            .config
+            .mission_pads
+            .iter()
+            .find(|(id, ..)| *id == pad)
+            .map(|(_, pad_x, pad_y)| (*pad_x, *pad_y));
+        let duration = {
+            let s = self.state.lock().unwrap();
+            pad.map_or(0.0, |(pad_x, pad_y)| {
+                let (dx, dy, dz) = (
+                    pad_x + x as f64 - s.x,
+                    pad_y + y as f64 - s.y,
+                    z as f64 - s.z,
+                );
+                (dx * dx + dy * dy + dz * dz).sqrt() / speed as f64
+            })
+        };
+        self.execute(command, duration, |s| {
+            Self::in_flight(s)?;
+            // the axes of the pads are the ones at take off
+            let (pad_x, pad_y) = pad.ok_or_else(|| "error Not found mission pad".to_string())?;
+            s.x = pad_x + x as f64;
+            s.y = pad_y + y as f64;
+            s.z = z as f64;
+            Ok(())
+        })
+        .await
+    }
+
+    fn odometry(&self) -> Odometry {
+        let mut s = self.state.lock().unwrap();
+        Self::settle(&mut s);
+        Odometry {
+            x: s.x,
+            y: s.y,
+            z: s.z,
+            rot: -s.heading.to_radians(),
+        }
+    }
+
+    fn try_recv_state(&mut self) -> Result<CommandModeState, TryRecvError> {
+        let mut s = self.state.lock().unwrap();
+        if !s.connected {
+            return Err(TryRecvError::Disconnected);
+        }
+        Self::settle(&mut s);
+        // dm/s like the Tello
+        let [vgx, vgy, _] = s.motion.as_ref().map_or([0.0; 3], Motion::velocity);
+        let yaw = if s.heading > 180.0 {
+            s.heading - 360.0
+        } else {
+            s.heading
+        };
+        Ok(CommandModeState {
+            yaw: yaw.round() as i16,
+            vgx: (vgx / 10.0).round() as i16,
+            vgy: (vgy / 10.0).round() as i16,
+            tof: s.z.round() as i16,
+            h: s.z.round() as i16,
+            bat: s.battery.round() as i8,
+            time: s.flight_time as f32,
+            ..Default::default()
+        })
+    }
+
+    fn stop_line(&self) -> Option<Arc<dyn StopLine>> {
+        Some(Arc::new(self.clone()))
+    }
+
+    fn telemetry_line(&self) -> Option<Arc<dyn TelemetryLine>> {
+        Some(Arc::new(self.clone()))
+    }
+}
+
+#[tokio::test]
+async fn simulated_drone_tracks_pose_and_battery() {
+    let mut drone = SimulatedDrone::new(SimulationConfig {
+        time_scale: 0.0,
+        battery_drain: 1.0,
+        ..Default::default()
+    });
+    assert!(drone.go_to(100, 0, 0, 100).await.is_err());
+
+    drone.connect("127.0.0.1:8889").await.unwrap();
+    assert_eq!(
+        drone.go_to(100, 0, 0, 100).await.unwrap_err(),
+        "error Motor stop"
+    );
+    drone.take_off().await.unwrap();
+    drone.go_to(100, 0, 0, 100).await.unwrap();
+    drone.cw(90).await.unwrap();
+    drone.go_to(200, 0, 50, 100).await.unwrap();
+    drone.ccw(180).await.unwrap();
+
+    let (x, y, z, heading) = drone.pose();
+    assert_eq!((x.round(), y.round(), z.round()), (100.0, -200.0, 150.0));
+    assert_eq!(heading, 270.0);
+    assert_eq!(drone.try_recv_state().unwrap().yaw, -90);
+
+    // 1s + 2.06s moving, 1s + 2s turning
+    assert_eq!(drone.battery(), 94);
+    assert!(drone.go_to(10, 0, 0, 100).await.is_err());
+    drone.land().await.unwrap();
+    assert!(!drone.is_flying());
+}
+
+#[tokio::test]
+async fn simulated_drone_injects_failures() {
+    let mut drone = SimulatedDrone::new(SimulationConfig {
+        time_scale: 0.0,
+        ..Default::default()
+    });
+    drone.connect("127.0.0.1:8889").await.unwrap();
+    drone.take_off().await.unwrap();
+
+    drone.inject_failure("go", SimulatedFailure::Timeout);
+    assert_eq!(drone.go_to(100, 0, 0, 100).await.unwrap_err(), "timeout");
+    drone.go_to(100, 0, 0, 100).await.unwrap();
+    assert_eq!(drone.pose().0.round(), 100.0);
+
+    drone.inject_failure("land", SimulatedFailure::Error("error".to_string()));
+    assert!(drone.land().await.is_err());
+    drone.land().await.unwrap();
+
+    drone.set_battery(5);
+    assert!(drone.take_off().await.is_err());
+    assert_eq!(
+        drone.commands(),
+        vec![
+            "command",
+            "takeoff",
+            "go 100 0 0 100",
+            "go 100 0 0 100",
+            "land",
+            "land",
+            "takeoff"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn simulated_drone_flies_curves_flips_and_mission_pads() {
+    let mut drone = SimulatedDrone::new(SimulationConfig {
+        time_scale: 0.0,
+        mission_pads: vec![(1, 300.0, 100.0)],
+        ..Default::default()
+    });
+    drone.connect("127.0.0.1:8889").await.unwrap();
+    drone.take_off().await.unwrap();
+
+    drone.speed(50).await.unwrap();
+    drone.curve(100, 100, 0, 200, 0, 50, 30).await.unwrap();
+    assert!(drone.curve(10, 10, 0, 200, 0, 0, 30).await.is_err());
+    drone.flip(FlipDirection::Left).await.unwrap();
+    let (x, y, z, heading) = drone.pose();
+    assert_eq!(
+        (x.round(), y.round(), z.round(), heading),
+        (200.0, 0.0, 150.0, 0.0)
+    );
+
+    drone.camera(CameraAction::StartVideo).await.unwrap();
+
+    drone.go_to_pad(0, -50, 80, 50, 1).await.unwrap();
+    let (x, y, z, _) = drone.pose();
+    assert_eq!((x.round(), y.round(), z.round()), (300.0, 50.0, 80.0));
+    assert_eq!(
+        drone.go_to_pad(0, 0, 80, 50, 2).await.unwrap_err(),
+        "error Not found mission pad"
+    );
+
+    drone.set_battery(40);
+    assert_eq!(
+        drone.flip(FlipDirection::Back).await.unwrap_err(),
+        "error battery low"
+    );
+    assert_eq!(
+        drone.commands()[2..7],
+        [
+            "speed 50",
+            "curve 100 100 0 200 0 50 30",
+            "flip l",
+            "streamon",
+            "go 0 -50 80 50 m1"
+        ]
+    );
+}
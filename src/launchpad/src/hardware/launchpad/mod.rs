@@ -4,7 +4,7 @@ use std::time::Duration;
 use tokio::time::sleep;
 
 // Gpio uses BCM pin numbering. BCM GPIO 23 is tied to physical pin 16.
-const GPIO_PWM: u8 = 12;
+pub const DEFAULT_SERVO_PIN: u8 = 12;
 
 // Servo configuration. Change these values based on your servo's verified safe
 // minimum and maximum values.
@@ -23,26 +23,26 @@ fn get_pw_duration(proc: f32) -> Duration {
     Duration::from_micros(get_pw(proc))
 }
 
-pub async fn enable_drone() -> Result<(), anyhow::Error> {
+pub async fn enable_drone(servo_pin: u8) -> Result<(), anyhow::Error> {
     let period_ms = Duration::from_millis(PERIOD_MS);
 
     println!("switch on drone");
     // Rotate the servo to the opposite side.
     // Retrieve the GPIO pin and configure it as an output.
-    let mut pin = Gpio::new()?.get(GPIO_PWM)?.into_output();
+    let mut pin = Gpio::new()?.get(servo_pin)?.into_output();
     // Enable software-based PWM with the specified period, and rotate the servo by
     // setting the pulse width to its maximum value.
-    pin.set_pwm(period_ms.clone(), get_pw_duration(1.00f32))?;
+    pin.set_pwm(period_ms, get_pw_duration(1.00f32))?;
 
     // Sleep for 500 ms while the servo moves into position.
     // Rotate the servo to the opposite side.
     sleep(Duration::from_millis(1500)).await;
     // Rotate the servo to the opposite side.
-    pin.set_pwm(period_ms.clone(), get_pw_duration(0.70f32))?;
+    pin.set_pwm(period_ms, get_pw_duration(0.70f32))?;
 
     sleep(Duration::from_millis(500)).await;
 
-    pin.set_pwm(period_ms.clone(), get_pw_duration(1.00f32))?;
+    pin.set_pwm(period_ms, get_pw_duration(1.00f32))?;
 
     sleep(Duration::from_millis(500)).await;
     Ok(())
@@ -1,6 +1,9 @@
-use crate::twins::drone_twin::DroneTwin;
+use crate::twins::drone_twin::events::{
+    DroneConnectedEvent, DroneEvent, DroneLandedEvent, DroneLaunchedEvent,
+    DroneMissionCompletedEvent, MissionOutcome, Position,
+};
 use crate::twins::mission_twin::MissionTwinState;
-use actyx_sdk::HttpClient;
+use actyx_sdk::service::EventService;
 use std::sync::mpsc::TryRecvError;
 use std::time::Duration;
 use tello::command_mode::CommandModeState;
@@ -8,7 +11,7 @@ use tello::odometry::Odometry;
 use tokio::time::sleep;
 
 pub mod drone_control;
-use self::drone_control::DroneControl;
+use self::drone_control::{emergency::EmergencyStop, DroneControl, Tracker};
 
 #[cfg(feature = "wifi")]
 mod network;
@@ -18,30 +21,100 @@ use network::Network;
 #[cfg(feature = "hardware")]
 mod launchpad;
 
-pub struct Hardware {
-    service: HttpClient,
+/// Hardware of one launchpad. Unset values fall back to the setup of a single launchpad.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HardwareConfig {
+    /// BCM pin of the servo that switches the drone on
+    pub servo_pin: Option<u8>,
+    /// adapter that joins the drone's wifi, the first `wl*` adapter if unset
+    pub wifi_adapter: Option<String>,
+    /// used instead of the ip the drone was defined with
+    pub drone_ip: Option<String>,
+    /// local port for the drone commands, a free one if unset
+    pub command_port: Option<u16>,
+    /// local port for the drone state, 8890 if unset
+    pub state_port: Option<u16>,
+}
+
+impl HardwareConfig {
+    #[cfg(not(feature = "simulation"))]
+    fn tello_ports(&self) -> drone_control::driver::TelloPorts {
+        let default = drone_control::driver::TelloPorts::default();
+        drone_control::driver::TelloPorts {
+            command: self.command_port.unwrap_or(default.command),
+            state: self.state_port.unwrap_or(default.state),
+        }
+    }
+}
+
+pub struct Hardware<S: EventService> {
+    /// launchpad the hardware belongs to
+    name: String,
+    service: S,
+    config: HardwareConfig,
     drone: DroneControl,
 }
-impl Hardware {
-    pub fn new(service: HttpClient) -> Self {
+impl<S: EventService> Hardware<S> {
+    pub fn new(name: String, service: S, config: HardwareConfig) -> Self {
+        #[cfg(feature = "simulation")]
+        let drone = DroneControl::with_driver(drone_control::simulated::SimulatedDrone::default());
+        #[cfg(not(feature = "simulation"))]
+        let drone = DroneControl::new(config.tello_ports());
+        Self::with_drone(name, service, config, drone)
+    }
+    pub fn with_drone(
+        name: String,
+        service: S,
+        config: HardwareConfig,
+        drone: DroneControl,
+    ) -> Self {
         Self {
+            name,
             service,
-            drone: DroneControl::new(),
+            config,
+            drone,
         }
     }
-    fn service(&self) -> HttpClient {
+    fn service(&self) -> S {
         self.service.clone()
     }
 
     pub fn get_state(&mut self) -> Result<CommandModeState, TryRecvError> {
         self.drone.try_recv_state()
     }
+
+    /// battery of the connected drone in percent
+    pub fn battery(&mut self) -> Option<u8> {
+        self.get_state().ok().map(|s| s.bat.max(0) as u8)
+    }
+
+    pub fn emergency_stop(&self) -> EmergencyStop {
+        self.drone.emergency_stop()
+    }
+
+    /// reads the position while a waypoint is executed, `None` until the drone took off
+    pub fn tracker(&self) -> Option<Tracker> {
+        self.drone.tracker()
+    }
+
+    /// ends the command of a waypoint that was dropped, the drone hovers where it is
+    pub fn hover(&mut self) -> Result<(), String> {
+        self.drone.hover()
+    }
 }
 
-impl Hardware {
+impl<S: EventService> Hardware<S> {
     pub async fn enable_drone(&mut self) {
         #[cfg(feature = "hardware")]
-        self::launchpad::enable_drone().await;
+        {
+            let pin = self
+                .config
+                .servo_pin
+                .unwrap_or(launchpad::DEFAULT_SERVO_PIN);
+            if let Err(e) = self::launchpad::enable_drone(pin).await {
+                println!("{}: failed to switch on drone {}", self.name, e);
+            }
+        }
         #[cfg(not(feature = "hardware"))]
         sleep(Duration::from_millis(5000)).await;
     }
@@ -52,22 +125,17 @@ impl Hardware {
         ssid: String,
         ip: String,
     ) -> Result<(), anyhow::Error> {
-        println!("connect to drone now {} {} {}", id, ssid, ip);
+        println!("{}: connect to drone now {} {} {}", self.name, id, ssid, ip);
         #[cfg(feature = "wifi")]
         {
             println!("activate drone {}", id);
-            Network::connect(ssid).await?;
+            Network::connect(ssid, self.config.wifi_adapter.clone()).await?;
         }
 
-        let ip = if !ip.contains(':') {
-            format!("{}:8889", ip)
-        } else {
-            ip
-        };
-
-        match self.drone.connect(ip).await {
+        let ip = self.config.drone_ip.clone().unwrap_or(ip);
+        match self.drone.connect(&id, drone_address(ip)).await {
             Ok(()) => {
-                DroneTwin::emit_drone_connected(self.service(), id)
+                DroneEvent::emit_drone_connected(self.service(), DroneConnectedEvent { id })
                     .await
                     .map(|_| ())?;
             }
@@ -85,16 +153,25 @@ impl Hardware {
         ssid: String,
         ip: String,
         mission_id: String,
+        mission_version: u32,
     ) -> Result<(), anyhow::Error> {
-        println!("take_off drone {}", id);
-        if self.drone.is_drone_connected() == false {
+        println!("{}: take_off drone {}", self.name, id);
+        if !self.drone.is_drone_connected() {
             self.connect_now(id.to_owned(), ssid, ip).await?;
         }
 
         match self.drone.take_off().await {
             Ok(_) => {
                 println!("trigger emit_drone_started");
-                DroneTwin::emit_drone_launched(self.service(), id.to_owned(), mission_id).await?;
+                DroneEvent::emit_drone_launched(
+                    self.service(),
+                    DroneLaunchedEvent {
+                        id: id.to_owned(),
+                        mission_id,
+                        mission_version: Some(mission_version),
+                    },
+                )
+                .await?;
             }
             Err(e) => {
                 println!("failed to start drone {}", e);
@@ -111,7 +188,7 @@ impl Hardware {
         mission: &MissionTwinState,
     ) -> Result<(), anyhow::Error> {
         let next_wp = current_wp_id + 1;
-        if let Some(wp) = mission.waypoints.get(next_wp) {
+        if mission.waypoints.get(next_wp).is_some() {
             println!("exec waypoint {}", next_wp);
             let command_result = self
                 .drone
@@ -119,8 +196,8 @@ impl Hardware {
                     self.service(),
                     drone_id.clone(),
                     mission.id.clone(),
-                    wp,
-                    next_wp as i32,
+                    &mission.waypoints,
+                    next_wp,
                 )
                 .await;
 
@@ -128,34 +205,70 @@ impl Hardware {
                 println!("command failed {:?}", e);
                 sleep(Duration::new(5, 0)).await;
                 let _ = self.land_now(drone_id.clone()).await;
-                let _ = DroneTwin::emit_drone_mission_completed(
+                // the mission wasn't flown
+                let _ = DroneEvent::emit_drone_mission_completed(
                     self.service(),
-                    drone_id,
-                    mission.id.clone(),
+                    DroneMissionCompletedEvent {
+                        id: drone_id,
+                        mission_id: mission.id.clone(),
+                        outcome: MissionOutcome::Aborted,
+                    },
                 )
                 .await;
             }
             Ok(())
         } else {
-            DroneTwin::emit_drone_mission_completed(self.service(), drone_id, mission.id.clone())
-                .await
-                .map(|_| ())
+            DroneEvent::emit_drone_mission_completed(
+                self.service(),
+                DroneMissionCompletedEvent {
+                    id: drone_id,
+                    mission_id: mission.id.clone(),
+                    outcome: MissionOutcome::Completed,
+                },
+            )
+            .await
+            .map(|_| ())
+        }
+    }
+
+    /// Stops the mission. With `return_home` the drone flies back to the start point first,
+    /// otherwise or if that fails it lands in place.
+    pub async fn abort_mission(
+        &mut self,
+        drone_id: String,
+        mission: &MissionTwinState,
+        return_home: bool,
+    ) -> Result<(), anyhow::Error> {
+        println!("{}: abort mission {}", self.name, mission.id);
+        if return_home {
+            if let Err(e) = self.drone.return_home().await {
+                println!("failed to return home, land in place {:?}", e);
+            }
         }
+        self.land_now(drone_id.clone()).await?;
+        DroneEvent::emit_drone_mission_completed(
+            self.service(),
+            DroneMissionCompletedEvent {
+                id: drone_id,
+                mission_id: mission.id.clone(),
+                outcome: MissionOutcome::Aborted,
+            },
+        )
+        .await
+        .map(|_| ())
     }
 
     pub async fn land_now(&mut self, id: String) -> Result<(), anyhow::Error> {
-        println!("land drone {}", id);
+        println!("{}: land drone {}", self.name, id);
         match self.drone.land().await {
             Ok(_) => {
                 let Odometry { x, y, z, .. } = self.drone.pos();
-                DroneTwin::emit_drone_landed(
-                    self.service(),
-                    "Launchpad-01".to_string(),
-                    x as f32,
-                    y as f32,
-                    z as f32,
-                )
-                .await?;
+                let at = Position {
+                    x: x as f32,
+                    y: y as f32,
+                    z: z as f32,
+                };
+                DroneEvent::emit_drone_landed(self.service(), DroneLandedEvent { id, at }).await?;
             }
             Err(e) => {
                 println!("failed to land drone {}", e);
@@ -165,3 +278,18 @@ impl Hardware {
         Ok(())
     }
 }
+
+/// adds the Tello command port if the drone ip has none
+fn drone_address(ip: String) -> String {
+    if !ip.contains(':') {
+        format!("{}:8889", ip)
+    } else {
+        ip
+    }
+}
+
+#[test]
+fn drone_address_defaults_to_the_tello_command_port() {
+    assert_eq!(drone_address("192.168.10.1".into()), "192.168.10.1:8889");
+    assert_eq!(drone_address("127.0.0.1:9889".into()), "127.0.0.1:9889");
+}
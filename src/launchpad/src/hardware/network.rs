@@ -5,15 +5,16 @@ use wifi_rs::{
     prelude::{Config, Connectivity},
     WiFi,
 };
-use wifiscanner;
 
 pub struct Network();
 
 impl Network {
-    pub async fn connect(ssid: String) -> Result<(), anyhow::Error> {
-        let adapters = Network::list_adapters();
-        println!("available adapters {:?}", adapters);
-        let adapter = Network::filter_wifi_adapter(adapters);
+    pub async fn connect(ssid: String, adapter: Option<String>) -> Result<(), anyhow::Error> {
+        let adapter = adapter.or_else(|| {
+            let adapters = Network::list_adapters();
+            println!("available adapters {:?}", adapters);
+            Network::filter_wifi_adapter(adapters)
+        });
         println!("Wifi adapter {:?}", adapter);
         if let Some(adapter) = adapter {
             Network::wait_for_ssid(ssid.clone()).await?;
@@ -95,7 +96,7 @@ fn list_adapters() {
 
     println!("adapters {:?}", adapters);
     assert!(
-        adapters.len() > 0,
+        !adapters.is_empty(),
         "expect a network card on any computer who compiles rust"
     );
 }
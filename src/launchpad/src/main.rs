@@ -1,30 +1,50 @@
-use crate::twins::launchpad_twin::LaunchpadTwin;
-use actyx_sdk::{app_id, AppManifest, HttpClient};
-use url::Url;
+use crate::config::{Args, Command, Config};
+use crate::hardware::drone_control::{emulator::TelloEmulator, simulated::SimulatedDrone};
+use crate::snapshot::SnapshotStore;
+use crate::supervisor::Supervisor;
+use actyx_sdk::HttpClient;
+use structopt::StructOpt;
 
+mod battery;
+mod clock;
+mod config;
 mod controller;
+mod fleet;
+mod flight;
+mod geofence;
 mod hardware;
+#[cfg(test)]
+mod memory_service;
+mod snapshot;
+mod supervisor;
 mod twin;
 mod twins;
 
 #[tokio::main]
 pub async fn main() -> anyhow::Result<()> {
-    let name = "Launchpad-01".to_string();
-    // add your app manifest, for brevity we will use one in trial mode
-    let app_manifest = AppManifest::new(
-        app_id!("com.example.launchpad"),
-        "Drone Launchpad".into(),
-        "0.1.0".into(),
-        None,
-    );
+    let args = Args::from_args();
 
-    // Url of the locally running Actyx node
-    let url = Url::parse("http://localhost:4454")?;
-    // Http client to connect to actyx
-    let service = HttpClient::new(url, app_manifest).await?;
-    LaunchpadTwin::emit_launchpad_registered(service.clone(), name.clone()).await?;
+    // `launchpad tello-emulator [addr]` plays the drone instead of controlling one
+    if let Some(Command::TelloEmulator { addr, state_port }) = &args.command {
+        return TelloEmulator::bind(addr, SimulatedDrone::default())
+            .await?
+            .state_port(*state_port)
+            .run()
+            .await;
+    }
+
+    // name, Actyx node and app manifest from command line, env and config file
+    let config = Config::load(&args)?;
+    let names: Vec<_> = config.launchpads.iter().map(|l| l.name.clone()).collect();
+    println!("starting launchpads {:?} on {}", names, config.actyx_url);
 
-    controller::Controller::new(name, service).start().await?;
+    // Http client to connect to actyx
+    let service = HttpClient::new(config.actyx_url, config.app_manifest).await?;
+    let snapshots = config.snapshot_dir.map(SnapshotStore::new).transpose()?;
 
-    Ok(())
+    // one controller per launchpad, each on its own thread
+    Supervisor::new(service, config.launchpads)
+        .snapshots(snapshots)
+        .run()
+        .await
 }
@@ -0,0 +1,467 @@
+use actyx_sdk::{
+    app_id,
+    language::{Query, SortKey, TagAtom, TagExpr},
+    service::{
+        EventResponse, EventService, OffsetMapResponse, OffsetsResponse, Order, PublishRequest,
+        PublishResponse, PublishResponseKey, QueryRequest, QueryResponse, StartFrom,
+        SubscribeMonotonicRequest, SubscribeMonotonicResponse, SubscribeRequest, SubscribeResponse,
+    },
+    AppId, EventKey, LamportTimestamp, NodeId, Offset, OffsetMap, Payload, StreamId, TagSet,
+    Timestamp,
+};
+use async_trait::async_trait;
+use futures::{
+    channel::mpsc,
+    future,
+    stream::{self, BoxStream},
+    StreamExt,
+};
+use std::sync::{Arc, Mutex};
+
+type StoredEvent = EventResponse<Payload>;
+
+/// In-process stand-in for an Actyx node.
+///
+/// Events are kept in memory and sorted by lamport, stream and offset like Actyx does. All clones
+/// share the same store, so a twin, the controller and a test can talk to each other without a
+/// running node. Only the `FROM` part of an AQL query is supported.
+#[derive(Clone)]
+pub struct MemoryEventService {
+    store: Arc<Mutex<Store>>,
+    app_id: AppId,
+    stream: StreamId,
+}
+
+#[derive(Default)]
+struct Store {
+    events: Vec<StoredEvent>,
+    lamport: LamportTimestamp,
+    offsets: OffsetMap,
+    subscribers: Vec<mpsc::UnboundedSender<StoredEvent>>,
+    /// the node can't be reached, all requests fail
+    offline: bool,
+}
+
+impl Store {
+    fn insert(&mut self, event: StoredEvent) {
+        self.lamport = self.lamport.max(event.lamport);
+        self.offsets.update(event.stream, event.offset);
+        self.subscribers
+            .retain(|subscriber| subscriber.unbounded_send(event.clone()).is_ok());
+
+        let key = event_key(&event);
+        let idx = self.events.partition_point(|e| event_key(e) < key);
+        self.events.insert(idx, event);
+    }
+
+    fn next_offset(&self, stream: StreamId) -> Offset {
+        self.offsets
+            .get(stream)
+            .map(|o| o.succ())
+            .unwrap_or_default()
+    }
+
+    /// Returns the matching history together with a receiver for all events published afterwards.
+    fn snapshot(
+        &mut self,
+        query: &Query,
+        local: StreamId,
+    ) -> (
+        Vec<StoredEvent>,
+        mpsc::UnboundedReceiver<StoredEvent>,
+        OffsetMap,
+    ) {
+        let (tx, rx) = mpsc::unbounded();
+        self.subscribers.push(tx);
+        let events = self
+            .events
+            .iter()
+            .filter(|e| matches(&query.from, local, e))
+            .cloned()
+            .collect();
+        (events, rx, self.offsets.clone())
+    }
+}
+
+impl MemoryEventService {
+    pub fn new() -> Self {
+        Self::with_app_id(app_id!("com.example.launchpad"))
+    }
+
+    pub fn with_app_id(app_id: AppId) -> Self {
+        Self {
+            store: Arc::new(Mutex::new(Store::default())),
+            app_id,
+            stream: Self::stream_id(0, 0),
+        }
+    }
+
+    /// Creates the stream id of a (simulated) node. Node `0` is the local one.
+    pub fn stream_id(node: u8, stream_nr: u64) -> StreamId {
+        NodeId::from_bytes(&[node; 32])
+            .expect("MemoryEventService: invalid NodeId")
+            .stream(stream_nr.into())
+    }
+
+    /// Adds an event as if it was replicated from another node.
+    ///
+    /// In contrast to `publish`, the lamport timestamp is taken as given, which allows to provoke
+    /// a time travel in running `subscribe_monotonic` sessions.
+    pub fn replicate(
+        &self,
+        stream: StreamId,
+        lamport: LamportTimestamp,
+        tags: TagSet,
+        payload: Payload,
+    ) -> EventKey {
+        let mut store = self.store.lock().unwrap();
+        let offset = store.next_offset(stream);
+        let event = StoredEvent {
+            lamport,
+            stream,
+            offset,
+            timestamp: Timestamp::now(),
+            tags,
+            app_id: self.app_id.clone(),
+            payload,
+        };
+        let key = event_key(&event);
+        store.insert(event);
+        key
+    }
+
+    /// Simulates a lost connection to the node. Running subscriptions end and all requests fail
+    /// until the service is back online.
+    pub fn set_offline(&self, offline: bool) {
+        let mut store = self.store.lock().unwrap();
+        store.offline = offline;
+        if offline {
+            store.subscribers.clear();
+        }
+    }
+
+    /// All stored events in lamport order, regardless of any query.
+    pub fn events(&self) -> Vec<StoredEvent> {
+        self.store.lock().unwrap().events.clone()
+    }
+
+    fn connected(&self) -> anyhow::Result<()> {
+        if self.store.lock().unwrap().offline {
+            Err(anyhow::Error::msg("MemoryEventService: offline"))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn validate(query: &Query) -> anyhow::Result<()> {
+        if query.ops.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow::Error::msg(
+                "MemoryEventService: only `FROM` queries are supported",
+            ))
+        }
+    }
+}
+
+impl Default for MemoryEventService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl EventService for MemoryEventService {
+    async fn offsets(&self) -> anyhow::Result<OffsetsResponse> {
+        self.connected()?;
+        Ok(OffsetsResponse {
+            present: self.store.lock().unwrap().offsets.clone(),
+            to_replicate: Default::default(),
+        })
+    }
+
+    async fn publish(&self, request: PublishRequest) -> anyhow::Result<PublishResponse> {
+        self.connected()?;
+        let mut store = self.store.lock().unwrap();
+        let data = request
+            .data
+            .into_iter()
+            .map(|e| {
+                let event = StoredEvent {
+                    lamport: store.lamport.incr(),
+                    stream: self.stream,
+                    offset: store.next_offset(self.stream),
+                    timestamp: Timestamp::now(),
+                    tags: e.tags,
+                    app_id: self.app_id.clone(),
+                    payload: e.payload,
+                };
+                let key = PublishResponseKey {
+                    lamport: event.lamport,
+                    stream: event.stream,
+                    offset: event.offset,
+                    timestamp: event.timestamp,
+                };
+                store.insert(event);
+                key
+            })
+            .collect();
+        Ok(PublishResponse { data })
+    }
+
+    async fn query(
+        &self,
+        request: QueryRequest,
+    ) -> anyhow::Result<BoxStream<'static, QueryResponse>> {
+        self.connected()?;
+        Self::validate(&request.query)?;
+        let store = self.store.lock().unwrap();
+        let mut events: Vec<StoredEvent> = store
+            .events
+            .iter()
+            .filter(|e| matches(&request.query.from, self.stream, e))
+            .filter(|e| !is_contained(request.lower_bound.as_ref(), e))
+            .filter(|e| {
+                request
+                    .upper_bound
+                    .as_ref()
+                    .is_none_or(|upper| is_contained(Some(upper), e))
+            })
+            .cloned()
+            .collect();
+        match request.order {
+            Order::Asc => {}
+            Order::Desc => events.reverse(),
+            Order::StreamAsc => events.sort_by_key(|e| (e.stream, e.offset)),
+        }
+        Ok(stream::iter(events.into_iter().map(QueryResponse::Event)).boxed())
+    }
+
+    async fn subscribe(
+        &self,
+        request: SubscribeRequest,
+    ) -> anyhow::Result<BoxStream<'static, SubscribeResponse>> {
+        self.connected()?;
+        Self::validate(&request.query)?;
+        let (history, live, _) = self
+            .store
+            .lock()
+            .unwrap()
+            .snapshot(&request.query, self.stream);
+        let lower_bound = request.lower_bound;
+        let from = request.query.from;
+        let local = self.stream;
+
+        let history = stream::iter(history);
+        let live = live.filter(move |e| future::ready(matches(&from, local, e)));
+        Ok(history
+            .chain(live)
+            .filter(move |e| future::ready(!is_contained(lower_bound.as_ref(), e)))
+            .map(SubscribeResponse::Event)
+            .boxed())
+    }
+
+    async fn subscribe_monotonic(
+        &self,
+        request: SubscribeMonotonicRequest,
+    ) -> anyhow::Result<BoxStream<'static, SubscribeMonotonicResponse>> {
+        self.connected()?;
+        Self::validate(&request.query)?;
+        let StartFrom::LowerBound(lower_bound) = request.from;
+        let (history, live, offsets) = self
+            .store
+            .lock()
+            .unwrap()
+            .snapshot(&request.query, self.stream);
+        let history: Vec<StoredEvent> = history
+            .into_iter()
+            .filter(|e| !is_contained(Some(&lower_bound), e))
+            .collect();
+        let last_key = history.last().map(event_key);
+        let history_len = history.len();
+
+        let replay = stream::iter(history.into_iter().enumerate().map(move |(idx, event)| {
+            SubscribeMonotonicResponse::Event {
+                event,
+                caught_up: idx + 1 == history_len,
+            }
+        }))
+        .chain(stream::once(future::ready(
+            SubscribeMonotonicResponse::Offsets(OffsetMapResponse { offsets }),
+        )));
+
+        let from = request.query.from;
+        let local = self.stream;
+        let live = stream::unfold(
+            (live, last_key, false),
+            move |(mut live, last_key, time_traveled)| {
+                let from = from.clone();
+                let lower_bound = lower_bound.clone();
+                async move {
+                    if time_traveled {
+                        return None;
+                    }
+                    loop {
+                        let event = live.next().await?;
+                        if !matches(&from, local, &event)
+                            || is_contained(Some(&lower_bound), &event)
+                        {
+                            continue;
+                        }
+                        let key = event_key(&event);
+                        if last_key.is_some_and(|last| key < last) {
+                            let response =
+                                SubscribeMonotonicResponse::TimeTravel { new_start: key };
+                            break Some((response, (live, last_key, true)));
+                        }
+                        let response = SubscribeMonotonicResponse::Event {
+                            event,
+                            caught_up: true,
+                        };
+                        break Some((response, (live, Some(key), false)));
+                    }
+                }
+            },
+        );
+        Ok(replay.chain(live).boxed())
+    }
+}
+
+fn event_key(event: &StoredEvent) -> EventKey {
+    EventKey {
+        lamport: event.lamport,
+        stream: event.stream,
+        offset: event.offset,
+    }
+}
+
+fn is_contained(bounds: Option<&OffsetMap>, event: &StoredEvent) -> bool {
+    bounds
+        .and_then(|b| b.get(event.stream))
+        .is_some_and(|offset| offset >= event.offset)
+}
+
+fn matches(expr: &TagExpr, local: StreamId, event: &StoredEvent) -> bool {
+    match expr {
+        TagExpr::Or(e) => matches(&e.0, local, event) || matches(&e.1, local, event),
+        TagExpr::And(e) => matches(&e.0, local, event) && matches(&e.1, local, event),
+        TagExpr::Atom(atom) => match atom {
+            TagAtom::Tag(tag) => event.tags.contains(tag),
+            TagAtom::AllEvents => true,
+            TagAtom::IsLocal => event.stream == local,
+            TagAtom::FromTime(t) => event.timestamp >= *t,
+            TagAtom::ToTime(t) => event.timestamp < *t,
+            TagAtom::FromLamport(k) => SortKey::new(event.lamport, event.stream) >= *k,
+            TagAtom::ToLamport(k) => SortKey::new(event.lamport, event.stream) < *k,
+            TagAtom::AppId(app_id) => event.app_id == *app_id,
+        },
+    }
+}
+
+#[tokio::test]
+async fn query_filters_by_from_clause_in_lamport_order() {
+    use crate::twin::{mk_publish_request, tag_with_id};
+
+    let service = MemoryEventService::new();
+    for (base, id) in &[
+        ("drone", "d1"),
+        ("mission", "m1"),
+        ("drone", "d2"),
+        ("drone", "d1"),
+    ] {
+        service
+            .publish(mk_publish_request(tag_with_id(base, id), &id.to_string()))
+            .await
+            .unwrap();
+    }
+    let query = |aql: &str| QueryRequest {
+        lower_bound: None,
+        upper_bound: None,
+        query: aql.parse().unwrap(),
+        order: Order::Asc,
+    };
+
+    let lamports = |responses: Vec<QueryResponse>| -> Vec<u64> {
+        responses
+            .into_iter()
+            .filter_map(|r| match r {
+                QueryResponse::Event(e) => Some(e.lamport.into()),
+                _ => None,
+            })
+            .collect()
+    };
+
+    let res = service.query(query("FROM 'drone:d1'")).await.unwrap();
+    assert_eq!(lamports(res.collect().await), vec![1, 4]);
+
+    let res = service
+        .query(query("FROM 'drone' & 'drone:d2' | 'mission'"))
+        .await
+        .unwrap();
+    assert_eq!(lamports(res.collect().await), vec![2, 3]);
+
+    assert!(service
+        .query(query("FROM 'drone' FILTER _ = 'd1'"))
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn subscribe_monotonic_streams_live_events_and_detects_time_travel() {
+    use crate::twin::{mk_publish_request, tag_with_id};
+    use actyx_sdk::service::SessionId;
+
+    let service = MemoryEventService::new();
+    service
+        .publish(mk_publish_request(tag_with_id("drone", &"d1"), &"first"))
+        .await
+        .unwrap();
+
+    let mut stream = service
+        .subscribe_monotonic(SubscribeMonotonicRequest {
+            session: SessionId::from("test"),
+            query: "FROM 'drone:d1'".parse().unwrap(),
+            from: StartFrom::LowerBound(OffsetMap::empty()),
+        })
+        .await
+        .unwrap();
+
+    assert!(matches!(
+        stream.next().await,
+        Some(SubscribeMonotonicResponse::Event {
+            caught_up: true,
+            ..
+        })
+    ));
+    assert!(matches!(
+        stream.next().await,
+        Some(SubscribeMonotonicResponse::Offsets(_))
+    ));
+
+    service
+        .publish(mk_publish_request(tag_with_id("drone", &"d2"), &"other"))
+        .await
+        .unwrap();
+    service
+        .publish(mk_publish_request(tag_with_id("drone", &"d1"), &"second"))
+        .await
+        .unwrap();
+    match stream.next().await {
+        Some(SubscribeMonotonicResponse::Event { event, .. }) => {
+            assert_eq!(u64::from(event.lamport), 3)
+        }
+        other => panic!("expected event, got {:?}", other),
+    }
+
+    let key = service.replicate(
+        MemoryEventService::stream_id(1, 0),
+        LamportTimestamp::new(2),
+        tag_with_id("drone", &"d1"),
+        Payload::compact(&"replicated").unwrap(),
+    );
+    assert_eq!(
+        stream.next().await,
+        Some(SubscribeMonotonicResponse::TimeTravel { new_start: key })
+    );
+    assert_eq!(stream.next().await, None);
+}
@@ -0,0 +1,282 @@
+use crate::twin::Twin;
+use actyx_sdk::{EventKey, OffsetMap};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, Mutex,
+};
+
+/// snapshots kept per twin, older ones are needed after a time travel
+const KEPT_SNAPSHOTS: usize = 3;
+/// events reduced between two snapshots
+const DEFAULT_INTERVAL: usize = 100;
+/// numbers the temporary files of the writers
+static WRITES: AtomicUsize = AtomicUsize::new(0);
+
+/// State of a twin after reducing all events in `offsets`, the last one has the `key`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Snapshot<S> {
+    pub key: EventKey,
+    pub offsets: OffsetMap,
+    pub state: S,
+}
+
+/// entry of a snapshot file
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredSnapshot {
+    twin: String,
+    version: u32,
+    key: EventKey,
+    offsets: OffsetMap,
+    state: serde_json::Value,
+}
+
+/// Keeps the last snapshots of each twin as json file in a local directory.
+///
+/// Only twins with a `snapshot_version` are stored, a snapshot of another version is ignored.
+/// Clones share the store, the executers of all launchpads save the same twins.
+#[derive(Clone, Debug)]
+pub struct SnapshotStore {
+    dir: PathBuf,
+    interval: usize,
+    /// held while a file is read, changed and written back
+    lock: Arc<Mutex<()>>,
+}
+
+impl SnapshotStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Result<Self, anyhow::Error> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir).map_err(|e| {
+            anyhow::Error::msg(format!(
+                "can't create snapshot dir {}: {}",
+                dir.display(),
+                e
+            ))
+        })?;
+        Ok(Self {
+            dir,
+            interval: DEFAULT_INTERVAL,
+            lock: Default::default(),
+        })
+    }
+
+    /// takes a snapshot after every `events` reduced events
+    #[allow(dead_code)]
+    pub fn every(mut self, events: usize) -> Self {
+        self.interval = events.max(1);
+        self
+    }
+
+    pub fn interval(&self) -> usize {
+        self.interval
+    }
+
+    /// latest snapshot of `twin`, with a key before `before` if given
+    pub fn load<T: Twin>(&self, twin: &T, before: Option<&EventKey>) -> Option<Snapshot<T::State>> {
+        let version = twin.snapshot_version()?;
+        self.read(twin)
+            .into_iter()
+            .rev()
+            .filter(|s| s.version == version && before.is_none_or(|before| s.key < *before))
+            .find_map(|s| {
+                let state = serde_json::from_value(s.state).ok()?;
+                Some(Snapshot {
+                    key: s.key,
+                    offsets: s.offsets,
+                    state,
+                })
+            })
+    }
+
+    pub fn save<T: Twin>(
+        &self,
+        twin: &T,
+        snapshot: &Snapshot<T::State>,
+    ) -> Result<(), anyhow::Error> {
+        let version = match twin.snapshot_version() {
+            Some(version) => version,
+            None => return Ok(()),
+        };
+        let _lock = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        let mut snapshots = self.read(twin);
+        // another executer of the twin may have saved it already
+        snapshots.retain(|s| s.version == version && s.key != snapshot.key);
+        snapshots.push(StoredSnapshot {
+            twin: twin_id(twin),
+            version,
+            key: snapshot.key,
+            offsets: snapshot.offsets.clone(),
+            state: serde_json::to_value(&snapshot.state)?,
+        });
+        snapshots.sort_by_key(|s| s.key);
+        let skip = snapshots.len().saturating_sub(KEPT_SNAPSHOTS);
+        self.write(twin, &snapshots[skip..])
+    }
+
+    /// Drops the snapshots that contain `key` or later events. An event before them arrived, so
+    /// their state misses it.
+    pub fn invalidate<T: Twin>(&self, twin: &T, key: &EventKey) -> Result<(), anyhow::Error> {
+        let _lock = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        let mut snapshots = self.read(twin);
+        let len = snapshots.len();
+        snapshots.retain(|s| s.key < *key);
+        if snapshots.len() == len {
+            Ok(())
+        } else {
+            self.write(twin, &snapshots)
+        }
+    }
+
+    fn read<T: Twin>(&self, twin: &T) -> Vec<StoredSnapshot> {
+        let snapshots: Vec<StoredSnapshot> = std::fs::read(self.path(twin))
+            .ok()
+            .and_then(|content| serde_json::from_slice(&content).ok())
+            .unwrap_or_default();
+        // different ids can end up in the same file name
+        let id = twin_id(twin);
+        snapshots.into_iter().filter(|s| s.twin == id).collect()
+    }
+
+    fn write<T: Twin>(&self, twin: &T, snapshots: &[StoredSnapshot]) -> Result<(), anyhow::Error> {
+        let path = self.path(twin);
+        // a crash while writing must not leave a broken file behind
+        let tmp = path.with_extension(format!(
+            "json.{}-{}.tmp",
+            std::process::id(),
+            WRITES.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::write(&tmp, serde_json::to_vec(snapshots)?)?;
+        std::fs::rename(&tmp, &path)?;
+        Ok(())
+    }
+
+    fn path<T: Twin>(&self, twin: &T) -> PathBuf {
+        let name: String = twin_id(twin)
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '-' || c == '.' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        Path::new(&self.dir).join(format!("{}.json", name))
+    }
+}
+
+fn twin_id<T: Twin>(twin: &T) -> String {
+    format!("{}:{}", twin.name(), twin.id())
+}
+
+#[test]
+fn keeps_the_latest_snapshots_per_twin() {
+    use crate::memory_service::MemoryEventService;
+    use crate::twins::launchpad_twin::{LaunchpadTwin, LaunchpadTwinState};
+    use crate::twins::mission_twin::MissionRegistryTwin;
+    use actyx_sdk::{LamportTimestamp, Offset};
+
+    let dir = std::env::temp_dir().join("launchpad-snapshot-store-test");
+    let _ = std::fs::remove_dir_all(&dir);
+    let store = SnapshotStore::new(&dir).unwrap();
+    let twin = LaunchpadTwin::new("pad/1".to_string());
+    let stream = MemoryEventService::stream_id(0, 0);
+    let snapshot = |lamport: u64| {
+        let mut offsets = OffsetMap::empty();
+        offsets.update(stream, Offset::from(lamport as u32));
+        Snapshot {
+            key: EventKey {
+                lamport: LamportTimestamp::from(lamport),
+                stream,
+                offset: Offset::from(lamport as u32),
+            },
+            offsets,
+            state: LaunchpadTwinState {
+                id: "pad/1".to_string(),
+                current_mission: Some(format!("m{}", lamport)),
+                ..Default::default()
+            },
+        }
+    };
+    assert_eq!(store.load(&twin, None), None);
+
+    for lamport in 1..=4 {
+        store.save(&twin, &snapshot(lamport)).unwrap();
+    }
+    assert_eq!(store.load(&twin, None), Some(snapshot(4)));
+    assert_eq!(store.load(&twin, Some(&snapshot(4).key)), Some(snapshot(3)));
+    // only the last three are kept
+    assert_eq!(store.load(&twin, Some(&snapshot(2).key)), None);
+    assert_eq!(
+        store.load(&LaunchpadTwin::new("pad_1".to_string()), None),
+        None
+    );
+
+    store.invalidate(&twin, &snapshot(3).key).unwrap();
+    assert_eq!(store.load(&twin, None), Some(snapshot(2)));
+
+    // twins without snapshot version are not stored
+    let mut offsets = OffsetMap::empty();
+    offsets.update(stream, Offset::from(1));
+    let registry = Snapshot {
+        key: snapshot(1).key,
+        offsets,
+        state: vec!["m1".to_string()].into_iter().collect(),
+    };
+    store.save(&MissionRegistryTwin, &registry).unwrap();
+    assert_eq!(store.load(&MissionRegistryTwin, None), None);
+}
+
+#[test]
+fn launchpads_save_the_same_twin_in_parallel() {
+    use crate::memory_service::MemoryEventService;
+    use crate::twins::launchpad_twin::{LaunchpadTwin, LaunchpadTwinState};
+    use actyx_sdk::{LamportTimestamp, Offset};
+
+    let dir = std::env::temp_dir().join("launchpad-snapshot-store-parallel-test");
+    let _ = std::fs::remove_dir_all(&dir);
+    let store = SnapshotStore::new(&dir).unwrap();
+    let twin = LaunchpadTwin::new("pad-1".to_string());
+    let stream = MemoryEventService::stream_id(0, 0);
+    let snapshot = move |lamport: u64| {
+        let mut offsets = OffsetMap::empty();
+        offsets.update(stream, Offset::from(lamport as u32));
+        Snapshot {
+            key: EventKey {
+                lamport: LamportTimestamp::from(lamport),
+                stream,
+                offset: Offset::from(lamport as u32),
+            },
+            offsets,
+            state: LaunchpadTwinState {
+                id: "pad-1".to_string(),
+                current_mission: Some(format!("m{}", lamport)),
+                ..Default::default()
+            },
+        }
+    };
+
+    // every launchpad runs an executer of the twin
+    let executers: Vec<_> = (0..8)
+        .map(|_| {
+            let store = store.clone();
+            let twin = twin.clone();
+            std::thread::spawn(move || {
+                for lamport in 1..=20 {
+                    store.save(&twin, &snapshot(lamport)).unwrap();
+                }
+            })
+        })
+        .collect();
+    for executer in executers {
+        executer.join().unwrap();
+    }
+    assert_eq!(store.load(&twin, None), Some(snapshot(20)));
+    assert_eq!(
+        store.load(&twin, Some(&snapshot(20).key)),
+        Some(snapshot(19))
+    );
+    let files = std::fs::read_dir(&dir).unwrap().count();
+    assert_eq!(files, 1);
+}
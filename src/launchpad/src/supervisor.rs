@@ -0,0 +1,214 @@
+use crate::{
+    config::LaunchpadConfig, controller::Controller, hardware::Hardware, snapshot::SnapshotStore,
+};
+use actyx_sdk::service::EventService;
+use std::{future::Future, thread::JoinHandle, time::Duration};
+use tokio::{select, sync::watch, task::LocalSet, time::sleep};
+
+/// time until a stopped or crashed launchpad is started again
+const RESTART_DELAY: Duration = Duration::from_secs(5);
+
+/// Runs one `Controller` per launchpad.
+///
+/// Every launchpad gets its own thread and runtime, so a busy launchpad doesn't delay the others.
+/// A controller that stops or panics is started again without disturbing the others.
+pub struct Supervisor<S: EventService> {
+    service: S,
+    launchpads: Vec<LaunchpadConfig>,
+    snapshots: Option<SnapshotStore>,
+}
+
+impl<S> Supervisor<S>
+where
+    S: EventService + Send + Sync + 'static,
+{
+    pub fn new(service: S, launchpads: Vec<LaunchpadConfig>) -> Self {
+        Self {
+            service,
+            launchpads,
+            snapshots: None,
+        }
+    }
+
+    /// store shared by the twins of all launchpads
+    pub fn snapshots(mut self, store: Option<SnapshotStore>) -> Self {
+        self.snapshots = store;
+        self
+    }
+
+    /// runs the launchpads until ctrl-c, stopped launchpads are started again meanwhile
+    pub async fn run(self) -> Result<(), anyhow::Error> {
+        let launchpads = self.spawn()?;
+        tokio::signal::ctrl_c().await?;
+        println!("stopping launchpads");
+        tokio::task::spawn_blocking(move || {
+            for launchpad in launchpads {
+                if launchpad.stop().is_err() {
+                    println!("a launchpad thread panicked");
+                }
+            }
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// starts the launchpads without waiting for them
+    pub fn spawn(self) -> Result<Vec<Supervised>, anyhow::Error> {
+        let Self {
+            service,
+            launchpads,
+            snapshots,
+        } = self;
+        let mut handles = vec![];
+        for launchpad in launchpads {
+            let service = service.clone();
+            let snapshots = snapshots.clone();
+            let name = launchpad.name.clone();
+            handles.push(supervise(name, RESTART_DELAY, move || {
+                let name = launchpad.name.clone();
+                let hardware =
+                    Hardware::new(name.clone(), service.clone(), launchpad.hardware.clone());
+                let mut controller = Controller::with_hardware(name, service.clone(), hardware)
+                    .on_abort(launchpad.on_abort)
+                    .battery_policy(launchpad.battery)
+                    .snapshots(snapshots.clone());
+                async move { controller.start().await }
+            })?);
+        }
+        Ok(handles)
+    }
+}
+
+/// A launchpad thread started by `supervise`.
+pub struct Supervised {
+    stop: watch::Sender<bool>,
+    thread: JoinHandle<()>,
+}
+
+impl Supervised {
+    /// drops the running launchpad without restarting it and waits for its thread
+    pub fn stop(self) -> std::thread::Result<()> {
+        // the thread is gone already if nobody listens
+        let _ = self.stop.send(true);
+        self.thread.join()
+    }
+}
+
+/// Runs `run` on its own thread and starts it again `restart_delay` after it stopped or panicked.
+pub fn supervise<F, Fut>(
+    name: String,
+    restart_delay: Duration,
+    run: F,
+) -> Result<Supervised, anyhow::Error>
+where
+    F: Fn() -> Fut + Send + 'static,
+    Fut: Future<Output = Result<(), anyhow::Error>> + 'static,
+{
+    let (stop, mut stopped) = watch::channel(false);
+    let thread = std::thread::Builder::new()
+        .name(name.clone())
+        .spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .expect("supervisor: failed to create runtime");
+            LocalSet::new().block_on(&runtime, async move {
+                while !*stopped.borrow() {
+                    println!("{}: start launchpad", name);
+                    // a panic only takes down the spawned task, not the thread
+                    let mut task = tokio::task::spawn_local(run());
+                    select! {
+                        res = &mut task => match res {
+                            Ok(Ok(())) => println!("{}: launchpad stopped", name),
+                            Ok(Err(e)) => println!("{}: launchpad failed {:?}", name, e),
+                            Err(e) => println!("{}: launchpad crashed {}", name, e),
+                        },
+                        _ = stopped.changed() => {
+                            task.abort();
+                            break;
+                        }
+                    }
+                    select! {
+                        _ = sleep(restart_delay) => (),
+                        _ = stopped.changed() => break,
+                    }
+                }
+                println!("{}: launchpad stopped for good", name);
+            })
+        })?;
+    Ok(Supervised { stop, thread })
+}
+
+#[tokio::test]
+async fn a_crashing_launchpad_does_not_stop_the_others() {
+    use crate::controller::AbortAction;
+    use crate::memory_service::MemoryEventService;
+    use crate::twins::launchpad_twin::events::LaunchPadEvent;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    let service = MemoryEventService::new();
+    let starts = Arc::new(AtomicUsize::new(0));
+    let counter = starts.clone();
+    let crashing = supervise(
+        "pad-crash".to_string(),
+        Duration::from_millis(10),
+        move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            async { panic!("launchpad crashed") }
+        },
+    )
+    .unwrap();
+
+    let launchpads = ["pad-1", "pad-2"]
+        .iter()
+        .map(|name| LaunchpadConfig {
+            name: name.to_string(),
+            on_abort: AbortAction::Return,
+            battery: Default::default(),
+            hardware: Default::default(),
+        })
+        .collect();
+    let launchpads = Supervisor::new(service.clone(), launchpads)
+        .spawn()
+        .unwrap();
+
+    let registered = || {
+        let mut ids: Vec<String> = service
+            .events()
+            .into_iter()
+            .filter_map(|e| match e.payload.extract::<LaunchPadEvent>() {
+                Ok(LaunchPadEvent::LaunchPadRegistered(e)) => Some(e.id),
+                _ => None,
+            })
+            .collect();
+        ids.sort();
+        ids
+    };
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while starts.load(Ordering::SeqCst) < 3 || registered().len() < 2 {
+            sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .expect("launchpads didn't start");
+    assert_eq!(registered(), ["pad-1", "pad-2"]);
+
+    // stopping joins the threads, a crashing launchpad isn't started again
+    let stop = tokio::task::spawn_blocking(move || {
+        crashing.stop().unwrap();
+        let started = starts.load(Ordering::SeqCst);
+        for launchpad in launchpads {
+            launchpad.stop().unwrap();
+        }
+        std::thread::sleep(Duration::from_millis(50));
+        started == starts.load(Ordering::SeqCst)
+    });
+    let not_restarted = tokio::time::timeout(Duration::from_secs(5), stop)
+        .await
+        .expect("launchpads didn't stop")
+        .unwrap();
+    assert!(not_restarted);
+}
@@ -1,4 +1,4 @@
-#![allow(dead_code)]
+use crate::snapshot::{Snapshot, SnapshotStore};
 use actyx_sdk::{
     language::Query,
     service::{
@@ -8,128 +8,424 @@ use actyx_sdk::{
     Event, EventKey, Metadata, OffsetMap, Payload, Tag, TagSet,
 };
 use futures::StreamExt;
+use serde::{de::DeserializeOwned, Serialize};
 use std::{
     fmt::Debug,
     str::FromStr,
-    thread::sleep,
     time::{Duration, Instant},
 };
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, watch};
 use tokio_stream::{wrappers::ReceiverStream, Stream};
 use tokio_stream_ext::{combine_latest, switch_map, StreamOpsExt};
 
 pub trait Twin: Clone + Send + Sync {
-    type State: Debug + Default + Clone + Send + Sized + Sync + Unpin + PartialEq + 'static;
+    type State: Debug
+        + Default
+        + Clone
+        + Send
+        + Sized
+        + Sync
+        + Unpin
+        + PartialEq
+        + Serialize
+        + DeserializeOwned
+        + 'static;
+    /// events of the twin, usually the enum of its `events` module
+    type Event: DeserializeOwned + Clone;
 
     fn name(&self) -> String;
     fn id(&self) -> String;
     fn query(&self) -> Query;
 
-    fn reducer(state: Self::State, event: Event<Payload>) -> Self::State;
+    fn reducer(state: Self::State, event: Event<Self::Event>) -> Self::State;
+
+    /// An event of the query isn't a `Self::Event`, e.g. an unknown `eventType` of a newer app or
+    /// a broken payload. The state stays as it is.
+    fn on_invalid_event(
+        state: Self::State,
+        event: &Event<Payload>,
+        error: anyhow::Error,
+    ) -> Self::State {
+        println!(
+            "{}: can't read event {:?} {}: {:?}",
+            std::any::type_name::<Self>(),
+            event.key,
+            event.payload.json_string(),
+            error
+        );
+        state
+    }
+
+    /// Why `event` can't happen in `state`, `None` if it can. The reducer leaves the state as it
+    /// is for a rejected event, `execute_twin` reports it.
+    fn rejection(_state: &Self::State, _event: &Event<Self::Event>) -> Option<String> {
+        None
+    }
+
+    /// Version of the snapshot format, `None` disables snapshots. Bump it when the state or the
+    /// reducer changes, snapshots of other versions are ignored.
+    fn snapshot_version(&self) -> Option<u32> {
+        None
+    }
 }
 
-pub fn execute_twin<S, T>(event_service: S, twin: T) -> TwinExecuter<T::State>
+/// Connection of a twin to the event service
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConnectionStatus {
+    /// the subscription delivers events
+    Connected,
+    /// not connected (yet), the twin keeps trying
+    Reconnecting,
+    /// the twin gave up, its stream ends
+    Failed,
+}
+
+/// an event the twin rejected, see `Twin::rejection`
+#[derive(Clone, Debug, PartialEq)]
+pub struct RejectedEvent {
+    pub key: EventKey,
+    pub reason: String,
+}
+
+/// rejected events a twin keeps
+const KEPT_REJECTED_EVENTS: usize = 20;
+
+/// delay before the first reconnect, doubled with every failed attempt
+const RECONNECT_DELAY: Duration = Duration::from_millis(100);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+/// failed attempts in a row before the twin gives up
+const MAX_RECONNECTS: u32 = 10;
+
+pub fn execute_twin<S, T>(
+    event_service: S,
+    twin: T,
+    snapshots: Option<SnapshotStore>,
+) -> TwinExecuter<T::State>
 where
     S: EventService + Sync + 'static,
     T: Twin + Sync + 'static,
 {
     let (tx, rx) = mpsc::channel::<T::State>(100);
+    let (status_tx, status_rx) = watch::channel(ConnectionStatus::Reconnecting);
+    let (travels_tx, travels_rx) = watch::channel(0);
+    let (rejected_tx, rejected_rx) = watch::channel(Vec::new());
     tokio::spawn(async move {
+        // every subscription gets its own session
+        let mut session = 0;
+        // start of the last time travel, the rebuild has to start before it
+        let mut time_travel: Option<EventKey> = None;
+        // state of the lost subscription, it continues after the reconnect
+        let mut resume: Option<(T::State, OffsetMap, Option<EventKey>)> = None;
+        let mut failed_attempts = 0;
+        // last event checked for a rejection, a replay doesn't report the same events again
+        let mut checked: Option<EventKey> = None;
         'subscription: loop {
+            let resumed = resume.is_some();
+            // resume from the last snapshot, or replay everything
+            let (mut state, mut offsets, mut last_key) = resume.take().unwrap_or_else(|| {
+                let snapshot = snapshots
+                    .as_ref()
+                    .and_then(|s| s.load(&twin, time_travel.as_ref()));
+                match snapshot {
+                    Some(s) => (s.state, s.offsets, Some(s.key)),
+                    None => (Default::default(), OffsetMap::empty(), None),
+                }
+            });
+            let mut reduced = 0;
+            session += 1;
+
             let launchpad_subscription = event_service
                 .subscribe_monotonic(SubscribeMonotonicRequest {
-                    session: SessionId::from(format!("{}:{}", twin.name(), twin.id())),
-                    from: StartFrom::LowerBound(OffsetMap::empty()),
+                    session: SessionId::from(format!("{}:{}:{}", twin.name(), twin.id(), session)),
+                    from: StartFrom::LowerBound(offsets.clone()),
                     query: twin.query(),
                 })
                 .await;
 
-            let mut state: T::State = Default::default();
-            let _ = tx.send(state.clone()).await;
-            if let Ok(mut stream) = launchpad_subscription {
-                'eventLoop: loop {
-                    match stream.next().await {
-                        Some(SubscribeMonotonicResponse::Event { event, .. }) => {
-                            let key = EventKey {
-                                lamport: event.lamport,
-                                stream: event.stream,
-                                offset: event.offset,
-                            };
-                            let meta = Metadata {
-                                timestamp: event.timestamp,
-                                tags: event.tags,
-                                app_id: event.app_id,
-                            };
-                            let payload = event.payload;
-                            let event = Event::<Payload> { key, meta, payload };
-                            state = T::reducer(state, event);
-                            let _ = tx.send(state.clone()).await;
+            // after a time travel, the consumers keep the last state until the rebuild caught up
+            let mut publish = time_travel.is_none();
+            if publish && !resumed {
+                let _ = tx.send(state.clone()).await;
+            }
+            let mut stream = match launchpad_subscription {
+                Ok(stream) => stream,
+                Err(e) => {
+                    println!("{}:{} failed to subscribe {:?}", twin.name(), twin.id(), e);
+                    resume = Some((state, offsets, last_key));
+                    failed_attempts += 1;
+                    if reconnect_delay(&status_tx, failed_attempts).await {
+                        continue 'subscription;
+                    }
+                    break 'subscription;
+                }
+            };
+            'eventLoop: loop {
+                let response = stream.next().await;
+                if response.is_some() {
+                    failed_attempts = 0;
+                    set_status(&status_tx, ConnectionStatus::Connected);
+                }
+                match response {
+                    Some(SubscribeMonotonicResponse::Event { event, caught_up }) => {
+                        let key = EventKey {
+                            lamport: event.lamport,
+                            stream: event.stream,
+                            offset: event.offset,
+                        };
+                        if last_key.is_some_and(|last| key < last) {
+                            // the snapshot misses this event, it has to be reduced earlier
+                            time_travel = Some(key);
+                            time_traveled(&snapshots, &twin, &key, &travels_tx);
+                            break 'eventLoop;
                         }
-                        Some(SubscribeMonotonicResponse::Offsets(..)) => {
-                            let _ = tx.send(state.clone()).await;
+                        offsets.update(event.stream, event.offset);
+                        last_key = Some(key);
+                        let meta = Metadata {
+                            timestamp: event.timestamp,
+                            tags: event.tags,
+                            app_id: event.app_id,
+                        };
+                        let payload = event.payload;
+                        let event = Event::<Payload> { key, meta, payload };
+                        if checked.is_none_or(|checked| key > checked) {
+                            checked = Some(key);
+                            report_rejection(&twin, &rejected_tx, &state, &event);
                         }
-                        Some(SubscribeMonotonicResponse::TimeTravel { .. }) => {
-                            break 'eventLoop;
+                        state = reduce::<T>(state, event);
+                        reduced += 1;
+                        if caught_up {
+                            publish = true;
+                            time_travel = None;
+                            save_snapshot(
+                                &snapshots,
+                                &twin,
+                                &mut reduced,
+                                last_key,
+                                &offsets,
+                                &state,
+                            );
                         }
-                        None => {
-                            println!("stream terminated?");
-                            sleep(Duration::from_millis(100));
-                            drop(tx);
-                            break 'subscription;
+                        if publish {
+                            let _ = tx.send(state.clone()).await;
                         }
-                        Some(other) => {
-                            println!("event {:?} ", other);
+                    }
+                    Some(SubscribeMonotonicResponse::Offsets(..)) => {
+                        publish = true;
+                        time_travel = None;
+                        save_snapshot(&snapshots, &twin, &mut reduced, last_key, &offsets, &state);
+                        let _ = tx.send(state.clone()).await;
+                    }
+                    Some(SubscribeMonotonicResponse::TimeTravel { new_start }) => {
+                        time_travel = Some(new_start);
+                        time_traveled(&snapshots, &twin, &new_start, &travels_tx);
+                        break 'eventLoop;
+                    }
+                    None => {
+                        println!("{}:{} subscription ended", twin.name(), twin.id());
+                        resume = Some((state, offsets, last_key));
+                        failed_attempts += 1;
+                        if reconnect_delay(&status_tx, failed_attempts).await {
+                            continue 'subscription;
                         }
+                        break 'subscription;
+                    }
+                    Some(other) => {
+                        println!("event {:?} ", other);
                     }
                 }
             }
         }
+        // dropping `tx` ends the stream of the twin
     });
-    TwinExecuter::new(rx, 100)
+    TwinExecuter::new(rx, status_rx, travels_rx, rejected_rx, 100)
 }
 
-pub async fn current_state<S, T>(event_service: S, twin: T) -> Box<Result<T::State, anyhow::Error>>
+/// Waits before the next subscription with an exponential backoff. `false` if the twin gives up
+/// after `attempt`.
+async fn reconnect_delay(status: &watch::Sender<ConnectionStatus>, attempt: u32) -> bool {
+    if attempt > MAX_RECONNECTS {
+        set_status(status, ConnectionStatus::Failed);
+        return false;
+    }
+    set_status(status, ConnectionStatus::Reconnecting);
+    let delay = RECONNECT_DELAY
+        .checked_mul(1 << (attempt - 1).min(16))
+        .unwrap_or(MAX_RECONNECT_DELAY)
+        .min(MAX_RECONNECT_DELAY);
+    tokio::time::sleep(delay).await;
+    true
+}
+
+fn set_status(status: &watch::Sender<ConnectionStatus>, new_status: ConnectionStatus) {
+    if *status.borrow() != new_status {
+        // nobody might watch the status
+        let _ = status.send(new_status);
+    }
+}
+
+/// publishes `event` if the twin rejects it in `state`, the oldest rejected events are dropped
+fn report_rejection<T: Twin>(
+    twin: &T,
+    rejected: &watch::Sender<Vec<RejectedEvent>>,
+    state: &T::State,
+    event: &Event<Payload>,
+) {
+    let reason = match event.extract::<T::Event>() {
+        Ok(typed) => T::rejection(state, &typed),
+        Err(_) => None,
+    };
+    if let Some(reason) = reason {
+        println!("{}:{} rejected {}", twin.name(), twin.id(), reason);
+        let mut events = rejected.borrow().clone();
+        events.push(RejectedEvent {
+            key: event.key,
+            reason,
+        });
+        let dropped = events.len().saturating_sub(KEPT_REJECTED_EVENTS);
+        events.drain(..dropped);
+        // nobody might watch the rejected events
+        let _ = rejected.send(events);
+    }
+}
+
+/// Reduces `event` with the reducer of the twin, or its `on_invalid_event` if it can't be read.
+pub fn reduce<T: Twin>(state: T::State, event: Event<Payload>) -> T::State {
+    match event.extract::<T::Event>() {
+        Ok(typed) => T::reducer(state, typed),
+        Err(e) => T::on_invalid_event(state, &event, e.into()),
+    }
+}
+
+/// An event before `new_start` arrived. Counts the time travel and drops the snapshots that miss
+/// the event.
+fn time_traveled<T: Twin>(
+    snapshots: &Option<SnapshotStore>,
+    twin: &T,
+    new_start: &EventKey,
+    travels: &watch::Sender<u64>,
+) {
+    println!(
+        "{}:{} time travel to {:?}",
+        twin.name(),
+        twin.id(),
+        new_start
+    );
+    let count = *travels.borrow() + 1;
+    // nobody might watch the time travels
+    let _ = travels.send(count);
+    if let Some(store) = snapshots {
+        if let Err(e) = store.invalidate(twin, new_start) {
+            println!(
+                "failed to drop snapshots of {}:{} {:?}",
+                twin.name(),
+                twin.id(),
+                e
+            );
+        }
+    }
+}
+
+/// stores the state once `reduced` reached the interval of the store
+fn save_snapshot<T: Twin>(
+    snapshots: &Option<SnapshotStore>,
+    twin: &T,
+    reduced: &mut usize,
+    key: Option<EventKey>,
+    offsets: &OffsetMap,
+    state: &T::State,
+) {
+    if let (Some(store), Some(key)) = (snapshots, key) {
+        if *reduced >= store.interval() {
+            let snapshot = Snapshot {
+                key,
+                offsets: offsets.clone(),
+                state: state.clone(),
+            };
+            if let Err(e) = store.save(twin, &snapshot) {
+                println!(
+                    "failed to store snapshot of {}:{} {:?}",
+                    twin.name(),
+                    twin.id(),
+                    e
+                );
+            }
+            *reduced = 0;
+        }
+    }
+}
+
+pub async fn current_state<S, T>(
+    event_service: S,
+    twin: T,
+    snapshots: Option<&SnapshotStore>,
+) -> Box<Result<T::State, anyhow::Error>>
 where
     T: Twin + Clone + Sync + 'static,
     S: EventService + Send + Sync + 'static,
 {
-    let event_stream = event_service
+    if let Some(snapshot) = snapshots.and_then(|s| s.load(&twin, None)) {
+        let offsets = Some(snapshot.offsets);
+        match reduce_query(
+            &event_service,
+            &twin,
+            snapshot.state,
+            offsets,
+            Some(snapshot.key),
+        )
+        .await
+        {
+            Ok(Some(state)) => return Box::new(Ok(state)),
+            // an event older than the snapshot arrived in the meantime, replay everything
+            Ok(None) => (),
+            Err(e) => return Box::new(Err(e)),
+        }
+    }
+    let state = reduce_query(&event_service, &twin, Default::default(), None, None).await;
+    Box::new(state.map(Option::unwrap_or_default))
+}
+
+/// Reduces the events after `lower_bound` onto `state`. `None` if an event is older than
+/// `last_key`, the key of the last event in `state`.
+async fn reduce_query<S, T>(
+    event_service: &S,
+    twin: &T,
+    mut state: T::State,
+    lower_bound: Option<OffsetMap>,
+    last_key: Option<EventKey>,
+) -> Result<Option<T::State>, anyhow::Error>
+where
+    T: Twin,
+    S: EventService,
+{
+    let mut stream = event_service
         .query(QueryRequest {
-            lower_bound: None,
+            lower_bound,
             upper_bound: None,
             query: twin.query(),
             order: Order::Asc,
         })
-        .await;
-
-    let mut state: T::State = Default::default();
-
-    match event_stream {
-        Ok(mut stream) => 'eventLoop: loop {
-            match stream.next().await {
-                Some(QueryResponse::Event(event)) => {
-                    let key = EventKey {
-                        lamport: event.lamport,
-                        stream: event.stream,
-                        offset: event.offset,
-                    };
-                    let meta = Metadata {
-                        timestamp: event.timestamp,
-                        tags: event.tags,
-                        app_id: event.app_id,
-                    };
-                    let payload = event.payload;
-                    let event = Event::<Payload> { key, meta, payload };
-                    state = T::reducer(state, event);
-                }
-                None => {
-                    break 'eventLoop Box::new(Ok(state.clone()));
-                }
-                _ => {}
+        .await?;
+    while let Some(response) = stream.next().await {
+        if let QueryResponse::Event(event) = response {
+            let key = EventKey {
+                lamport: event.lamport,
+                stream: event.stream,
+                offset: event.offset,
+            };
+            if last_key.is_some_and(|last| key < last) {
+                return Ok(None);
             }
-        },
-        Err(e) => Box::new(Err(e)),
+            let meta = Metadata {
+                timestamp: event.timestamp,
+                tags: event.tags,
+                app_id: event.app_id,
+            };
+            let payload = event.payload;
+            let event = Event::<Payload> { key, meta, payload };
+            state = reduce::<T>(state, event);
+        }
     }
+    Ok(Some(state))
 }
 
 #[derive(Debug)]
@@ -137,25 +433,60 @@ pub struct TwinExecuter<S>
 where
     S: Clone + Send + Sync,
 {
+    #[allow(dead_code)]
     last_interaction: Instant,
     input: mpsc::Receiver<S>,
+    #[allow(dead_code)]
     last_state: Option<S>,
+    #[allow(dead_code)]
     debounce_time_ms: u64,
+    status: watch::Receiver<ConnectionStatus>,
+    time_travels: watch::Receiver<u64>,
+    rejected: watch::Receiver<Vec<RejectedEvent>>,
 }
 
 impl<S> TwinExecuter<S>
 where
     S: Debug + Clone + Send + Sync + Unpin + PartialEq,
 {
-    pub fn new(input: mpsc::Receiver<S>, debounce_time_ms: u64) -> Self {
+    pub fn new(
+        input: mpsc::Receiver<S>,
+        status: watch::Receiver<ConnectionStatus>,
+        time_travels: watch::Receiver<u64>,
+        rejected: watch::Receiver<Vec<RejectedEvent>>,
+        debounce_time_ms: u64,
+    ) -> Self {
         Self {
             last_interaction: Instant::now(),
             input,
             debounce_time_ms,
             last_state: None,
+            status,
+            time_travels,
+            rejected,
         }
     }
 
+    /// connection status of the twin, take it before the state is turned into a stream
+    pub fn status(&self) -> watch::Receiver<ConnectionStatus> {
+        self.status.clone()
+    }
+
+    #[allow(dead_code)]
+    /// Time travels of the twin since it was started. Each one costs a rebuild of the state, a
+    /// rising count points to nodes that are often disconnected.
+    pub fn time_travels(&self) -> watch::Receiver<u64> {
+        self.time_travels.clone()
+    }
+
+    #[allow(dead_code)]
+    /// The last events the twin rejected, oldest first. A rising number points to a launchpad or
+    /// UI that is out of sync with the twin.
+    pub fn rejected_events(&self) -> watch::Receiver<Vec<RejectedEvent>> {
+        self.rejected.clone()
+    }
+
+    #[allow(clippy::wrong_self_convention)]
     pub fn as_stream(self) -> impl Stream<Item = S> + Unpin {
         Box::pin(
             ReceiverStream::new(self.input)
@@ -165,9 +496,10 @@ where
     }
 }
 
+#[allow(dead_code)]
 pub fn spawn_observer<T, F>(mut stream: T, state_changed: F) -> Observation
 where
-    F: Fn(T::Item) -> () + Send + 'static,
+    F: Fn(T::Item) + Send + 'static,
     T: Stream + Unpin + Send + 'static,
 {
     let (command_sender, mut tx) = tokio::sync::mpsc::channel(1);
@@ -198,6 +530,7 @@ pub fn resolve_registry<Actyx, Registry, Entity>(
     service: Actyx,
     registry_twin: Registry,
     map_to_entity: fn(Registry::State) -> Vec<Entity>,
+    snapshots: Option<SnapshotStore>,
 ) -> impl Stream<Item = Vec<Entity::State>> + Unpin
 where
     Actyx: EventService + Clone + Sync + 'static,
@@ -205,15 +538,22 @@ where
     Entity: Twin + 'static,
 {
     switch_map(
-        execute_twin(service.clone(), registry_twin).as_stream(),
+        execute_twin(service.clone(), registry_twin, snapshots.clone()).as_stream(),
         move |state| {
+            let entities = (map_to_entity)(state);
+            // combining no streams ends right away, the empty registry is emitted instead
+            if entities.is_empty() {
+                return Some(futures::stream::iter(vec![Vec::new()]).right_stream());
+            }
             let l = combine_latest(
-                (map_to_entity)(state)
+                entities
                     .iter()
-                    .map(|entity| execute_twin(service.clone(), entity.clone()).as_stream())
+                    .map(|entity| {
+                        execute_twin(service.clone(), entity.clone(), snapshots.clone()).as_stream()
+                    })
                     .collect(),
             );
-            Some(l)
+            Some(l.left_stream())
         },
     )
 }
@@ -222,6 +562,7 @@ pub fn resolve_relation<Actyx, From, Entity>(
     service: Actyx,
     registry_twin: From,
     map_to_entity: fn(From::State) -> Option<Entity>,
+    snapshots: Option<SnapshotStore>,
 ) -> impl Stream<Item = Entity::State> + Unpin
 where
     Actyx: EventService + Clone + Sync + 'static,
@@ -229,10 +570,10 @@ where
     Entity: Twin + 'static,
 {
     switch_map(
-        execute_twin(service.clone(), registry_twin).as_stream(),
+        execute_twin(service.clone(), registry_twin, snapshots.clone()).as_stream(),
         move |state| {
             let twin = (map_to_entity)(state);
-            twin.map(|e| execute_twin(service.clone(), e).as_stream())
+            twin.map(|e| execute_twin(service.clone(), e, snapshots.clone()).as_stream())
         },
     )
 }
@@ -242,9 +583,9 @@ pub struct Observation {
     handler: tokio::task::JoinHandle<()>,
 }
 
-impl Into<tokio::task::JoinHandle<()>> for Observation {
-    fn into(self) -> tokio::task::JoinHandle<()> {
-        self.handler
+impl From<Observation> for tokio::task::JoinHandle<()> {
+    fn from(val: Observation) -> Self {
+        val.handler
     }
 }
 
@@ -262,6 +603,7 @@ impl Observation {
         &self.handler
     }
 
+    #[allow(clippy::wrong_self_convention)]
     pub fn as_handler(self) -> tokio::task::JoinHandle<()> {
         self.handler
     }
@@ -291,3 +633,561 @@ where
         }],
     }
 }
+
+#[tokio::test]
+async fn current_state_and_execute_twin_reduce_the_same_events() {
+    use crate::memory_service::MemoryEventService;
+    use crate::twins::launchpad_twin::events::{
+        LaunchPadEvent, LaunchPadRegisteredEvent, MissionActivatedEvent,
+    };
+    use crate::twins::launchpad_twin::LaunchpadTwin;
+
+    let service = MemoryEventService::new();
+    let twin = LaunchpadTwin::new("pad-1".to_string());
+    LaunchPadEvent::emit_launch_pad_registered(
+        service.clone(),
+        LaunchPadRegisteredEvent {
+            id: "pad-1".to_string(),
+        },
+    )
+    .await
+    .unwrap();
+    LaunchPadEvent::emit_mission_activated(
+        service.clone(),
+        MissionActivatedEvent {
+            launchpad_id: "pad-1".to_string(),
+            mission_id: "m1".into(),
+        },
+    )
+    .await
+    .unwrap();
+    LaunchPadEvent::emit_mission_activated(
+        service.clone(),
+        MissionActivatedEvent {
+            launchpad_id: "pad-2".to_string(),
+            mission_id: "m2".into(),
+        },
+    )
+    .await
+    .unwrap();
+
+    let state = current_state(service.clone(), twin.clone(), None)
+        .await
+        .unwrap();
+    assert_eq!(state.id, "pad-1");
+    assert_eq!(state.current_mission, Some("m1".to_string()));
+
+    let mut stream = execute_twin(service.clone(), twin, None).as_stream();
+    let live = tokio::time::timeout(Duration::from_secs(1), stream.next())
+        .await
+        .unwrap();
+    assert_eq!(live, Some(state));
+
+    LaunchPadEvent::emit_mission_activated(
+        service.clone(),
+        MissionActivatedEvent {
+            launchpad_id: "pad-1".to_string(),
+            mission_id: "m3".into(),
+        },
+    )
+    .await
+    .unwrap();
+    let live = tokio::time::timeout(Duration::from_secs(1), stream.next())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(live.current_mission, Some("m3".to_string()));
+}
+
+#[tokio::test]
+async fn resolve_relation_follows_the_attached_drone() {
+    use crate::memory_service::MemoryEventService;
+    use crate::twins::{
+        drone_twin::{events as drone_ev, tag_drone_id, DroneTwin},
+        launchpad_twin::{events as launchpad_ev, tag_launchpad_id, LaunchpadTwin},
+    };
+
+    let service = MemoryEventService::new();
+    for id in &["d1", "d2"] {
+        let defined = drone_ev::DroneEvent::DroneDefined(drone_ev::DroneDefinedEvent {
+            id: id.to_string(),
+            ssid: format!("TELLO-{}", id),
+            ip: "127.0.0.1".to_string(),
+        });
+        service
+            .publish(mk_publish_request(tag_drone_id(id), &defined))
+            .await
+            .unwrap();
+    }
+    let mount = |drone: &str| {
+        mk_publish_request(
+            tag_launchpad_id(&"pad-1"),
+            &launchpad_ev::LaunchPadEvent::DroneMounted(launchpad_ev::DroneMountedEvent {
+                id: "pad-1".to_string(),
+                drone: drone.to_string(),
+            }),
+        )
+    };
+    service.publish(mount("d1")).await.unwrap();
+
+    let mut drone = resolve_relation(
+        service.clone(),
+        LaunchpadTwin::new("pad-1".to_string()),
+        |s| s.attached_drone.map(|id| DroneTwin { id }),
+        None,
+    );
+    let state = tokio::time::timeout(Duration::from_secs(1), drone.next()).await;
+    assert_eq!(state.unwrap().map(|s| s.id()), Some("d1".to_string()));
+
+    service.publish(mount("d2")).await.unwrap();
+    let state = tokio::time::timeout(Duration::from_secs(1), drone.next()).await;
+    assert_eq!(state.unwrap().map(|s| s.id()), Some("d2".to_string()));
+}
+
+#[tokio::test]
+async fn resolve_registry_follows_the_fleet() {
+    use crate::memory_service::MemoryEventService;
+    use crate::twins::drone_twin::{events as drone_ev, DroneRegistryTwin, DroneTwin};
+
+    let service = MemoryEventService::new();
+    for id in &["d1", "d2"] {
+        drone_ev::DroneEvent::emit_drone_defined(
+            service.clone(),
+            drone_ev::DroneDefinedEvent {
+                id: id.to_string(),
+                ssid: format!("TELLO-{}", id),
+                ip: "127.0.0.1".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+    }
+
+    let mut fleet = resolve_registry(
+        service.clone(),
+        DroneRegistryTwin,
+        |ids| ids.into_iter().map(|id| DroneTwin { id }).collect(),
+        None,
+    );
+    let ids = |drones: Vec<crate::twins::drone_twin::states::DroneTwinState>| {
+        let mut ids: Vec<String> = drones.iter().map(|d| d.id()).collect();
+        ids.sort();
+        ids
+    };
+    let drones = tokio::time::timeout(Duration::from_secs(1), fleet.next()).await;
+    assert_eq!(ids(drones.unwrap().unwrap()), ["d1", "d2"]);
+
+    for id in &["d1", "d2"] {
+        drone_ev::DroneEvent::emit_drone_removed(
+            service.clone(),
+            drone_ev::DroneRemovedEvent { id: id.to_string() },
+        )
+        .await
+        .unwrap();
+    }
+    // the fleet shrinks until it is empty
+    loop {
+        let drones = tokio::time::timeout(Duration::from_secs(1), fleet.next()).await;
+        if drones.unwrap().unwrap().is_empty() {
+            break;
+        }
+    }
+}
+
+#[tokio::test]
+async fn execute_twin_resumes_from_the_last_snapshot() {
+    use crate::memory_service::MemoryEventService;
+    use crate::twins::launchpad_twin::events::{
+        LaunchPadEvent, LaunchPadRegisteredEvent, MissionActivatedEvent,
+    };
+    use crate::twins::launchpad_twin::{
+        events as ev, tag_launchpad_id, LaunchpadTwin, LaunchpadTwinState,
+    };
+    use actyx_sdk::LamportTimestamp;
+
+    let dir = std::env::temp_dir().join("launchpad-execute-twin-snapshot-test");
+    let _ = std::fs::remove_dir_all(&dir);
+    let store = SnapshotStore::new(&dir).unwrap().every(1);
+    let service = MemoryEventService::new();
+    let twin = LaunchpadTwin::new("pad-1".to_string());
+    LaunchPadEvent::emit_launch_pad_registered(
+        service.clone(),
+        LaunchPadRegisteredEvent {
+            id: "pad-1".to_string(),
+        },
+    )
+    .await
+    .unwrap();
+    let activated = LaunchPadEvent::emit_mission_activated(
+        service.clone(),
+        MissionActivatedEvent {
+            launchpad_id: "pad-1".to_string(),
+            mission_id: "m1".to_string(),
+        },
+    )
+    .await
+    .unwrap();
+
+    // a snapshot the events can't produce, to see that they are not replayed
+    let key = &activated.data[0];
+    let snapshot_state = LaunchpadTwinState {
+        id: "pad-1".to_string(),
+        current_mission: Some("from-snapshot".to_string()),
+        ..Default::default()
+    };
+    let snapshot = Snapshot {
+        key: EventKey {
+            lamport: key.lamport,
+            stream: key.stream,
+            offset: key.offset,
+        },
+        offsets: service.offsets().await.unwrap().present,
+        state: snapshot_state.clone(),
+    };
+    store.save(&twin, &snapshot).unwrap();
+    let state = current_state(service.clone(), twin.clone(), Some(&store)).await;
+    assert_eq!(state.unwrap(), snapshot_state);
+
+    let mut stream = execute_twin(service.clone(), twin.clone(), Some(store.clone())).as_stream();
+    assert_eq!(
+        tokio::time::timeout(Duration::from_secs(1), stream.next())
+            .await
+            .unwrap(),
+        Some(snapshot_state)
+    );
+
+    LaunchPadEvent::emit_mission_activated(
+        service.clone(),
+        MissionActivatedEvent {
+            launchpad_id: "pad-1".to_string(),
+            mission_id: "m2".into(),
+        },
+    )
+    .await
+    .unwrap();
+    let state = tokio::time::timeout(Duration::from_secs(1), stream.next())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(state.current_mission, Some("m2".to_string()));
+    assert_eq!(store.load(&twin, None).unwrap().state, state);
+
+    // an event older than the snapshots invalidates them, the twin replays all events
+    let other_node = MemoryEventService::stream_id(1, 0);
+    service.replicate(
+        other_node,
+        LamportTimestamp::from(1),
+        tag_launchpad_id(&"pad-1"),
+        Payload::compact(&ev::LaunchPadEvent::MissionActivated(
+            ev::MissionActivatedEvent {
+                launchpad_id: "pad-1".to_string(),
+                mission_id: "m0".to_string(),
+            },
+        ))
+        .unwrap(),
+    );
+    tokio::time::timeout(Duration::from_secs(2), async {
+        while store
+            .load(&twin, None)
+            .is_none_or(|s| s.offsets.get(other_node).is_none())
+        {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .expect("no snapshot after the replay");
+    let replayed = store.load(&twin, None).unwrap().state;
+    assert_eq!(replayed.current_mission, Some("m2".to_string()));
+    let state = current_state(service.clone(), twin, Some(&store)).await;
+    assert_eq!(state.unwrap(), replayed);
+}
+
+#[tokio::test]
+async fn time_travel_rebuilds_from_an_older_snapshot() {
+    use crate::memory_service::MemoryEventService;
+    use crate::twins::launchpad_twin::events::{
+        LaunchPadEvent, LaunchPadRegisteredEvent, MissionActivatedEvent,
+    };
+    use crate::twins::launchpad_twin::{events as ev, tag_launchpad_id, LaunchpadTwin};
+
+    let dir = std::env::temp_dir().join("launchpad-time-travel-test");
+    let _ = std::fs::remove_dir_all(&dir);
+    let store = SnapshotStore::new(&dir).unwrap().every(1);
+    let service = MemoryEventService::new();
+    let twin = LaunchpadTwin::new("pad-tt".to_string());
+    LaunchPadEvent::emit_launch_pad_registered(
+        service.clone(),
+        LaunchPadRegisteredEvent {
+            id: "pad-tt".to_string(),
+        },
+    )
+    .await
+    .unwrap();
+    LaunchPadEvent::emit_mission_activated(
+        service.clone(),
+        MissionActivatedEvent {
+            launchpad_id: "pad-tt".to_string(),
+            mission_id: "m1".into(),
+        },
+    )
+    .await
+    .unwrap();
+
+    let executer = execute_twin(service.clone(), twin.clone(), Some(store.clone()));
+    let time_travels = executer.time_travels();
+    let mut stream = executer.as_stream();
+    let state = tokio::time::timeout(Duration::from_secs(1), stream.next())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(state.current_mission, Some("m1".to_string()));
+    assert_eq!(*time_travels.borrow(), 0);
+
+    // mark the snapshot before the time travel, a full replay would lose the mark
+    let mut snapshot = store.load(&twin, None).unwrap();
+    snapshot.state.mission_queue = vec!["from-snapshot".to_string()];
+    store.save(&twin, &snapshot).unwrap();
+    LaunchPadEvent::emit_mission_activated(
+        service.clone(),
+        MissionActivatedEvent {
+            launchpad_id: "pad-tt".to_string(),
+            mission_id: "m2".into(),
+        },
+    )
+    .await
+    .unwrap();
+    let state = tokio::time::timeout(Duration::from_secs(1), stream.next())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(state.current_mission, Some("m2".to_string()));
+
+    // sorts between the activation of m1 and m2
+    service.replicate(
+        MemoryEventService::stream_id(1, 0),
+        snapshot.key.lamport,
+        tag_launchpad_id(&"pad-tt"),
+        Payload::compact(&ev::LaunchPadEvent::MissionQueued(ev::MissionQueuedEvent {
+            launchpad_id: "pad-tt".to_string(),
+            mission_id: "m9".to_string(),
+            schedule: None,
+        }))
+        .unwrap(),
+    );
+    let state = tokio::time::timeout(Duration::from_secs(2), stream.next())
+        .await
+        .expect("no state after the time travel")
+        .unwrap();
+    // the default state is not published while the state is rebuilt
+    assert_eq!(state.id, "pad-tt");
+    assert_eq!(state.current_mission, Some("m2".to_string()));
+    assert_eq!(state.mission_queue, ["from-snapshot", "m9"]);
+    assert_eq!(*time_travels.borrow(), 1);
+}
+
+#[tokio::test(start_paused = true)]
+async fn execute_twin_reconnects_after_the_subscription_ended() {
+    use crate::memory_service::MemoryEventService;
+    use crate::twins::launchpad_twin::events::{
+        LaunchPadEvent, LaunchPadRegisteredEvent, MissionActivatedEvent,
+    };
+    use crate::twins::launchpad_twin::{events as ev, tag_launchpad_id, LaunchpadTwin};
+
+    async fn wait_for(status: &mut watch::Receiver<ConnectionStatus>, expected: ConnectionStatus) {
+        while *status.borrow() != expected {
+            status.changed().await.unwrap();
+        }
+    }
+
+    let service = MemoryEventService::new();
+    let twin = LaunchpadTwin::new("pad-rc".to_string());
+    LaunchPadEvent::emit_launch_pad_registered(
+        service.clone(),
+        LaunchPadRegisteredEvent {
+            id: "pad-rc".to_string(),
+        },
+    )
+    .await
+    .unwrap();
+    LaunchPadEvent::emit_mission_activated(
+        service.clone(),
+        MissionActivatedEvent {
+            launchpad_id: "pad-rc".to_string(),
+            mission_id: "m1".into(),
+        },
+    )
+    .await
+    .unwrap();
+
+    let executer = execute_twin(service.clone(), twin, None);
+    let mut status = executer.status();
+    let mut stream = executer.as_stream();
+    let state = tokio::time::timeout(Duration::from_secs(1), stream.next())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(state.current_mission, Some("m1".to_string()));
+    assert_eq!(*status.borrow(), ConnectionStatus::Connected);
+
+    service.set_offline(true);
+    wait_for(&mut status, ConnectionStatus::Reconnecting).await;
+
+    // another node activates a mission while this one is offline
+    service.replicate(
+        MemoryEventService::stream_id(1, 0),
+        service.events().last().unwrap().lamport.incr(),
+        tag_launchpad_id(&"pad-rc"),
+        Payload::compact(&ev::LaunchPadEvent::MissionActivated(
+            ev::MissionActivatedEvent {
+                launchpad_id: "pad-rc".to_string(),
+                mission_id: "m2".to_string(),
+            },
+        ))
+        .unwrap(),
+    );
+    tokio::time::sleep(Duration::from_secs(2)).await;
+    service.set_offline(false);
+    wait_for(&mut status, ConnectionStatus::Connected).await;
+    // the twin continues with its state, only the missed event is reduced
+    let state = tokio::time::timeout(Duration::from_secs(1), stream.next())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(state.id, "pad-rc");
+    assert_eq!(state.current_mission, Some("m2".to_string()));
+
+    // the twin gives up after a few minutes
+    service.set_offline(true);
+    wait_for(&mut status, ConnectionStatus::Reconnecting).await;
+    wait_for(&mut status, ConnectionStatus::Failed).await;
+    assert_eq!(stream.next().await, None);
+}
+
+#[tokio::test]
+async fn rejected_events_are_reported_once() {
+    use crate::memory_service::MemoryEventService;
+    use crate::twins::drone_twin::{events::*, tag_drone_id, DroneTwin};
+
+    let service = MemoryEventService::new();
+    // an undefined drone neither gets ready nor lands
+    DroneEvent::emit_drone_ready(service.clone(), DroneReadyEvent { id: "d9".into() })
+        .await
+        .unwrap();
+    DroneEvent::emit_drone_landed(
+        service.clone(),
+        DroneLandedEvent {
+            id: "d9".into(),
+            at: Position {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+            },
+        },
+    )
+    .await
+    .unwrap();
+
+    let executer = execute_twin(service.clone(), DroneTwin { id: "d9".into() }, None);
+    let rejected = executer.rejected_events();
+    let time_travels = executer.time_travels();
+    let mut stream = executer.as_stream();
+    tokio::time::timeout(Duration::from_secs(1), stream.next())
+        .await
+        .unwrap()
+        .unwrap();
+    let keys: Vec<EventKey> = service
+        .events()
+        .iter()
+        .map(|e| EventKey {
+            lamport: e.lamport,
+            stream: e.stream,
+            offset: e.offset,
+        })
+        .collect();
+    let reported = |rejected: &watch::Receiver<Vec<RejectedEvent>>| -> Vec<EventKey> {
+        rejected.borrow().iter().map(|r| r.key).collect()
+    };
+    assert_eq!(reported(&rejected), keys);
+    assert!(rejected.borrow()[1].reason.contains("state undefined"));
+
+    // sorts between both events, the replay after the time travel rejects the landing again
+    service.replicate(
+        MemoryEventService::stream_id(1, 0),
+        keys[0].lamport,
+        tag_drone_id(&"d9"),
+        Payload::compact(&DroneEvent::DroneDefined(DroneDefinedEvent {
+            id: "d9".into(),
+            ssid: "TELLO-D9".into(),
+            ip: "127.0.0.1".into(),
+        }))
+        .unwrap(),
+    );
+    let state = tokio::time::timeout(Duration::from_secs(2), stream.next())
+        .await
+        .expect("no state after the time travel")
+        .unwrap();
+    assert_eq!(state.name(), "ready");
+    assert_eq!(*time_travels.borrow(), 1);
+    assert_eq!(reported(&rejected), keys);
+}
+
+#[tokio::test]
+async fn events_the_twin_cant_read_go_to_on_invalid_event() {
+    use crate::memory_service::MemoryEventService;
+    use crate::twins::mission_twin::events::{DefineMissionEvent, MissionEvent};
+
+    #[derive(Clone)]
+    struct Missions;
+    impl Twin for Missions {
+        type State = Vec<String>;
+        type Event = MissionEvent;
+        fn name(&self) -> String {
+            "missions".to_string()
+        }
+        fn id(&self) -> String {
+            "all".to_string()
+        }
+        fn query(&self) -> Query {
+            "FROM 'mission'".parse().unwrap()
+        }
+        fn reducer(mut state: Self::State, event: Event<MissionEvent>) -> Self::State {
+            if let MissionEvent::DefineMission(e) = event.payload {
+                state.push(e.id);
+            }
+            state
+        }
+        fn on_invalid_event(
+            mut state: Self::State,
+            _: &Event<Payload>,
+            error: anyhow::Error,
+        ) -> Self::State {
+            state.push(format!("invalid: {}", error));
+            state
+        }
+    }
+
+    let service = MemoryEventService::new();
+    let define = DefineMissionEvent {
+        id: "m1".to_string(),
+        name: "square".to_string(),
+        waypoints: vec![],
+        version: None,
+    };
+    MissionEvent::emit_define_mission(service.clone(), define)
+        .await
+        .unwrap();
+    let unknown = serde_json::json!({ "eventType": "launchMission", "id": "m1" });
+    let broken = serde_json::json!({ "eventType": "defineMission", "id": 1 });
+    for payload in &[unknown, broken] {
+        service
+            .publish(mk_publish_request(tag_with_id("mission", &"m1"), payload))
+            .await
+            .unwrap();
+    }
+
+    let state = current_state(service, Missions, None).await.unwrap();
+    assert_eq!(state.len(), 3);
+    assert_eq!(state[0], "m1");
+    assert!(state[1].starts_with("invalid: unknown variant `launchMission`"));
+    assert!(state[2].starts_with("invalid: "));
+}
@@ -1,3 +1,4 @@
+use event_emitter::EventEmitter;
 use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
 
@@ -14,14 +15,21 @@ pub struct DroneDefinedEvent {
     pub ssid: String,
     pub ip: String,
 }
+/// the drone leaves the fleet, it is undefined until it is defined again
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DroneRemovedEvent {
+    pub id: String,
+}
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DroneReadyEvent {
     pub id: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct DroneActivatedEvent {
     pub id: String,
+    #[serde(alias = "launchpad_id")]
     pub launchpad_id: String,
 }
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -34,26 +42,98 @@ pub struct DroneStatsUpdatedEvent {
     pub battery: u8,
 }
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct DroneLaunchedEvent {
     pub id: String,
+    #[serde(alias = "mission_id")]
     pub mission_id: String,
+    /// version of the mission the drone flies, missing in events of older launchpads
+    #[serde(default)]
+    pub mission_version: Option<u32>,
 }
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct DroneStartedToNextWaypointEvent {
     pub id: String,
+    #[serde(alias = "mission_id")]
     pub mission_id: String,
+    #[serde(alias = "waypoint_id")]
     pub waypoint_id: i32,
 }
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct DroneArrivedAtWaypointEvent {
     pub id: String,
+    #[serde(alias = "mission_id")]
     pub mission_id: String,
+    #[serde(alias = "waypoint_id")]
     pub waypoint_id: i32,
 }
+/// how a mission ended, missing in events of launchpads without abort support
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MissionOutcome {
+    #[default]
+    Completed,
+    Aborted,
+    /// the drone didn't take off
+    Refused,
+}
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct DroneMissionCompletedEvent {
     pub id: String,
+    #[serde(alias = "mission_id")]
+    pub mission_id: String,
+    #[serde(default)]
+    pub outcome: MissionOutcome,
+}
+/// an operator stops the mission, the drone returns or lands in place
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MissionAbortedEvent {
+    pub id: String,
+    #[serde(alias = "mission_id")]
     pub mission_id: String,
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+/// Tello command to stop the drone right now
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum StopCommand {
+    /// cuts the motors, the drone falls down
+    Emergency,
+    Land,
+}
+/// bypasses the controller and reaches the drone even while it executes a command
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmergencyStopEvent {
+    pub id: String,
+    pub command: StopCommand,
+}
+/// decision of the launchpad's battery policy
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum BatteryDecision {
+    LaunchApproved,
+    /// the battery is below the launch minimum
+    LaunchRefused,
+    /// the mission would drain the battery below the flight minimum
+    MissionInfeasible,
+    /// the battery dropped below the flight minimum, the drone lands in place
+    Landing,
+}
+/// the launchpad checked the battery, `required` is the level the decision was based on
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatteryCheckedEvent {
+    pub id: String,
+    #[serde(alias = "mission_id")]
+    pub mission_id: String,
+    pub battery: u8,
+    pub required: u8,
+    pub decision: BatteryDecision,
 }
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DroneLandedEvent {
@@ -64,20 +144,157 @@ pub struct DroneLandedEvent {
 pub struct DroneDisconnectedEvent {
     pub id: String,
 }
-
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DroneChargingStartedEvent {
+    pub id: String,
+}
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DroneChargingCompletedEvent {
+    pub id: String,
+    pub battery: u8,
+}
+/// the hardware of the drone is broken, e.g. a motor or the camera
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DroneFaultDetectedEvent {
+    pub id: String,
+    pub reason: String,
+}
+/// the drone is repaired
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DroneFaultClearedEvent {
+    pub id: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, EventEmitter)]
 #[serde(tag = "eventType")]
 #[serde(rename_all = "camelCase")]
+#[emitter(base = "drone")]
+#[allow(clippy::enum_variant_names)]
 pub enum DroneEvent {
+    #[emitter(tag = "drone.defined")]
     DroneDefined(DroneDefinedEvent),
     DroneReady(DroneReadyEvent),
     DroneActivated(DroneActivatedEvent),
     DroneConnected(DroneConnectedEvent),
     DroneStatsUpdated(DroneStatsUpdatedEvent),
+    #[emitter(tag = "drone.mission.started")]
     DroneLaunched(DroneLaunchedEvent),
     DroneStartedToNextWaypoint(DroneStartedToNextWaypointEvent),
     DroneArrivedAtWaypoint(DroneArrivedAtWaypointEvent),
+    #[emitter(tag = "drone.mission.completed")]
     DroneMissionCompleted(DroneMissionCompletedEvent),
+    MissionAborted(MissionAbortedEvent),
+    #[emitter(tag = "drone.emergency")]
+    EmergencyStop(EmergencyStopEvent),
+    BatteryChecked(BatteryCheckedEvent),
     DroneLanded(DroneLandedEvent),
     DroneDisconnected(DroneDisconnectedEvent),
+    DroneChargingStarted(DroneChargingStartedEvent),
+    DroneChargingCompleted(DroneChargingCompletedEvent),
+    DroneFaultDetected(DroneFaultDetectedEvent),
+    DroneFaultCleared(DroneFaultClearedEvent),
+    #[emitter(tag = "drone.defined")]
+    DroneRemoved(DroneRemovedEvent),
+}
+
+impl DroneEvent {
+    /// id of the drone
+    pub fn id(&self) -> &str {
+        match self {
+            DroneEvent::DroneDefined(e) => &e.id,
+            DroneEvent::DroneReady(e) => &e.id,
+            DroneEvent::DroneActivated(e) => &e.id,
+            DroneEvent::DroneConnected(e) => &e.id,
+            DroneEvent::DroneStatsUpdated(e) => &e.id,
+            DroneEvent::DroneLaunched(e) => &e.id,
+            DroneEvent::DroneStartedToNextWaypoint(e) => &e.id,
+            DroneEvent::DroneArrivedAtWaypoint(e) => &e.id,
+            DroneEvent::DroneMissionCompleted(e) => &e.id,
+            DroneEvent::MissionAborted(e) => &e.id,
+            DroneEvent::EmergencyStop(e) => &e.id,
+            DroneEvent::BatteryChecked(e) => &e.id,
+            DroneEvent::DroneLanded(e) => &e.id,
+            DroneEvent::DroneDisconnected(e) => &e.id,
+            DroneEvent::DroneChargingStarted(e) => &e.id,
+            DroneEvent::DroneChargingCompleted(e) => &e.id,
+            DroneEvent::DroneFaultDetected(e) => &e.id,
+            DroneEvent::DroneFaultCleared(e) => &e.id,
+            DroneEvent::DroneRemoved(e) => &e.id,
+        }
+    }
+}
+
+#[tokio::test]
+async fn emit_functions_tag_the_events_of_their_twin() {
+    use crate::memory_service::MemoryEventService;
+    use crate::twins::launchpad_twin::events::{LaunchPadEvent, MissionQueuedEvent};
+    use actyx_sdk::{tags, TagSet};
+
+    let service = MemoryEventService::new();
+    DroneEvent::emit_drone_ready(service.clone(), DroneReadyEvent { id: "d1".into() })
+        .await
+        .unwrap();
+    DroneEvent::emit_drone_mission_completed(
+        service.clone(),
+        DroneMissionCompletedEvent {
+            id: "d1".into(),
+            mission_id: "m1".into(),
+            outcome: MissionOutcome::Completed,
+        },
+    )
+    .await
+    .unwrap();
+    LaunchPadEvent::emit_mission_queued(
+        service.clone(),
+        MissionQueuedEvent {
+            launchpad_id: "pad-1".into(),
+            mission_id: "m1".into(),
+            schedule: None,
+        },
+    )
+    .await
+    .unwrap();
+
+    let tags: Vec<TagSet> = service.events().into_iter().map(|e| e.tags).collect();
+    assert_eq!(
+        tags,
+        [
+            tags!("drone", "drone:d1"),
+            tags!("drone", "drone:d1", "drone.mission.completed"),
+            tags!("launchpad", "launchpad:pad-1", "mission.queued"),
+        ]
+    );
+    let payload = service.events()[1].payload.extract::<DroneEvent>().unwrap();
+    assert!(matches!(payload, DroneEvent::DroneMissionCompleted(e) if e.mission_id == "m1"));
+}
+
+#[test]
+fn drone_events_are_camel_case_and_older_snake_case_ones_are_read() {
+    let launched = DroneEvent::DroneLaunched(DroneLaunchedEvent {
+        id: "d1".into(),
+        mission_id: "m1".into(),
+        mission_version: Some(2),
+    });
+    assert_eq!(
+        serde_json::to_value(&launched).unwrap(),
+        serde_json::json!({
+            "eventType": "droneLaunched",
+            "id": "d1",
+            "missionId": "m1",
+            "missionVersion": 2
+        })
+    );
+
+    // written by launchpads before the drone stream was camelCase
+    let arrived: DroneEvent = serde_json::from_value(serde_json::json!({
+        "eventType": "droneArrivedAtWaypoint",
+        "id": "d1",
+        "mission_id": "m1",
+        "waypoint_id": 3
+    }))
+    .unwrap();
+    assert!(matches!(
+        arrived,
+        DroneEvent::DroneArrivedAtWaypoint(e) if e.mission_id == "m1" && e.waypoint_id == 3
+    ));
 }
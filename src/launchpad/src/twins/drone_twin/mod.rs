@@ -1,17 +1,16 @@
-use std::convert::TryInto;
-use std::time::{Duration, SystemTime};
-
-use self::ev::Position;
 use self::events as ev;
-use self::states::UsedState;
+use self::transitions::Transition;
+use crate::twin::tag_with_id;
 use crate::twin::Twin;
-use crate::twin::{mk_publish_request, tag_with_id};
-use actyx_sdk::service::{EventService, PublishResponse};
-use actyx_sdk::{tag, Event, Payload, TagSet};
+use actyx_sdk::{Event, TagSet};
+use std::collections::HashSet;
 
 pub mod events;
 pub mod states;
+pub mod transitions;
 
+/// tags of all events of a drone, the emit functions of `DroneEvent` add the others
+#[allow(dead_code)]
 pub fn tag_drone_id<T>(id: &T) -> TagSet
 where
     T: core::fmt::Display,
@@ -19,20 +18,6 @@ where
     tag_with_id("drone", id)
 }
 
-pub fn tag_drone_mission_started<T>(id: &T) -> TagSet
-where
-    T: core::fmt::Display,
-{
-    tag_drone_id(id) + tag!("drone.mission.started")
-}
-
-pub fn tag_drone_mission_completed<T>(id: &T) -> TagSet
-where
-    T: core::fmt::Display,
-{
-    tag_drone_id(id) + tag!("drone.mission.completed")
-}
-
 #[derive(Clone)]
 pub struct DroneTwin {
     pub id: String,
@@ -40,6 +25,7 @@ pub struct DroneTwin {
 
 impl Twin for DroneTwin {
     type State = states::DroneTwinState;
+    type Event = ev::DroneEvent;
     fn name(&self) -> String {
         "drone".to_string()
     }
@@ -51,455 +37,59 @@ impl Twin for DroneTwin {
             .parse()
             .expect("DroneTwin: AQL query not parse-able")
     }
-
-    fn reducer(state: Self::State, event: Event<Payload>) -> Self::State {
-        if let Ok(ev) = event.extract::<ev::DroneEvent>() {
-            match ev.payload {
-                ev::DroneEvent::DroneDefined(e) => {
-                    states::DroneTwinState::Ready(states::ReadyState {
-                        id: e.id,
-                        ip: e.ip,
-                        ssid: e.ssid,
-                        enabled: SystemTime::UNIX_EPOCH,
-                        battery: 100,
-                        connected: false,
-                    })
-                }
-                ev::DroneEvent::DroneReady(e) => DroneTwin::handle_ready_event(state, e),
-                ev::DroneEvent::DroneActivated(e) => {
-                    DroneTwin::handle_activated_event(state, e, event.meta)
-                }
-                ev::DroneEvent::DroneConnected(e) => DroneTwin::handle_connected_event(state, e),
-                ev::DroneEvent::DroneStatsUpdated(e) => {
-                    DroneTwin::handle_states_updated_event(state, e)
-                }
-                ev::DroneEvent::DroneLaunched(e) => DroneTwin::handle_launched_event(state, e),
-                ev::DroneEvent::DroneStartedToNextWaypoint(e) => {
-                    DroneTwin::handle_started_to_next_waypoint(state, e)
-                }
-                ev::DroneEvent::DroneArrivedAtWaypoint(e) => {
-                    DroneTwin::handle_arrived_at_waypoint(state, e)
-                }
-                ev::DroneEvent::DroneMissionCompleted(e) => {
-                    DroneTwin::handle_mission_completed(state, e)
-                }
-                ev::DroneEvent::DroneLanded(e) => DroneTwin::handle_landed(state, e),
-                ev::DroneEvent::DroneDisconnected(_) => state,
-            }
-        } else {
-            state
-        }
+    fn snapshot_version(&self) -> Option<u32> {
+        Some(4)
     }
-}
 
-impl DroneTwin {
-    fn handle_ready_event(
-        state: states::DroneTwinState,
-        _: events::DroneReadyEvent,
-    ) -> states::DroneTwinState {
-        match state {
-            states::DroneTwinState::Undefined(_) => state,
-            states::DroneTwinState::Ready(state) => {
-                states::DroneTwinState::Ready(states::ReadyState {
-                    id: state.id,
-                    ip: state.ip,
-                    ssid: state.ssid,
-                    battery: state.battery,
-                    enabled: state.enabled,
-                    connected: false,
-                })
-            }
-            states::DroneTwinState::Launched(state) => {
-                states::DroneTwinState::Ready(states::ReadyState {
-                    id: state.id,
-                    ip: state.ip,
-                    ssid: state.ssid,
-                    enabled: SystemTime::UNIX_EPOCH,
-                    battery: state.battery,
-                    connected: false,
-                })
-            }
-            states::DroneTwinState::Used(state) => {
-                states::DroneTwinState::Ready(states::ReadyState {
-                    id: state.id,
-                    ip: state.ip,
-                    ssid: state.ssid,
-                    enabled: SystemTime::UNIX_EPOCH,
-                    battery: state.battery,
-                    connected: false,
-                })
-            }
-        }
-    }
-    fn handle_activated_event(
-        state: states::DroneTwinState,
-        _: events::DroneActivatedEvent,
-        meta: actyx_sdk::Metadata,
-    ) -> states::DroneTwinState {
-        match state {
-            states::DroneTwinState::Undefined(_) => state,
-            states::DroneTwinState::Ready(mut state) => {
-                state.enabled =
-                    SystemTime::UNIX_EPOCH + Duration::from_micros(meta.timestamp.into());
-                return states::DroneTwinState::Ready(state);
-            }
-            states::DroneTwinState::Launched(_) => state,
-            states::DroneTwinState::Used(state) => {
-                states::DroneTwinState::Ready(states::ReadyState {
-                    id: state.id,
-                    ip: state.ip,
-                    ssid: state.ssid,
-                    battery: state.battery,
-                    enabled: SystemTime::UNIX_EPOCH + Duration::from_micros(meta.timestamp.into()),
-                    connected: false,
-                })
-            }
-        }
-    }
-    fn handle_connected_event(
-        state: states::DroneTwinState,
-        _: events::DroneConnectedEvent,
-    ) -> states::DroneTwinState {
-        match state {
-            states::DroneTwinState::Undefined(_) => state,
-            states::DroneTwinState::Ready(mut state) => {
-                state.connected = true;
-                return states::DroneTwinState::Ready(state);
-            }
-            states::DroneTwinState::Launched(_) => state,
-            states::DroneTwinState::Used(state) => {
-                states::DroneTwinState::Ready(states::ReadyState {
-                    id: state.id,
-                    ip: state.ip,
-                    ssid: state.ssid,
-                    enabled: SystemTime::UNIX_EPOCH,
-                    battery: state.battery,
-                    connected: true,
-                })
-            }
-        }
-    }
-    fn handle_states_updated_event(
-        state: states::DroneTwinState,
-        e: events::DroneStatsUpdatedEvent,
-    ) -> states::DroneTwinState {
-        match state {
-            states::DroneTwinState::Undefined(_) => state,
-            states::DroneTwinState::Ready(mut state) => {
-                state.battery = e.battery;
-                states::DroneTwinState::Ready(state)
-            }
-            states::DroneTwinState::Launched(mut state) => {
-                state.battery = e.battery;
-                states::DroneTwinState::Launched(state)
-            }
-            states::DroneTwinState::Used(mut state) => {
-                state.battery = e.battery;
-                states::DroneTwinState::Used(state)
-            }
+    /// applies the transition table, invalid events leave the state as it is
+    fn reducer(state: Self::State, event: Event<ev::DroneEvent>) -> Self::State {
+        match transitions::transition(&state, &event.payload, &event.meta) {
+            Transition::To(next) => next,
+            Transition::Stay | Transition::Invalid => state,
         }
     }
-    fn handle_launched_event(
-        state: states::DroneTwinState,
-        e: events::DroneLaunchedEvent,
-    ) -> states::DroneTwinState {
-        match state {
-            states::DroneTwinState::Undefined(_) => state,
-            states::DroneTwinState::Ready(s) => {
-                states::DroneTwinState::Launched(states::LaunchedState {
-                    id: e.id,
-                    ip: s.ip,
-                    ssid: s.ssid,
-                    battery: s.battery,
-                    at_waypoint_id: 0,
-                    mission_id: e.mission_id,
-                    target_waypoint_id: None,
-                    completed: false,
-                })
-            }
-            states::DroneTwinState::Launched(_) => state,
-            states::DroneTwinState::Used(s) => {
-                states::DroneTwinState::Launched(states::LaunchedState {
-                    id: e.id,
-                    ip: s.ip,
-                    ssid: s.ssid,
-                    battery: s.battery,
-                    at_waypoint_id: 0,
-                    mission_id: e.mission_id,
-                    target_waypoint_id: None,
-                    completed: false,
-                })
-            }
-        }
-    }
-    fn handle_started_to_next_waypoint(
-        state: states::DroneTwinState,
-        e: events::DroneStartedToNextWaypointEvent,
-    ) -> states::DroneTwinState {
-        match state {
-            states::DroneTwinState::Undefined(_) => state,
-            states::DroneTwinState::Ready(s) => {
-                states::DroneTwinState::Launched(states::LaunchedState {
-                    id: e.id,
-                    ip: s.ip,
-                    ssid: s.ssid,
-                    battery: s.battery,
-                    at_waypoint_id: (e.waypoint_id.max(1) - 1).try_into().unwrap(),
-                    mission_id: e.mission_id,
-                    target_waypoint_id: Some(e.waypoint_id.try_into().unwrap()),
-                    completed: false,
-                })
-            }
-            states::DroneTwinState::Launched(s) => {
-                states::DroneTwinState::Launched(states::LaunchedState {
-                    id: e.id,
-                    ip: s.ip,
-                    ssid: s.ssid,
-                    battery: s.battery,
-                    at_waypoint_id: s.target_waypoint_id.unwrap_or(0),
-                    mission_id: e.mission_id,
-                    target_waypoint_id: Some(e.waypoint_id.try_into().unwrap()),
-                    completed: false,
-                })
-            }
-            states::DroneTwinState::Used(s) => {
-                states::DroneTwinState::Launched(states::LaunchedState {
-                    id: e.id,
-                    ip: s.ip,
-                    ssid: s.ssid,
-                    battery: s.battery,
-                    at_waypoint_id: (e.waypoint_id.max(1) - 1).try_into().unwrap(),
-                    mission_id: e.mission_id,
-                    target_waypoint_id: Some(e.waypoint_id.try_into().unwrap()),
-                    completed: false,
-                })
-            }
-        }
-    }
-    fn handle_arrived_at_waypoint(
-        state: states::DroneTwinState,
-        e: events::DroneArrivedAtWaypointEvent,
-    ) -> states::DroneTwinState {
-        match state {
-            states::DroneTwinState::Undefined(_) => state,
-            states::DroneTwinState::Ready(s) => {
-                states::DroneTwinState::Launched(states::LaunchedState {
-                    id: e.id,
-                    ip: s.ip,
-                    ssid: s.ssid,
-                    battery: s.battery,
-                    at_waypoint_id: e.waypoint_id as u32,
-                    mission_id: e.mission_id,
-                    target_waypoint_id: None,
-                    completed: false,
-                })
-            }
-            states::DroneTwinState::Launched(mut s) => {
-                s.at_waypoint_id = e.waypoint_id as u32;
-                s.mission_id = e.mission_id;
-                s.target_waypoint_id = None;
 
-                states::DroneTwinState::Launched(s)
-            }
-            states::DroneTwinState::Used(s) => {
-                states::DroneTwinState::Launched(states::LaunchedState {
-                    id: e.id,
-                    ip: s.ip,
-                    ssid: s.ssid,
-                    battery: s.battery,
-                    at_waypoint_id: e.waypoint_id as u32,
-                    mission_id: e.mission_id,
-                    target_waypoint_id: None,
-                    completed: false,
-                })
-            }
-        }
-    }
-    fn handle_mission_completed(
-        state: states::DroneTwinState,
-        _: events::DroneMissionCompletedEvent,
-    ) -> states::DroneTwinState {
-        match state {
-            states::DroneTwinState::Undefined(_) => state,
-            states::DroneTwinState::Ready(_) => state,
-            states::DroneTwinState::Launched(mut s) => {
-                s.completed = true;
-                s.target_waypoint_id = None;
-                states::DroneTwinState::Launched(s)
-            }
-            states::DroneTwinState::Used(_) => state,
-        }
-    }
-    fn handle_landed(
-        state: states::DroneTwinState,
-        _: events::DroneLandedEvent,
-    ) -> states::DroneTwinState {
-        match state {
-            states::DroneTwinState::Undefined(_) => state,
-            states::DroneTwinState::Ready(_) => state,
-            states::DroneTwinState::Launched(mut s) => {
-                s.completed = false;
-                s.target_waypoint_id = None;
-                states::DroneTwinState::Used(UsedState {
-                    id: s.id,
-                    ip: s.ip,
-                    ssid: s.ssid,
-                    last_mission_id: s.mission_id,
-                    battery: s.battery,
-                })
-            }
-            states::DroneTwinState::Used(_) => state,
+    fn rejection(state: &Self::State, event: &Event<ev::DroneEvent>) -> Option<String> {
+        match transitions::transition(state, &event.payload, &event.meta) {
+            Transition::Invalid => Some(format!(
+                "drone {}: {:?} is invalid in state {}",
+                event.payload.id(),
+                event.payload,
+                state.name()
+            )),
+            _ => None,
         }
     }
 }
 
-impl DroneTwin {
-    #[allow(dead_code)]
-    pub async fn emit_drone_ready(
-        service: impl EventService,
-        id: String,
-    ) -> Result<PublishResponse, anyhow::Error> {
-        service
-            .publish(mk_publish_request(
-                tag_drone_id(&id),
-                &ev::DroneEvent::DroneReady(ev::DroneReadyEvent { id }),
-            ))
-            .await
-    }
-
-    #[allow(dead_code)]
-    pub async fn emit_drone_activated(
-        service: impl EventService,
-        id: String,
-        launchpad_id: String,
-    ) -> Result<PublishResponse, anyhow::Error> {
-        service
-            .publish(mk_publish_request(
-                tag_drone_id(&id),
-                &ev::DroneEvent::DroneActivated(ev::DroneActivatedEvent { id, launchpad_id }),
-            ))
-            .await
-    }
-
-    #[allow(dead_code)]
-    pub async fn emit_drone_connected(
-        service: impl EventService,
-        id: String,
-    ) -> Result<PublishResponse, anyhow::Error> {
-        service
-            .publish(mk_publish_request(
-                tag_drone_id(&id),
-                &ev::DroneEvent::DroneConnected(ev::DroneConnectedEvent { id }),
-            ))
-            .await
-    }
+/// ids of the drones of the fleet, defined and not removed
+#[derive(Clone)]
+pub struct DroneRegistryTwin;
 
-    #[allow(dead_code)]
-    pub async fn emit_drone_stats_updated(
-        service: impl EventService,
-        id: String,
-        battery: u8,
-    ) -> Result<PublishResponse, anyhow::Error> {
-        service
-            .publish(mk_publish_request(
-                tag_drone_id(&id),
-                &ev::DroneEvent::DroneStatsUpdated(ev::DroneStatsUpdatedEvent { id, battery }),
-            ))
-            .await
-    }
-    #[allow(dead_code)]
-    pub async fn emit_drone_launched(
-        service: impl EventService,
-        id: String,
-        mission_id: String,
-    ) -> Result<PublishResponse, anyhow::Error> {
-        service
-            .publish(mk_publish_request(
-                tag_drone_mission_started(&id),
-                &ev::DroneEvent::DroneLaunched(ev::DroneLaunchedEvent { id, mission_id }),
-            ))
-            .await
-    }
-    #[allow(dead_code)]
-    pub async fn emit_drone_started_to_next_waypoint(
-        service: impl EventService,
-        id: String,
-        mission_id: String,
-        waypoint_id: i32,
-    ) -> Result<PublishResponse, anyhow::Error> {
-        service
-            .publish(mk_publish_request(
-                tag_drone_id(&id),
-                &ev::DroneEvent::DroneStartedToNextWaypoint(ev::DroneStartedToNextWaypointEvent {
-                    id,
-                    mission_id,
-                    waypoint_id,
-                }),
-            ))
-            .await
-    }
-    #[allow(dead_code)]
-    pub async fn emit_drone_arrived_at_waypoint(
-        service: impl EventService,
-        id: String,
-        mission_id: String,
-        waypoint_id: i32,
-    ) -> Result<PublishResponse, anyhow::Error> {
-        service
-            .publish(mk_publish_request(
-                tag_drone_id(&id),
-                &ev::DroneEvent::DroneArrivedAtWaypoint(ev::DroneArrivedAtWaypointEvent {
-                    id,
-                    mission_id,
-                    waypoint_id,
-                }),
-            ))
-            .await
+impl Twin for DroneRegistryTwin {
+    type State = HashSet<String>;
+    type Event = ev::DroneEvent;
+    fn name(&self) -> String {
+        "droneRegistry".to_string()
     }
-    #[allow(dead_code)]
-    pub async fn emit_drone_mission_completed(
-        service: impl EventService,
-        id: String,
-        mission_id: String,
-    ) -> Result<PublishResponse, anyhow::Error> {
-        service
-            .publish(mk_publish_request(
-                tag_drone_mission_completed(&id),
-                &ev::DroneEvent::DroneMissionCompleted(ev::DroneMissionCompletedEvent {
-                    id,
-                    mission_id,
-                }),
-            ))
-            .await
+    fn id(&self) -> String {
+        "reg".to_string()
     }
-    #[allow(dead_code)]
-    pub async fn emit_drone_landed(
-        service: impl EventService,
-        id: String,
-        x: f32,
-        y: f32,
-        z: f32,
-    ) -> Result<PublishResponse, anyhow::Error> {
-        service
-            .publish(mk_publish_request(
-                tag_drone_id(&id),
-                &ev::DroneEvent::DroneLanded(ev::DroneLandedEvent {
-                    id,
-                    at: Position { x, y, z },
-                }),
-            ))
-            .await
+    fn query(&self) -> actyx_sdk::language::Query {
+        "FROM 'drone.defined'"
+            .parse()
+            .expect("DroneRegistryTwin: AQL query not parse-able")
     }
-    #[allow(dead_code)]
-    pub async fn emit_drone_disconnected(
-        service: impl EventService,
-        id: String,
-    ) -> Result<PublishResponse, anyhow::Error> {
-        service
-            .publish(mk_publish_request(
-                tag_drone_id(&id),
-                &ev::DroneEvent::DroneDisconnected(ev::DroneDisconnectedEvent { id }),
-            ))
-            .await
+    fn reducer(mut state: Self::State, event: Event<ev::DroneEvent>) -> Self::State {
+        match event.payload {
+            ev::DroneEvent::DroneDefined(e) => {
+                state.insert(e.id);
+            }
+            ev::DroneEvent::DroneRemoved(e) => {
+                state.remove(&e.id);
+            }
+            _ => (),
+        }
+        state
     }
 }
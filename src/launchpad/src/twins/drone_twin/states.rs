@@ -1,38 +1,66 @@
+use serde::{Deserialize, Serialize};
 use std::time::{Duration, SystemTime};
 
-#[derive(Clone, Debug, PartialEq)]
+/// time the drone is connectable after it was enabled
+pub const ENABLE_WINDOW: Duration = Duration::from_secs(15);
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct UndefinedState {
     pub id: String,
 }
 
-#[derive(Clone, Debug, PartialEq)]
+/// the drone is defined and waits on the ground
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct ReadyState {
     pub id: String,
     pub ip: String,
     pub ssid: String,
     pub battery: u8,
-    pub enabled: SystemTime,
-    pub connected: bool,
 }
-impl ReadyState {
-    pub fn is_enabled(&self) -> bool {
-        self.enabled.elapsed().unwrap() < Duration::new(15, 0)
+
+/// the launchpad switched the drone on, it is connectable for a short time
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EnabledState {
+    pub id: String,
+    pub ip: String,
+    pub ssid: String,
+    pub battery: u8,
+    pub since: SystemTime,
+}
+impl EnabledState {
+    /// the drone is still connectable at `now`
+    pub fn is_enabled(&self, now: SystemTime) -> bool {
+        now.duration_since(self.since)
+            .map_or(true, |enabled| enabled < ENABLE_WINDOW)
     }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+/// the launchpad is connected to the drone, it can take off
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ConnectedState {
+    pub id: String,
+    pub ip: String,
+    pub ssid: String,
+    pub battery: u8,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct LaunchedState {
     pub id: String,
     pub ip: String,
     pub ssid: String,
     pub mission_id: String,
+    /// the drone flies the waypoints of this version, even if the mission is changed meanwhile
+    pub mission_version: Option<u32>,
     pub at_waypoint_id: u32,
     pub target_waypoint_id: Option<u32>,
     pub completed: bool,
+    /// the mission was aborted, the drone returns and lands instead of flying the next waypoint
+    pub aborted: bool,
     pub battery: u8,
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct UsedState {
     pub id: String,
     pub ip: String,
@@ -41,12 +69,45 @@ pub struct UsedState {
     pub battery: u8,
 }
 
-#[derive(Clone, Debug, PartialEq)]
+/// the connection to the drone on the ground was lost, the launchpad connects again
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DisconnectedState {
+    pub id: String,
+    pub ip: String,
+    pub ssid: String,
+    pub battery: u8,
+}
+
+/// the drone sits on a charger until it is charged
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ChargingState {
+    pub id: String,
+    pub ip: String,
+    pub ssid: String,
+    pub battery: u8,
+}
+
+/// the hardware of the drone is broken, it doesn't fly until the fault is cleared
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FaultState {
+    pub id: String,
+    pub ip: String,
+    pub ssid: String,
+    pub battery: u8,
+    pub reason: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum DroneTwinState {
     Undefined(UndefinedState),
     Ready(ReadyState),
+    Enabled(EnabledState),
+    Connected(ConnectedState),
     Launched(LaunchedState),
     Used(UsedState),
+    Disconnected(DisconnectedState),
+    Charging(ChargingState),
+    Fault(FaultState),
 }
 
 impl Default for DroneTwinState {
@@ -63,8 +124,60 @@ impl DroneTwinState {
         match self {
             DroneTwinState::Undefined(s) => s.id.to_owned(),
             DroneTwinState::Ready(s) => s.id.to_owned(),
+            DroneTwinState::Enabled(s) => s.id.to_owned(),
+            DroneTwinState::Connected(s) => s.id.to_owned(),
             DroneTwinState::Launched(s) => s.id.to_owned(),
             DroneTwinState::Used(s) => s.id.to_owned(),
+            DroneTwinState::Disconnected(s) => s.id.to_owned(),
+            DroneTwinState::Charging(s) => s.id.to_owned(),
+            DroneTwinState::Fault(s) => s.id.to_owned(),
         }
     }
+
+    /// last known battery level, `None` for undefined drones
+    pub fn battery(&self) -> Option<u8> {
+        match self {
+            DroneTwinState::Undefined(_) => None,
+            DroneTwinState::Ready(s) => Some(s.battery),
+            DroneTwinState::Enabled(s) => Some(s.battery),
+            DroneTwinState::Connected(s) => Some(s.battery),
+            DroneTwinState::Launched(s) => Some(s.battery),
+            DroneTwinState::Used(s) => Some(s.battery),
+            DroneTwinState::Disconnected(s) => Some(s.battery),
+            DroneTwinState::Charging(s) => Some(s.battery),
+            DroneTwinState::Fault(s) => Some(s.battery),
+        }
+    }
+
+    /// name of the state, like `state` in the drone fish
+    pub fn name(&self) -> &'static str {
+        match self {
+            DroneTwinState::Undefined(_) => "undefined",
+            DroneTwinState::Ready(_) => "ready",
+            DroneTwinState::Enabled(_) => "enabled",
+            DroneTwinState::Connected(_) => "connected",
+            DroneTwinState::Launched(_) => "launched",
+            DroneTwinState::Used(_) => "used",
+            DroneTwinState::Disconnected(_) => "disconnected",
+            DroneTwinState::Charging(_) => "charging",
+            DroneTwinState::Fault(_) => "fault",
+        }
+    }
+}
+
+#[test]
+fn the_drone_is_enabled_for_the_enable_window() {
+    let since = SystemTime::UNIX_EPOCH + Duration::from_secs(1000);
+    let enabled = EnabledState {
+        id: "d1".to_string(),
+        ip: "192.168.10.1".to_string(),
+        ssid: "TELLO-D1".to_string(),
+        battery: 80,
+        since,
+    };
+    assert!(enabled.is_enabled(since));
+    assert!(enabled.is_enabled(since + Duration::from_secs(14)));
+    assert!(!enabled.is_enabled(since + ENABLE_WINDOW));
+    // the clock of the launchpad is behind the one that published the event
+    assert!(enabled.is_enabled(since - Duration::from_secs(2)));
 }
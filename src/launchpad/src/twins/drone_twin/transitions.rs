@@ -0,0 +1,519 @@
+use super::events::DroneEvent as E;
+use super::states::{
+    ChargingState, ConnectedState, DisconnectedState, DroneTwinState as S, EnabledState,
+    FaultState, LaunchedState, ReadyState, UndefinedState, UsedState,
+};
+use actyx_sdk::Metadata;
+use std::time::{Duration, SystemTime};
+
+/// What an event does to the state of a drone
+#[derive(Clone, Debug, PartialEq)]
+pub enum Transition {
+    /// the drone moves into this state
+    To(S),
+    /// the event is fine, but doesn't change the state, e.g. a late `DroneLanded`
+    Stay,
+    /// the event can't happen in this state, the state stays as it is
+    Invalid,
+}
+
+/// The transition table of a drone. Every state × event pair is listed, unlisted pairs of a
+/// state are invalid.
+pub fn transition(state: &S, event: &E, meta: &Metadata) -> Transition {
+    use Transition::*;
+    let d = drone(state);
+    match (state, event) {
+        // the drone is (re)defined, a flying drone keeps its mission until it lands
+        (S::Launched(_), E::DroneDefined(_)) => Stay,
+        (_, E::DroneDefined(e)) => To(S::Ready(ReadyState {
+            id: e.id.clone(),
+            ip: e.ip.clone(),
+            ssid: e.ssid.clone(),
+            battery: 100,
+        })),
+        // a flying drone lands before it leaves the fleet
+        (S::Launched(_), E::DroneRemoved(_)) => Invalid,
+        (_, E::DroneRemoved(e)) => To(S::Undefined(UndefinedState { id: e.id.clone() })),
+        (S::Undefined(_), _) => Invalid,
+
+        // every defined drone reports its battery, the check only informs the UI
+        (_, E::DroneStatsUpdated(e)) => To(with_battery(state, e.battery)),
+        (_, E::BatteryChecked(_)) => Stay,
+        // faults are reported on the ground, a flying drone is stopped with `EmergencyStop`
+        (S::Launched(_), E::DroneFaultDetected(_)) => Invalid,
+        (_, E::DroneFaultDetected(e)) => To(S::Fault(FaultState {
+            id: d.id,
+            ip: d.ip,
+            ssid: d.ssid,
+            battery: d.battery,
+            reason: e.reason.clone(),
+        })),
+
+        (S::Ready(_), E::DroneActivated(_)) => To(enabled(d, meta)),
+        // the drone was switched on by hand
+        (S::Ready(_), E::DroneConnected(_)) => To(connected(d)),
+        (S::Ready(_), E::DroneChargingStarted(_)) => To(charging(d)),
+        (S::Ready(_), E::DroneReady(_))
+        | (S::Ready(_), E::DroneDisconnected(_))
+        | (S::Ready(_), E::DroneMissionCompleted(_))
+        | (S::Ready(_), E::EmergencyStop(_)) => Stay,
+        (S::Ready(_), _) => Invalid,
+
+        (S::Enabled(_), E::DroneReady(_)) => To(S::Ready(d)),
+        // enabled again after the connect window passed
+        (S::Enabled(_), E::DroneActivated(_)) => To(enabled(d, meta)),
+        (S::Enabled(_), E::DroneConnected(_)) => To(connected(d)),
+        (S::Enabled(_), E::DroneChargingStarted(_)) => To(charging(d)),
+        (S::Enabled(_), E::DroneDisconnected(_))
+        | (S::Enabled(_), E::DroneMissionCompleted(_))
+        | (S::Enabled(_), E::EmergencyStop(_)) => Stay,
+        (S::Enabled(_), _) => Invalid,
+
+        (S::Connected(_), E::DroneReady(_)) => To(S::Ready(d)),
+        (S::Connected(_), E::DroneDisconnected(_)) => To(S::Disconnected(DisconnectedState {
+            id: d.id,
+            ip: d.ip,
+            ssid: d.ssid,
+            battery: d.battery,
+        })),
+        (S::Connected(_), E::DroneChargingStarted(_)) => To(charging(d)),
+        (S::Connected(_), E::DroneLaunched(e)) => To(S::Launched(LaunchedState {
+            id: e.id.clone(),
+            ip: d.ip,
+            ssid: d.ssid,
+            mission_id: e.mission_id.clone(),
+            mission_version: e.mission_version,
+            at_waypoint_id: 0,
+            target_waypoint_id: None,
+            completed: false,
+            aborted: false,
+            battery: d.battery,
+        })),
+        // a refused mission is completed without take off, a stop on the ground is harmless
+        (S::Connected(_), E::DroneActivated(_))
+        | (S::Connected(_), E::DroneConnected(_))
+        | (S::Connected(_), E::DroneMissionCompleted(_))
+        | (S::Connected(_), E::EmergencyStop(_)) => Stay,
+        (S::Connected(_), _) => Invalid,
+
+        // a flying drone is brought down with `EmergencyStop` before it is reset
+        (S::Launched(_), E::DroneReady(_)) => Invalid,
+        // the drone keeps flying its command while the link is down
+        (S::Launched(_), E::DroneConnected(_)) | (S::Launched(_), E::DroneDisconnected(_)) => Stay,
+        (S::Launched(s), E::DroneStartedToNextWaypoint(e)) if e.mission_id == s.mission_id => {
+            To(S::Launched(LaunchedState {
+                at_waypoint_id: s.target_waypoint_id.unwrap_or(s.at_waypoint_id),
+                target_waypoint_id: Some(e.waypoint_id.max(0) as u32),
+                ..s.clone()
+            }))
+        }
+        (S::Launched(s), E::DroneArrivedAtWaypoint(e)) if e.mission_id == s.mission_id => {
+            To(S::Launched(LaunchedState {
+                at_waypoint_id: e.waypoint_id.max(0) as u32,
+                target_waypoint_id: None,
+                ..s.clone()
+            }))
+        }
+        (S::Launched(s), E::DroneMissionCompleted(e)) if e.mission_id == s.mission_id => {
+            To(S::Launched(LaunchedState {
+                completed: true,
+                target_waypoint_id: None,
+                ..s.clone()
+            }))
+        }
+        (S::Launched(s), E::MissionAborted(e)) if e.mission_id == s.mission_id => {
+            To(S::Launched(LaunchedState {
+                aborted: true,
+                ..s.clone()
+            }))
+        }
+        // the drone is down, either landed or fallen
+        (S::Launched(s), E::DroneLanded(_)) | (S::Launched(s), E::EmergencyStop(_)) => {
+            To(S::Used(UsedState {
+                id: d.id,
+                ip: d.ip,
+                ssid: d.ssid,
+                last_mission_id: s.mission_id.clone(),
+                battery: d.battery,
+            }))
+        }
+        (S::Launched(_), _) => Invalid,
+
+        (S::Used(_), E::DroneReady(_)) => To(S::Ready(d)),
+        (S::Used(_), E::DroneActivated(_)) => To(enabled(d, meta)),
+        (S::Used(_), E::DroneChargingStarted(_)) => To(charging(d)),
+        // the mission is completed after the landing
+        (S::Used(_), E::DroneMissionCompleted(_))
+        | (S::Used(_), E::DroneLanded(_))
+        | (S::Used(_), E::EmergencyStop(_))
+        | (S::Used(_), E::DroneDisconnected(_)) => Stay,
+        (S::Used(_), _) => Invalid,
+
+        (S::Disconnected(_), E::DroneReady(_)) => To(S::Ready(d)),
+        (S::Disconnected(_), E::DroneActivated(_)) => To(enabled(d, meta)),
+        (S::Disconnected(_), E::DroneConnected(_)) => To(connected(d)),
+        (S::Disconnected(_), E::DroneChargingStarted(_)) => To(charging(d)),
+        (S::Disconnected(_), E::DroneDisconnected(_))
+        | (S::Disconnected(_), E::DroneMissionCompleted(_))
+        | (S::Disconnected(_), E::EmergencyStop(_)) => Stay,
+        (S::Disconnected(_), _) => Invalid,
+
+        // taken from the charger
+        (S::Charging(_), E::DroneReady(_)) => To(S::Ready(d)),
+        (S::Charging(_), E::DroneChargingCompleted(e)) => To(S::Ready(ReadyState {
+            battery: e.battery,
+            ..d
+        })),
+        (S::Charging(_), E::DroneChargingStarted(_))
+        | (S::Charging(_), E::DroneDisconnected(_))
+        | (S::Charging(_), E::EmergencyStop(_)) => Stay,
+        (S::Charging(_), _) => Invalid,
+
+        (S::Fault(_), E::DroneFaultCleared(_)) => To(S::Ready(d)),
+        (S::Fault(_), E::DroneDisconnected(_)) | (S::Fault(_), E::EmergencyStop(_)) => Stay,
+        (S::Fault(_), _) => Invalid,
+    }
+}
+
+/// what every defined drone has, empty for an undefined drone
+fn drone(state: &S) -> ReadyState {
+    let (id, ip, ssid, battery) = match state {
+        S::Undefined(s) => (&s.id, "", "", 0),
+        S::Ready(s) => (&s.id, s.ip.as_str(), s.ssid.as_str(), s.battery),
+        S::Enabled(s) => (&s.id, s.ip.as_str(), s.ssid.as_str(), s.battery),
+        S::Connected(s) => (&s.id, s.ip.as_str(), s.ssid.as_str(), s.battery),
+        S::Launched(s) => (&s.id, s.ip.as_str(), s.ssid.as_str(), s.battery),
+        S::Used(s) => (&s.id, s.ip.as_str(), s.ssid.as_str(), s.battery),
+        S::Disconnected(s) => (&s.id, s.ip.as_str(), s.ssid.as_str(), s.battery),
+        S::Charging(s) => (&s.id, s.ip.as_str(), s.ssid.as_str(), s.battery),
+        S::Fault(s) => (&s.id, s.ip.as_str(), s.ssid.as_str(), s.battery),
+    };
+    ReadyState {
+        id: id.clone(),
+        ip: ip.to_string(),
+        ssid: ssid.to_string(),
+        battery,
+    }
+}
+
+fn enabled(d: ReadyState, meta: &Metadata) -> S {
+    S::Enabled(EnabledState {
+        id: d.id,
+        ip: d.ip,
+        ssid: d.ssid,
+        battery: d.battery,
+        since: SystemTime::UNIX_EPOCH + Duration::from_micros(meta.timestamp.into()),
+    })
+}
+
+fn connected(d: ReadyState) -> S {
+    S::Connected(ConnectedState {
+        id: d.id,
+        ip: d.ip,
+        ssid: d.ssid,
+        battery: d.battery,
+    })
+}
+
+fn charging(d: ReadyState) -> S {
+    S::Charging(ChargingState {
+        id: d.id,
+        ip: d.ip,
+        ssid: d.ssid,
+        battery: d.battery,
+    })
+}
+
+fn with_battery(state: &S, battery: u8) -> S {
+    match state.clone() {
+        S::Undefined(s) => S::Undefined(s),
+        S::Ready(s) => S::Ready(ReadyState { battery, ..s }),
+        S::Enabled(s) => S::Enabled(EnabledState { battery, ..s }),
+        S::Connected(s) => S::Connected(ConnectedState { battery, ..s }),
+        S::Launched(s) => S::Launched(LaunchedState { battery, ..s }),
+        S::Used(s) => S::Used(UsedState { battery, ..s }),
+        S::Disconnected(s) => S::Disconnected(DisconnectedState { battery, ..s }),
+        S::Charging(s) => S::Charging(ChargingState { battery, ..s }),
+        S::Fault(s) => S::Fault(FaultState { battery, ..s }),
+    }
+}
+
+#[cfg(test)]
+fn sample_states() -> Vec<S> {
+    let d = ReadyState {
+        id: "d1".to_string(),
+        ip: "192.168.10.1".to_string(),
+        ssid: "TELLO-D1".to_string(),
+        battery: 80,
+    };
+    vec![
+        S::Undefined(UndefinedState {
+            id: "d1".to_string(),
+        }),
+        S::Ready(d.clone()),
+        enabled(d.clone(), &meta()),
+        connected(d.clone()),
+        S::Launched(LaunchedState {
+            id: "d1".to_string(),
+            ip: d.ip.clone(),
+            ssid: d.ssid.clone(),
+            mission_id: "m1".to_string(),
+            mission_version: Some(1),
+            at_waypoint_id: 1,
+            target_waypoint_id: Some(2),
+            completed: false,
+            aborted: false,
+            battery: 80,
+        }),
+        S::Used(UsedState {
+            id: "d1".to_string(),
+            ip: d.ip.clone(),
+            ssid: d.ssid.clone(),
+            last_mission_id: "m1".to_string(),
+            battery: 80,
+        }),
+        S::Disconnected(DisconnectedState {
+            id: "d1".to_string(),
+            ip: d.ip.clone(),
+            ssid: d.ssid.clone(),
+            battery: 80,
+        }),
+        charging(d.clone()),
+        S::Fault(FaultState {
+            id: d.id,
+            ip: d.ip,
+            ssid: d.ssid,
+            battery: d.battery,
+            reason: "motor 3".to_string(),
+        }),
+    ]
+}
+
+/// one event per variant, in the order of `DroneEvent`
+#[cfg(test)]
+fn sample_events() -> Vec<E> {
+    use super::events::*;
+    let id = || "d1".to_string();
+    let mission_id = || "m1".to_string();
+    let events = vec![
+        E::DroneDefined(DroneDefinedEvent {
+            id: id(),
+            ssid: "TELLO-D1".to_string(),
+            ip: "192.168.10.1".to_string(),
+        }),
+        E::DroneReady(DroneReadyEvent { id: id() }),
+        E::DroneActivated(DroneActivatedEvent {
+            id: id(),
+            launchpad_id: "lp1".to_string(),
+        }),
+        E::DroneConnected(DroneConnectedEvent { id: id() }),
+        E::DroneStatsUpdated(DroneStatsUpdatedEvent {
+            id: id(),
+            battery: 50,
+        }),
+        E::DroneLaunched(DroneLaunchedEvent {
+            id: id(),
+            mission_id: mission_id(),
+            mission_version: Some(2),
+        }),
+        E::DroneStartedToNextWaypoint(DroneStartedToNextWaypointEvent {
+            id: id(),
+            mission_id: mission_id(),
+            waypoint_id: 3,
+        }),
+        E::DroneArrivedAtWaypoint(DroneArrivedAtWaypointEvent {
+            id: id(),
+            mission_id: mission_id(),
+            waypoint_id: 2,
+        }),
+        E::DroneMissionCompleted(DroneMissionCompletedEvent {
+            id: id(),
+            mission_id: mission_id(),
+            outcome: MissionOutcome::Completed,
+        }),
+        E::MissionAborted(MissionAbortedEvent {
+            id: id(),
+            mission_id: mission_id(),
+            reason: None,
+        }),
+        E::EmergencyStop(EmergencyStopEvent {
+            id: id(),
+            command: StopCommand::Land,
+        }),
+        E::BatteryChecked(BatteryCheckedEvent {
+            id: id(),
+            mission_id: mission_id(),
+            battery: 80,
+            required: 30,
+            decision: BatteryDecision::LaunchApproved,
+        }),
+        E::DroneLanded(DroneLandedEvent {
+            id: id(),
+            at: Position {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+            },
+        }),
+        E::DroneDisconnected(DroneDisconnectedEvent { id: id() }),
+        E::DroneChargingStarted(DroneChargingStartedEvent { id: id() }),
+        E::DroneChargingCompleted(DroneChargingCompletedEvent {
+            id: id(),
+            battery: 100,
+        }),
+        E::DroneFaultDetected(DroneFaultDetectedEvent {
+            id: id(),
+            reason: "camera".to_string(),
+        }),
+        E::DroneFaultCleared(DroneFaultClearedEvent { id: id() }),
+        E::DroneRemoved(DroneRemovedEvent { id: id() }),
+    ];
+    // a new variant doesn't compile until it has a sample
+    for (idx, event) in events.iter().enumerate() {
+        let variant = match event {
+            E::DroneDefined(_) => 0,
+            E::DroneReady(_) => 1,
+            E::DroneActivated(_) => 2,
+            E::DroneConnected(_) => 3,
+            E::DroneStatsUpdated(_) => 4,
+            E::DroneLaunched(_) => 5,
+            E::DroneStartedToNextWaypoint(_) => 6,
+            E::DroneArrivedAtWaypoint(_) => 7,
+            E::DroneMissionCompleted(_) => 8,
+            E::MissionAborted(_) => 9,
+            E::EmergencyStop(_) => 10,
+            E::BatteryChecked(_) => 11,
+            E::DroneLanded(_) => 12,
+            E::DroneDisconnected(_) => 13,
+            E::DroneChargingStarted(_) => 14,
+            E::DroneChargingCompleted(_) => 15,
+            E::DroneFaultDetected(_) => 16,
+            E::DroneFaultCleared(_) => 17,
+            E::DroneRemoved(_) => 18,
+        };
+        assert_eq!(idx, variant, "sample of {:?} is out of order", event);
+    }
+    events
+}
+
+#[cfg(test)]
+fn meta() -> Metadata {
+    Metadata {
+        timestamp: actyx_sdk::Timestamp::new(5_000_000),
+        tags: actyx_sdk::tags!("drone"),
+        app_id: actyx_sdk::app_id!("com.example.launchpad"),
+    }
+}
+
+#[test]
+fn every_state_and_event_has_a_transition() {
+    // per event: the outcome in the states in the order of `DroneTwinState`. `To` is named by the
+    // first letters of the next state, `-` stays, `x` is invalid
+    #[rustfmt::skip]
+    let table = [
+        // und   rea    ena    con    lau    use    dis    cha    fau
+        ["rea", "rea", "rea", "rea", "-",   "rea", "rea", "rea", "rea"], // DroneDefined
+        ["x",   "-",   "rea", "rea", "x",   "rea", "rea", "rea", "x"  ], // DroneReady
+        ["x",   "ena", "ena", "-",   "x",   "ena", "ena", "x",   "x"  ], // DroneActivated
+        ["x",   "con", "con", "-",   "-",   "x",   "con", "x",   "x"  ], // DroneConnected
+        ["x",   "rea", "ena", "con", "lau", "use", "dis", "cha", "fau"], // DroneStatsUpdated
+        ["x",   "x",   "x",   "lau", "x",   "x",   "x",   "x",   "x"  ], // DroneLaunched
+        ["x",   "x",   "x",   "x",   "lau", "x",   "x",   "x",   "x"  ], // DroneStartedToNextWaypoint
+        ["x",   "x",   "x",   "x",   "lau", "x",   "x",   "x",   "x"  ], // DroneArrivedAtWaypoint
+        ["x",   "-",   "-",   "-",   "lau", "-",   "-",   "x",   "x"  ], // DroneMissionCompleted
+        ["x",   "x",   "x",   "x",   "lau", "x",   "x",   "x",   "x"  ], // MissionAborted
+        ["x",   "-",   "-",   "-",   "use", "-",   "-",   "-",   "-"  ], // EmergencyStop
+        ["x",   "-",   "-",   "-",   "-",   "-",   "-",   "-",   "-"  ], // BatteryChecked
+        ["x",   "x",   "x",   "x",   "use", "-",   "x",   "x",   "x"  ], // DroneLanded
+        ["x",   "-",   "-",   "dis", "-",   "-",   "-",   "-",   "-"  ], // DroneDisconnected
+        ["x",   "cha", "cha", "cha", "x",   "cha", "cha", "-",   "x"  ], // DroneChargingStarted
+        ["x",   "x",   "x",   "x",   "x",   "x",   "x",   "rea", "x"  ], // DroneChargingCompleted
+        ["x",   "fau", "fau", "fau", "x",   "fau", "fau", "fau", "fau"], // DroneFaultDetected
+        ["x",   "x",   "x",   "x",   "x",   "x",   "x",   "x",   "rea"], // DroneFaultCleared
+        ["und", "und", "und", "und", "x",   "und", "und", "und", "und"], // DroneRemoved
+    ];
+    let states = sample_states();
+    let events = sample_events();
+    assert_eq!((events.len(), states.len()), (table.len(), table[0].len()));
+    for (event, expected) in events.iter().zip(table.iter()) {
+        for (state, expected) in states.iter().zip(expected.iter()) {
+            let outcome = match transition(state, event, &meta()) {
+                Transition::To(next) => &next.name()[..3],
+                Transition::Stay => "-",
+                Transition::Invalid => "x",
+            };
+            assert_eq!(outcome, *expected, "{:?} in state {}", event, state.name());
+        }
+    }
+}
+
+#[test]
+fn transitions_update_the_state() {
+    let states = sample_states();
+    let events = sample_events();
+    let next = |state: &S, event: &E| match transition(state, event, &meta()) {
+        Transition::To(next) => next,
+        other => panic!("{:?} in {} is {:?}", event, state.name(), other),
+    };
+
+    match next(&states[1], &events[2]) {
+        S::Enabled(s) => assert_eq!(s.since, SystemTime::UNIX_EPOCH + Duration::from_secs(5)),
+        s => panic!("{:?}", s),
+    }
+    match next(&states[3], &events[5]) {
+        S::Launched(s) => {
+            assert_eq!((s.at_waypoint_id, s.target_waypoint_id), (0, None));
+            assert_eq!(s.battery, 80);
+        }
+        s => panic!("{:?}", s),
+    }
+    match next(&states[4], &events[6]) {
+        S::Launched(s) => assert_eq!((s.at_waypoint_id, s.target_waypoint_id), (2, Some(3))),
+        s => panic!("{:?}", s),
+    }
+    match next(&states[4], &events[7]) {
+        S::Launched(s) => assert_eq!((s.at_waypoint_id, s.target_waypoint_id), (2, None)),
+        s => panic!("{:?}", s),
+    }
+    match next(&states[4], &events[9]) {
+        S::Launched(s) => assert!(s.aborted),
+        s => panic!("{:?}", s),
+    }
+    match next(&states[4], &events[12]) {
+        S::Used(s) => assert_eq!(s.last_mission_id, "m1"),
+        s => panic!("{:?}", s),
+    }
+    match next(&states[5], &events[4]) {
+        S::Used(s) => assert_eq!(s.battery, 50),
+        s => panic!("{:?}", s),
+    }
+    match next(&states[7], &events[15]) {
+        S::Ready(s) => assert_eq!(s.battery, 100),
+        s => panic!("{:?}", s),
+    }
+    match next(&states[1], &events[16]) {
+        S::Fault(s) => assert_eq!(s.reason, "camera"),
+        s => panic!("{:?}", s),
+    }
+}
+
+#[test]
+fn events_of_another_mission_are_invalid() {
+    let launched = &sample_states()[4];
+    for mut event in sample_events() {
+        let mission_id = match &mut event {
+            E::DroneStartedToNextWaypoint(e) => &mut e.mission_id,
+            E::DroneArrivedAtWaypoint(e) => &mut e.mission_id,
+            E::DroneMissionCompleted(e) => &mut e.mission_id,
+            E::MissionAborted(e) => &mut e.mission_id,
+            _ => continue,
+        };
+        *mission_id = "m2".to_string();
+        assert_eq!(
+            transition(launched, &event, &meta()),
+            Transition::Invalid,
+            "{:?}",
+            event
+        );
+    }
+}
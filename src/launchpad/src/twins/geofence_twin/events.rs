@@ -0,0 +1,55 @@
+use event_emitter::{EventEmitter, Reducer};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum GeofenceKind {
+    /// the drones stay inside
+    Fence,
+    /// the drones stay outside
+    NoFlyZone,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DefineGeofenceEvent {
+    pub id: String,
+    pub name: String,
+    pub kind: GeofenceKind,
+    /// corners on the map of the `MissionPlanner`, like `GoToWaypoint.map_x/map_y`
+    pub polygon: Vec<[f64; 2]>,
+    /// in cm, like `GoToWaypoint.height`
+    pub min_height: i16,
+    pub max_height: i16,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoveGeofenceEvent {
+    pub id: String,
+}
+
+/// a mission crosses the geofence, or a drone left it during a mission
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GeofenceViolatedEvent {
+    pub id: String,
+    pub mission_id: String,
+    /// the flying drone, missing if the mission was rejected at activation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub drone_id: Option<String>,
+    /// the planned waypoint that crosses the geofence, or the last one the drone reached
+    pub waypoint_id: usize,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, EventEmitter, Reducer)]
+#[serde(tag = "eventType")]
+#[serde(rename_all = "camelCase")]
+#[emitter(base = "geofence")]
+pub enum GeofenceEvent {
+    #[emitter(tag = "geofence.defined")]
+    DefineGeofence(DefineGeofenceEvent),
+    #[emitter(tag = "geofence.defined")]
+    RemoveGeofence(RemoveGeofenceEvent),
+    GeofenceViolated(GeofenceViolatedEvent),
+}
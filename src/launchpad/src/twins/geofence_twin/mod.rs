@@ -0,0 +1,121 @@
+use crate::twin::Twin;
+use crate::twins::geofence_twin::events::{
+    DefineGeofenceEvent, GeofenceEvent, GeofenceEventReducer, GeofenceKind, GeofenceViolatedEvent,
+    RemoveGeofenceEvent,
+};
+use actyx_sdk::{Event, Metadata};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+pub mod events;
+
+/// Area the drones stay inside or outside of. An undefined or removed geofence has no corners
+/// and constrains nothing.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GeofenceTwinState {
+    pub id: String,
+    pub name: String,
+    pub kind: GeofenceKind,
+    pub polygon: Vec<[f64; 2]>,
+    pub min_height: i16,
+    pub max_height: i16,
+}
+
+impl Default for GeofenceTwinState {
+    fn default() -> Self {
+        Self {
+            id: Default::default(),
+            name: Default::default(),
+            kind: GeofenceKind::Fence,
+            polygon: vec![],
+            min_height: 0,
+            max_height: 0,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct GeofenceTwin {
+    pub id: String,
+}
+
+impl Twin for GeofenceTwin {
+    type State = GeofenceTwinState;
+    type Event = GeofenceEvent;
+    fn name(&self) -> String {
+        "geofence".to_string()
+    }
+    fn id(&self) -> String {
+        self.id.clone()
+    }
+    fn query(&self) -> actyx_sdk::language::Query {
+        format!("FROM 'geofence:{}'", self.id)
+            .parse()
+            .expect("GeofenceTwin: AQL query not parse-able")
+    }
+    fn snapshot_version(&self) -> Option<u32> {
+        Some(1)
+    }
+    fn reducer(state: Self::State, event: Event<GeofenceEvent>) -> Self::State {
+        Self::on_event(state, event)
+    }
+}
+
+impl GeofenceEventReducer for GeofenceTwin {
+    fn on_define_geofence(_: Self::State, e: DefineGeofenceEvent, _: &Metadata) -> Self::State {
+        Self::State {
+            id: e.id,
+            name: e.name,
+            kind: e.kind,
+            polygon: e.polygon,
+            min_height: e.min_height,
+            max_height: e.max_height,
+        }
+    }
+
+    fn on_remove_geofence(_: Self::State, e: RemoveGeofenceEvent, _: &Metadata) -> Self::State {
+        Self::State {
+            id: e.id,
+            ..Default::default()
+        }
+    }
+
+    fn on_geofence_violated(
+        state: Self::State,
+        _: GeofenceViolatedEvent,
+        _: &Metadata,
+    ) -> Self::State {
+        state
+    }
+}
+
+/// ids of the geofences, defined and not removed
+#[derive(Clone)]
+pub struct GeofenceRegistryTwin;
+
+impl Twin for GeofenceRegistryTwin {
+    type State = HashSet<String>;
+    type Event = GeofenceEvent;
+    fn name(&self) -> String {
+        "geofenceRegistry".to_string()
+    }
+    fn id(&self) -> String {
+        "reg".to_string()
+    }
+    fn query(&self) -> actyx_sdk::language::Query {
+        "FROM 'geofence.defined'"
+            .parse()
+            .expect("GeofenceRegistryTwin: AQL query not parse-able")
+    }
+    fn reducer(mut state: Self::State, event: Event<GeofenceEvent>) -> Self::State {
+        match event.payload {
+            GeofenceEvent::DefineGeofence(e) => {
+                state.insert(e.id);
+            }
+            GeofenceEvent::RemoveGeofence(e) => {
+                state.remove(&e.id);
+            }
+            GeofenceEvent::GeofenceViolated(_) => (),
+        }
+        state
+    }
+}
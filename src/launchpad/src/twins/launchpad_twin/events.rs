@@ -1,4 +1,6 @@
+use super::schedule::Schedule;
 use crate::twins::drone_twin::events as drone_events;
+use event_emitter::{EventEmitter, Reducer};
 use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
 
@@ -6,6 +8,11 @@ use std::fmt::Debug;
 pub struct LaunchPadRegisteredEvent {
     pub id: String,
 }
+/// the launchpad is taken out of service
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LaunchPadDeregisteredEvent {
+    pub id: String,
+}
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DroneMountedEvent {
     pub id: String,
@@ -17,6 +24,9 @@ pub struct DroneMountedEvent {
 pub struct MissionQueuedEvent {
     pub launchpad_id: String,
     pub mission_id: String,
+    /// missing for missions that fly as soon as possible
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<Schedule>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -26,13 +36,51 @@ pub struct MissionActivatedEvent {
     pub mission_id: String,
 }
 
+/// a queued mission is cancelled, an active mission is aborted on the drone instead
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MissionDequeuedEvent {
+    pub launchpad_id: String,
+    pub mission_id: String,
+}
+
+/// a queued mission moves to `position` of the waiting missions, 0 flies next
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MissionMovedEvent {
+    pub launchpad_id: String,
+    pub mission_id: String,
+    pub position: usize,
+}
+
+/// all waiting missions are cancelled, the active one continues
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MissionQueueClearedEvent {
+    pub launchpad_id: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, EventEmitter, Reducer)]
 #[serde(tag = "eventType")]
 #[serde(rename_all = "camelCase")]
+#[emitter(base = "launchpad")]
 pub enum LaunchPadEvent {
+    #[emitter(tag = "launchpad.registered")]
     LaunchPadRegistered(LaunchPadRegisteredEvent),
+    #[emitter(tag = "launchpad.registered")]
+    LaunchPadDeregistered(LaunchPadDeregisteredEvent),
     DroneMounted(DroneMountedEvent),
+    #[emitter(id = "launchpad_id", tag = "mission.queued")]
     MissionQueued(MissionQueuedEvent),
+    #[emitter(id = "launchpad_id")]
     MissionActivated(MissionActivatedEvent),
+    #[emitter(id = "launchpad_id", tag = "mission.queue.changed")]
+    MissionDequeued(MissionDequeuedEvent),
+    #[emitter(id = "launchpad_id", tag = "mission.queue.changed")]
+    MissionMoved(MissionMovedEvent),
+    #[emitter(id = "launchpad_id", tag = "mission.queue.changed")]
+    MissionQueueCleared(MissionQueueClearedEvent),
+    /// published by the drone, tagged `drone.mission.completed`
+    #[emitter(skip)]
     DroneMissionCompleted(drone_events::DroneMissionCompletedEvent),
 }
@@ -1,25 +1,69 @@
-use self::events as ev;
+use self::events::{self as ev, LaunchPadEventReducer};
+use crate::twin::tag_with_id;
 use crate::twin::Twin;
-use crate::twin::{mk_publish_request, tag_with_id};
-use actyx_sdk::service::{EventService, PublishResponse};
-use actyx_sdk::{tag, Event, Payload, TagSet};
+use crate::twins::drone_twin::events::DroneMissionCompletedEvent;
+use actyx_sdk::{Event, Metadata, TagSet};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
 pub mod events;
+pub mod schedule;
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Default, Serialize, Deserialize)]
 pub struct LaunchpadTwinState {
     pub id: String,
     pub current_mission: Option<String>,
     pub mission_queue: Vec<String>,
     pub attached_drone: Option<String>,
+    /// when the attached drone was mounted, in ms since the epoch
+    #[serde(default)]
+    pub mounted_at: Option<u64>,
+    /// schedules of the queued missions that don't fly as soon as possible
+    #[serde(default)]
+    pub schedules: BTreeMap<String, schedule::Schedule>,
 }
 
-impl Default for LaunchpadTwinState {
-    fn default() -> Self {
+impl LaunchpadTwinState {
+    /// the queued missions without the active one
+    pub fn waiting_missions(&self) -> Vec<String> {
+        self.mission_queue
+            .iter()
+            .filter(|id| Some(*id) != self.current_mission.as_ref())
+            .cloned()
+            .collect()
+    }
+
+    /// the mission to fly at `now`: the active one, or the first waiting one that is due
+    pub fn next_mission(&self, now: u64) -> Option<String> {
+        self.current_mission.clone().or_else(|| {
+            self.waiting_missions()
+                .into_iter()
+                .find(|id| self.schedules.get(id).is_none_or(|s| s.is_due(now)))
+        })
+    }
+
+    /// waiting missions that may not start anymore
+    pub fn expired_missions(&self, now: u64) -> Vec<String> {
+        self.waiting_missions()
+            .into_iter()
+            .filter(|id| self.schedules.get(id).is_some_and(|s| s.is_expired(now)))
+            .collect()
+    }
+
+    /// the active mission stays in front of the queue
+    fn with_waiting_missions(self, waiting: Vec<String>) -> Self {
+        let mission_queue: Vec<String> = self
+            .current_mission
+            .iter()
+            .filter(|id| self.mission_queue.contains(id))
+            .cloned()
+            .chain(waiting)
+            .collect();
+        let mut schedules = self.schedules;
+        schedules.retain(|id, _| mission_queue.contains(id));
         Self {
-            id: Default::default(),
-            current_mission: None,
-            mission_queue: Vec::new(),
-            attached_drone: None,
+            mission_queue,
+            schedules,
+            ..self
         }
     }
 }
@@ -37,6 +81,7 @@ impl LaunchpadTwin {
 
 impl Twin for LaunchpadTwin {
     type State = LaunchpadTwinState;
+    type Event = ev::LaunchPadEvent;
     fn name(&self) -> String {
         "launchpad".to_string()
     }
@@ -48,69 +93,191 @@ impl Twin for LaunchpadTwin {
             .parse()
             .expect("LaunchpadTwin: AQL query not parse-able")
     }
+    fn snapshot_version(&self) -> Option<u32> {
+        Some(4)
+    }
+
+    fn reducer(state: Self::State, event: Event<ev::LaunchPadEvent>) -> Self::State {
+        Self::on_event(state, event)
+    }
+}
+
+impl LaunchPadEventReducer for LaunchpadTwin {
+    fn on_launch_pad_registered(
+        state: Self::State,
+        e: ev::LaunchPadRegisteredEvent,
+        _: &Metadata,
+    ) -> Self::State {
+        Self::State { id: e.id, ..state }
+    }
 
-    fn reducer(state: Self::State, event: Event<Payload>) -> Self::State {
-        //println!("{:?}", event.payload.json_value());
-        if let Ok(ev) = event.extract::<ev::LaunchPadEvent>() {
-            match ev.payload {
-                ev::LaunchPadEvent::DroneMounted(e) => Self::State {
-                    id: e.id,
-                    current_mission: state.current_mission,
-                    mission_queue: state.mission_queue,
-                    attached_drone: Some(e.drone),
-                },
-                ev::LaunchPadEvent::LaunchPadRegistered(e) => Self::State {
-                    id: e.id,
-                    current_mission: state.current_mission,
-                    mission_queue: state.mission_queue,
-                    attached_drone: state.attached_drone,
-                },
-                ev::LaunchPadEvent::MissionActivated(e) => Self::State {
-                    id: state.id,
-                    current_mission: Some(e.mission_id),
-                    mission_queue: state.mission_queue,
-                    attached_drone: state.attached_drone,
-                },
-                ev::LaunchPadEvent::DroneMissionCompleted(e) => {
-                    if Some(e.id) == state.attached_drone {
-                        let mission_queue = state
-                            .mission_queue
-                            .iter()
-                            .filter({
-                                let id = e.mission_id.clone();
-                                move |m_id| **m_id != id
-                            })
-                            .map(|s| s.to_owned())
-                            .collect::<Vec<String>>();
-
-                        Self::State {
-                            id: state.id,
-                            current_mission: None,
-                            mission_queue,
-                            attached_drone: None,
-                        }
-                    } else {
-                        state
-                    }
+    /// only the registry forgets the launchpad, its queue stays for a registration again
+    fn on_launch_pad_deregistered(
+        state: Self::State,
+        _: ev::LaunchPadDeregisteredEvent,
+        _: &Metadata,
+    ) -> Self::State {
+        state
+    }
+
+    fn on_drone_mounted(
+        state: Self::State,
+        e: ev::DroneMountedEvent,
+        meta: &Metadata,
+    ) -> Self::State {
+        Self::State {
+            id: e.id,
+            attached_drone: Some(e.drone),
+            mounted_at: Some(u64::from(meta.timestamp) / 1000),
+            ..state
+        }
+    }
+
+    fn on_mission_queued(
+        state: Self::State,
+        e: ev::MissionQueuedEvent,
+        meta: &Metadata,
+    ) -> Self::State {
+        let mut mission_queue = state.mission_queue.clone();
+        mission_queue.push(e.mission_id.clone());
+        let mut schedules = state.schedules;
+        match e.schedule {
+            Some(schedule) => {
+                let queued = u64::from(meta.timestamp) / 1000;
+                schedules.insert(e.mission_id, schedule.first(queued));
+            }
+            None => {
+                schedules.remove(&e.mission_id);
+            }
+        }
+
+        Self::State {
+            id: e.launchpad_id,
+            current_mission: state.current_mission,
+            mission_queue,
+            attached_drone: state.attached_drone,
+            mounted_at: state.mounted_at,
+            schedules,
+        }
+    }
+
+    fn on_mission_activated(
+        state: Self::State,
+        e: ev::MissionActivatedEvent,
+        _: &Metadata,
+    ) -> Self::State {
+        Self::State {
+            current_mission: Some(e.mission_id),
+            ..state
+        }
+    }
+
+    fn on_mission_dequeued(
+        state: Self::State,
+        e: ev::MissionDequeuedEvent,
+        _: &Metadata,
+    ) -> Self::State {
+        let mut waiting = state.waiting_missions();
+        waiting.retain(|id| *id != e.mission_id);
+        state.with_waiting_missions(waiting)
+    }
+
+    fn on_mission_moved(state: Self::State, e: ev::MissionMovedEvent, _: &Metadata) -> Self::State {
+        let mut waiting = state.waiting_missions();
+        match waiting.iter().position(|id| *id == e.mission_id) {
+            Some(idx) => {
+                let mission = waiting.remove(idx);
+                waiting.insert(e.position.min(waiting.len()), mission);
+                state.with_waiting_missions(waiting)
+            }
+            None => state,
+        }
+    }
+
+    fn on_mission_queue_cleared(
+        state: Self::State,
+        _: ev::MissionQueueClearedEvent,
+        _: &Metadata,
+    ) -> Self::State {
+        state.with_waiting_missions(Vec::new())
+    }
+
+    fn on_drone_mission_completed(
+        state: Self::State,
+        e: DroneMissionCompletedEvent,
+        meta: &Metadata,
+    ) -> Self::State {
+        if Some(e.id) == state.attached_drone {
+            let mut mission_queue = state
+                .mission_queue
+                .iter()
+                .filter({
+                    let id = e.mission_id.clone();
+                    move |m_id| **m_id != id
+                })
+                .map(|s| s.to_owned())
+                .collect::<Vec<String>>();
+            // a recurring mission is queued again for its next flight
+            let mut schedules = state.schedules;
+            let completed = u64::from(meta.timestamp) / 1000;
+            match schedules.get(&e.mission_id).and_then(|s| s.next(completed)) {
+                Some(next) => {
+                    mission_queue.push(e.mission_id.clone());
+                    schedules.insert(e.mission_id, next);
                 }
-                ev::LaunchPadEvent::MissionQueued(e) => {
-                    let mut mission_queue = state.mission_queue.clone();
-                    mission_queue.push(e.mission_id);
-
-                    Self::State {
-                        id: e.launchpad_id,
-                        current_mission: state.current_mission,
-                        mission_queue,
-                        attached_drone: state.attached_drone,
-                    }
+                None => {
+                    schedules.remove(&e.mission_id);
                 }
             }
+
+            Self::State {
+                id: state.id,
+                current_mission: None,
+                mission_queue,
+                attached_drone: None,
+                mounted_at: None,
+                schedules,
+            }
         } else {
             state
         }
     }
 }
 
+/// ids of the registered launchpads
+#[derive(Clone)]
+pub struct LaunchpadRegistryTwin;
+
+impl Twin for LaunchpadRegistryTwin {
+    type State = HashSet<String>;
+    type Event = ev::LaunchPadEvent;
+    fn name(&self) -> String {
+        "launchpadRegistry".to_string()
+    }
+    fn id(&self) -> String {
+        "reg".to_string()
+    }
+    fn query(&self) -> actyx_sdk::language::Query {
+        "FROM 'launchpad.registered'"
+            .parse()
+            .expect("LaunchpadRegistryTwin: AQL query not parse-able")
+    }
+    fn reducer(mut state: Self::State, event: Event<ev::LaunchPadEvent>) -> Self::State {
+        match event.payload {
+            ev::LaunchPadEvent::LaunchPadRegistered(e) => {
+                state.insert(e.id);
+            }
+            ev::LaunchPadEvent::LaunchPadDeregistered(e) => {
+                state.remove(&e.id);
+            }
+            _ => (),
+        }
+        state
+    }
+}
+
+/// tags of all events of a launchpad, the emit functions of `LaunchPadEvent` add the others
+#[allow(dead_code)]
 pub fn tag_launchpad_id<T>(id: &T) -> TagSet
 where
     T: core::fmt::Display,
@@ -118,41 +285,118 @@ where
     tag_with_id("launchpad", &id)
 }
 
-pub fn tag_launchpad_registered<T>(id: &T) -> TagSet
-where
-    T: core::fmt::Display,
-{
-    tag_launchpad_id(&id) + tag!("launchpad.registered")
+#[test]
+fn queue_events_cancel_and_reorder_the_waiting_missions() {
+    use ev::*;
+    let meta = Metadata {
+        timestamp: actyx_sdk::Timestamp::new(0),
+        tags: actyx_sdk::tags!("launchpad"),
+        app_id: actyx_sdk::app_id!("com.example.launchpad"),
+    };
+    let pad = || "pad-1".to_string();
+    let state = LaunchpadTwinState {
+        id: pad(),
+        current_mission: Some("m1".to_string()),
+        mission_queue: vec!["m1", "m2", "m3", "m4"]
+            .into_iter()
+            .map(String::from)
+            .collect(),
+        attached_drone: Some("d1".to_string()),
+        ..Default::default()
+    };
+
+    let moved = |state, mission_id: &str, position| {
+        LaunchpadTwin::on_mission_moved(
+            state,
+            MissionMovedEvent {
+                launchpad_id: pad(),
+                mission_id: mission_id.to_string(),
+                position,
+            },
+            &meta,
+        )
+    };
+    let state = moved(state, "m4", 0);
+    assert_eq!(state.mission_queue, ["m1", "m4", "m2", "m3"]);
+    let state = moved(state, "m4", 10);
+    assert_eq!(state.mission_queue, ["m1", "m2", "m3", "m4"]);
+    // the active mission can't be moved or dequeued
+    let state = moved(state, "m1", 2);
+    assert_eq!(state.mission_queue, ["m1", "m2", "m3", "m4"]);
+
+    let dequeued = |state, mission_id: &str| {
+        LaunchpadTwin::on_mission_dequeued(
+            state,
+            MissionDequeuedEvent {
+                launchpad_id: pad(),
+                mission_id: mission_id.to_string(),
+            },
+            &meta,
+        )
+    };
+    let state = dequeued(state, "m3");
+    assert_eq!(state.mission_queue, ["m1", "m2", "m4"]);
+    let state = dequeued(state, "m1");
+    assert_eq!(state.mission_queue, ["m1", "m2", "m4"]);
+
+    let state = LaunchpadTwin::on_mission_queue_cleared(
+        state,
+        MissionQueueClearedEvent {
+            launchpad_id: pad(),
+        },
+        &meta,
+    );
+    assert_eq!(state.mission_queue, ["m1"]);
+    assert_eq!(state.current_mission, Some("m1".to_string()));
 }
 
-impl LaunchpadTwin {
-    #[allow(dead_code)]
-    pub async fn emit_launchpad_registered(
-        service: impl EventService,
-        id: String,
-    ) -> Result<PublishResponse, anyhow::Error> {
-        service
-            .publish(mk_publish_request(
-                tag_launchpad_registered(&id),
-                &ev::LaunchPadEvent::LaunchPadRegistered(ev::LaunchPadRegisteredEvent { id }),
-            ))
-            .await
-    }
-
-    #[allow(dead_code)]
-    pub async fn emit_mission_activated(
-        service: impl EventService,
-        launchpad_id: String,
-        mission_id: String,
-    ) -> Result<PublishResponse, anyhow::Error> {
-        service
-            .publish(mk_publish_request(
-                tag_launchpad_id(&launchpad_id),
-                &ev::LaunchPadEvent::MissionActivated(ev::MissionActivatedEvent {
-                    launchpad_id,
-                    mission_id,
-                }),
-            ))
-            .await
-    }
+#[test]
+fn recurring_missions_are_queued_again_after_their_flight() {
+    use schedule::{Recurrence, Schedule};
+    let meta = |ms: u64| Metadata {
+        timestamp: actyx_sdk::Timestamp::new(ms * 1000),
+        tags: actyx_sdk::tags!("launchpad"),
+        app_id: actyx_sdk::app_id!("com.example.launchpad"),
+    };
+    let queued = |state, mission_id: &str, schedule| {
+        LaunchpadTwin::on_mission_queued(
+            state,
+            ev::MissionQueuedEvent {
+                launchpad_id: "pad-1".to_string(),
+                mission_id: mission_id.to_string(),
+                schedule,
+            },
+            &meta(0),
+        )
+    };
+    let patrol = Schedule {
+        not_before: Some(60_000),
+        recurrence: Some(Recurrence::Every { minutes: 10 }),
+        expires: None,
+    };
+    let state = LaunchpadTwinState {
+        id: "pad-1".to_string(),
+        attached_drone: Some("d1".to_string()),
+        ..Default::default()
+    };
+    let state = queued(state, "patrol", Some(patrol));
+    let state = queued(state, "m2", None);
+    assert_eq!(state.next_mission(0), Some("m2".to_string()));
+    assert_eq!(state.next_mission(60_000), Some("patrol".to_string()));
+
+    let state = LaunchpadTwin::on_drone_mission_completed(
+        LaunchpadTwinState {
+            current_mission: Some("patrol".to_string()),
+            ..state
+        },
+        DroneMissionCompletedEvent {
+            id: "d1".to_string(),
+            mission_id: "patrol".to_string(),
+            outcome: Default::default(),
+        },
+        &meta(120_000),
+    );
+    assert_eq!(state.mission_queue, ["m2", "patrol"]);
+    assert_eq!(state.schedules["patrol"].not_before, Some(660_000));
+    assert_eq!(state.next_mission(120_000), Some("m2".to_string()));
 }
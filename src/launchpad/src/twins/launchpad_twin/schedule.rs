@@ -0,0 +1,149 @@
+use serde::{Deserialize, Serialize};
+use std::time::SystemTime;
+
+/// in ms
+const MINUTE: u64 = 60_000;
+/// in ms
+const DAY: u64 = 24 * 60 * MINUTE;
+
+/// When a queued mission flies. Times are unix times in ms, times of day are UTC.
+///
+/// ```json
+/// { "notBefore": 1633046400000, "recurrence": { "type": "every", "minutes": 30 } }
+/// { "recurrence": { "type": "daily", "at": ["06:00", "18:30"] }, "expires": 1635724800000 }
+/// ```
+#[derive(Clone, Debug, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Schedule {
+    /// the mission doesn't start earlier
+    #[serde(default)]
+    pub not_before: Option<u64>,
+    /// the mission is queued again after it is completed
+    #[serde(default)]
+    pub recurrence: Option<Recurrence>,
+    /// the mission doesn't start later, a recurring mission ends
+    #[serde(default)]
+    pub expires: Option<u64>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", tag = "type")]
+pub enum Recurrence {
+    /// every `minutes` after the first start
+    Every { minutes: u32 },
+    /// at times of day, like `"06:30"`
+    Daily { at: Vec<String> },
+}
+
+impl Schedule {
+    pub fn is_due(&self, now: u64) -> bool {
+        self.not_before.is_none_or(|t| t <= now) && !self.is_expired(now)
+    }
+
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires.is_some_and(|t| t <= now)
+    }
+
+    /// schedule of a mission queued at `queued`, a daily mission waits for its next time of day
+    pub fn first(self, queued: u64) -> Self {
+        match (&self.recurrence, self.not_before) {
+            (Some(Recurrence::Daily { at }), None) => Self {
+                not_before: next_time_of_day(at, queued),
+                ..self
+            },
+            _ => self,
+        }
+    }
+
+    /// schedule of the next flight of a mission completed at `completed`, `None` if it doesn't
+    /// recur or the next flight is after the expiry
+    pub fn next(&self, completed: u64) -> Option<Self> {
+        let next = match self.recurrence.as_ref()? {
+            Recurrence::Every { minutes } => {
+                let every = u64::from((*minutes).max(1)) * MINUTE;
+                match self.not_before {
+                    // stays on the times of the first flight, missed flights are skipped
+                    Some(start) if start <= completed => {
+                        start + ((completed - start) / every + 1) * every
+                    }
+                    _ => completed + every,
+                }
+            }
+            Recurrence::Daily { at } => next_time_of_day(at, completed)?,
+        };
+        if self.is_expired(next) {
+            return None;
+        }
+        Some(Self {
+            not_before: Some(next),
+            ..self.clone()
+        })
+    }
+}
+
+fn next_time_of_day(at: &[String], after: u64) -> Option<u64> {
+    let day = after / DAY * DAY;
+    at.iter()
+        .filter_map(|at| time_of_day(at))
+        .flat_map(|t| vec![day + t, day + DAY + t])
+        .filter(|t| *t > after)
+        .min()
+}
+
+/// `"HH:MM"` in ms after midnight
+fn time_of_day(at: &str) -> Option<u64> {
+    let (hours, minutes) = at.split_once(':')?;
+    let hours: u64 = hours.trim().parse().ok()?;
+    let minutes: u64 = minutes.trim().parse().ok()?;
+    if hours < 24 && minutes < 60 {
+        Some((hours * 60 + minutes) * MINUTE)
+    } else {
+        println!("Schedule: invalid time of day {:?}", at);
+        None
+    }
+}
+
+/// `time` as unix time in ms
+pub fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |t| t.as_millis() as u64)
+}
+
+#[test]
+fn recurring_missions_fly_on_the_times_of_their_first_flight() {
+    let schedule = Schedule {
+        not_before: Some(10 * MINUTE),
+        recurrence: Some(Recurrence::Every { minutes: 30 }),
+        expires: Some(130 * MINUTE),
+    };
+    let next = schedule.next(12 * MINUTE).unwrap();
+    assert_eq!(next.not_before, Some(40 * MINUTE));
+    // the flight at 70 was missed
+    let next = next.next(75 * MINUTE).unwrap();
+    assert_eq!(next.not_before, Some(100 * MINUTE));
+    assert!(!next.is_due(99 * MINUTE));
+    assert!(next.is_due(101 * MINUTE));
+    // the flight at 130 would be too late
+    assert_eq!(next.next(105 * MINUTE), None);
+}
+
+#[test]
+fn daily_missions_fly_at_their_times_of_day() {
+    let schedule = Schedule {
+        recurrence: Some(Recurrence::Daily {
+            at: vec![
+                "18:30".to_string(),
+                "06:00".to_string(),
+                "25:00".to_string(),
+            ],
+        }),
+        ..Default::default()
+    }
+    .first(3 * DAY + 7 * 60 * MINUTE);
+    assert_eq!(schedule.not_before, Some(3 * DAY + (18 * 60 + 30) * MINUTE));
+    assert!(!schedule.is_due(3 * DAY + 18 * 60 * MINUTE));
+
+    let next = schedule.next(3 * DAY + 19 * 60 * MINUTE).unwrap();
+    assert_eq!(next.not_before, Some(4 * DAY + 6 * 60 * MINUTE));
+    assert_eq!(Schedule::default().next(DAY), None);
+}
@@ -0,0 +1,369 @@
+use super::events::{DefineMissionEvent, MissionEvent};
+use super::types::*;
+use super::MissionTwin;
+use crate::twin::current_state;
+use actyx_sdk::service::EventService;
+
+/// shortest and longest `go` step of a Tello, in cm
+const GO_STEP_CM: std::ops::RangeInclusive<f32> = 20.0..=500.0;
+/// widest turn of a Tello `cw` / `ccw`, in deg
+const MAX_TURN_DEG: i16 = 3600;
+/// speed of `go`, in cm/s
+const SPEED: std::ops::RangeInclusive<u8> = 10..=100;
+/// speed of `curve`, in cm/s
+const CURVE_SPEED: std::ops::RangeInclusive<u8> = 10..=60;
+/// coordinates of `go` and `curve`, in cm
+const COORDINATE: std::ops::RangeInclusive<i16> = -500..=500;
+/// the mission pads of a Tello EDU
+const MISSION_PADS: std::ops::RangeInclusive<u8> = 1..=8;
+
+/// Builds and validates the waypoints of a mission, the same waypoints the `MissionPlanner`
+/// defines. The first waypoint is the start point at the launchpad.
+///
+/// ```ignore
+/// MissionBuilder::new("m1", "square")
+///     .goto(2.0, 100)
+///     .turn(90)
+///     .delay(1000.0)
+///     .emit(service)
+///     .await?;
+/// ```
+#[derive(Clone, Debug)]
+#[allow(dead_code)]
+pub struct MissionBuilder {
+    id: String,
+    name: String,
+    waypoints: Vec<Waypoint>,
+    version: Option<u32>,
+}
+
+#[allow(dead_code)]
+impl MissionBuilder {
+    pub fn new(id: impl Into<String>, name: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            name: name.into(),
+            waypoints: vec![Waypoint::Goto(GoToWaypoint {
+                map_x: 0.0,
+                map_y: 0.0,
+                height: 0,
+                angle: None,
+                distance: 0.0,
+                duration: 0.0,
+            })],
+            version: None,
+        }
+    }
+
+    /// version of the mission, `emit` takes the one after the defined mission if unset
+    pub fn version(mut self, version: u32) -> Self {
+        self.version = Some(version);
+        self
+    }
+
+    /// flies `distance` m forward to `height` cm, with the duration of the `MissionPlanner`
+    pub fn goto(mut self, distance: f32, height: i16) -> Self {
+        self.waypoints.push(Waypoint::Goto(GoToWaypoint {
+            map_x: 0.0,
+            map_y: 0.0,
+            height,
+            angle: None,
+            distance,
+            duration: 2000.0 + distance * 5000.0,
+        }));
+        self
+    }
+
+    /// turns clockwise by `deg`, counterclockwise if it is negative
+    pub fn turn(mut self, deg: i16) -> Self {
+        self.waypoints.push(Waypoint::Turn(TurnWaypoint {
+            deg,
+            duration: 4000.0,
+        }));
+        self
+    }
+
+    /// hovers for `duration` ms
+    pub fn delay(mut self, duration: f32) -> Self {
+        self.waypoints
+            .push(Waypoint::Delay(DelayWaypoint { duration }));
+        self
+    }
+
+    /// speed of the following moves, in cm/s
+    pub fn speed(mut self, speed: u8) -> Self {
+        self.waypoints
+            .push(Waypoint::SetSpeed(SetSpeedWaypoint { speed }));
+        self
+    }
+
+    /// flies an arc through `via` to `to`, in cm relative to the drone
+    pub fn curve(mut self, via: [i16; 3], to: [i16; 3], speed: u8) -> Self {
+        let ([x1, y1, z1], [x2, y2, z2]) = (via, to);
+        self.waypoints.push(Waypoint::Curve(CurveWaypoint {
+            x1,
+            y1,
+            z1,
+            x2,
+            y2,
+            z2,
+            speed,
+        }));
+        self
+    }
+
+    pub fn flip(mut self, direction: FlipDirection) -> Self {
+        self.waypoints
+            .push(Waypoint::Flip(FlipWaypoint { direction }));
+        self
+    }
+
+    pub fn camera(mut self, action: CameraAction) -> Self {
+        self.waypoints
+            .push(Waypoint::Camera(CameraWaypoint { action }));
+        self
+    }
+
+    /// lands and takes off again after `duration` ms
+    pub fn land(mut self, duration: f32) -> Self {
+        self.waypoints
+            .push(Waypoint::Land(LandWaypoint { duration }));
+        self
+    }
+
+    /// flies to `x y z` cm relative to the mission pad `pad`
+    pub fn mission_pad(mut self, pad: u8, [x, y, z]: [i16; 3], speed: u8) -> Self {
+        self.waypoints
+            .push(Waypoint::MissionPad(MissionPadWaypoint {
+                pad,
+                x,
+                y,
+                z,
+                speed,
+            }));
+        self
+    }
+
+    pub fn build(self) -> Result<DefineMissionEvent, anyhow::Error> {
+        validate(&self.waypoints)?;
+        Ok(DefineMissionEvent {
+            id: self.id,
+            name: self.name,
+            waypoints: self.waypoints,
+            version: self.version,
+        })
+    }
+
+    /// builds the mission and publishes its `DefineMission`
+    pub async fn emit<S>(self, service: S) -> Result<(), anyhow::Error>
+    where
+        S: EventService + Clone + Send + Sync + 'static,
+    {
+        let mut mission = self.build()?;
+        if mission.version.is_none() {
+            let twin = MissionTwin {
+                id: mission.id.clone(),
+            };
+            let defined = (*current_state(service.clone(), twin, None).await)?;
+            mission.version = Some(defined.version + 1);
+        }
+        MissionEvent::emit_define_mission(service, mission)
+            .await
+            .map(|_| ())
+    }
+}
+
+/// Checks the waypoints against the limits of a Tello, waypoint 0 is the start point and isn't
+/// flown. The error names every invalid waypoint.
+#[allow(dead_code)]
+pub fn validate(waypoints: &[Waypoint]) -> Result<(), anyhow::Error> {
+    let errors: Vec<String> = waypoints
+        .iter()
+        .enumerate()
+        .skip(1)
+        .filter_map(|(idx, wp)| Some(format!("waypoint {}: {}", idx, invalid(wp)?)))
+        .collect();
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow::Error::msg(errors.join(", ")))
+    }
+}
+
+fn invalid(wp: &Waypoint) -> Option<String> {
+    match wp {
+        Waypoint::Goto(GoToWaypoint {
+            distance, height, ..
+        }) => {
+            let distance = distance * 100.0;
+            if !GO_STEP_CM.contains(&distance) {
+                Some(format!("distance {}cm is not within 20-500cm", distance))
+            } else if !GO_STEP_CM.contains(&(*height as f32)) {
+                Some(format!("height {}cm is not within 20-500cm", height))
+            } else {
+                None
+            }
+        }
+        Waypoint::Turn(TurnWaypoint { deg, .. }) => {
+            if *deg == 0 || deg.abs() > MAX_TURN_DEG {
+                Some(format!("turn {}° is not within ±1-3600°", deg))
+            } else {
+                None
+            }
+        }
+        Waypoint::Delay(DelayWaypoint { duration }) => {
+            // `> 0.0` is false for NaN as well
+            if *duration > 0.0 {
+                None
+            } else {
+                Some(format!("delay {}ms is not positive", duration))
+            }
+        }
+        Waypoint::SetSpeed(SetSpeedWaypoint { speed }) => (!SPEED.contains(speed))
+            .then(|| format!("speed {}cm/s is not within 10-100cm/s", speed)),
+        Waypoint::Curve(CurveWaypoint {
+            x1,
+            y1,
+            z1,
+            x2,
+            y2,
+            z2,
+            speed,
+        }) => {
+            if ![x1, y1, z1, x2, y2, z2]
+                .iter()
+                .all(|c| COORDINATE.contains(*c))
+            {
+                Some("curve is not within ±500cm".to_string())
+            } else if is_short([*x1, *y1, *z1]) || is_short([*x2, *y2, *z2]) {
+                Some("curve point is closer than 20cm".to_string())
+            } else if !CURVE_SPEED.contains(speed) {
+                Some(format!("curve speed {}cm/s is not within 10-60cm/s", speed))
+            } else {
+                None
+            }
+        }
+        Waypoint::Flip(_) | Waypoint::Camera(_) => None,
+        Waypoint::Land(LandWaypoint { duration }) => {
+            // `>= 0.0` is false for NaN as well
+            if *duration >= 0.0 {
+                None
+            } else {
+                Some(format!("landing for {}ms is negative", duration))
+            }
+        }
+        Waypoint::MissionPad(MissionPadWaypoint {
+            pad,
+            x,
+            y,
+            z,
+            speed,
+        }) => {
+            if !MISSION_PADS.contains(pad) {
+                Some(format!("mission pad {} is not within 1-8", pad))
+            } else if ![x, y, z].iter().all(|c| COORDINATE.contains(*c)) {
+                Some("mission pad move is not within ±500cm".to_string())
+            } else if !SPEED.contains(speed) {
+                Some(format!("speed {}cm/s is not within 10-100cm/s", speed))
+            } else {
+                None
+            }
+        }
+    }
+}
+
+/// the Tello refuses moves with all coordinates within ±20cm
+fn is_short(point: [i16; 3]) -> bool {
+    point.iter().all(|c| c.abs() < 20)
+}
+
+#[test]
+fn rejects_waypoints_beyond_the_limits_of_a_tello() {
+    let mission = MissionBuilder::new("m1", "square")
+        .goto(2.0, 100)
+        .turn(-90)
+        .delay(500.0)
+        .goto(5.0, 20)
+        .turn(3600)
+        .build()
+        .unwrap();
+    assert_eq!(mission.waypoints.len(), 6);
+
+    let err = MissionBuilder::new("m2", "wild")
+        .goto(0.1, 100)
+        .goto(1.0, 600)
+        .turn(3601)
+        .turn(0)
+        .delay(0.0)
+        .delay(f32::NAN)
+        .goto(1.0, 100)
+        .build()
+        .unwrap_err()
+        .to_string();
+    for idx in 1..=6 {
+        assert!(err.contains(&format!("waypoint {}:", idx)), "{}", err);
+    }
+    assert!(!err.contains("waypoint 7:"), "{}", err);
+
+    let err = MissionBuilder::new("m3", "tricks")
+        .speed(50)
+        .curve([100, 100, 0], [200, 0, 0], 30)
+        .flip(FlipDirection::Back)
+        .camera(CameraAction::StartVideo)
+        .land(0.0)
+        .mission_pad(1, [0, 0, 100], 50)
+        .speed(5)
+        .curve([10, 10, 0], [200, 0, 0], 30)
+        .curve([100, 100, 0], [200, 0, 0], 80)
+        .land(-1.0)
+        .mission_pad(9, [0, 0, 100], 50)
+        .build()
+        .unwrap_err()
+        .to_string();
+    for idx in 7..=11 {
+        assert!(err.contains(&format!("waypoint {}:", idx)), "{}", err);
+    }
+    assert!(!err.contains("waypoint 6:"), "{}", err);
+}
+
+#[tokio::test]
+async fn emits_the_mission_for_the_mission_twin() {
+    use crate::memory_service::MemoryEventService;
+
+    let service = MemoryEventService::new();
+    MissionBuilder::new("m1", "square")
+        .goto(2.0, 100)
+        .turn(90)
+        .emit(service.clone())
+        .await
+        .unwrap();
+    // invalid missions aren't published
+    assert!(MissionBuilder::new("m1", "square")
+        .goto(9.0, 100)
+        .emit(service.clone())
+        .await
+        .is_err());
+
+    let mission = current_state(service.clone(), MissionTwin { id: "m1".into() }, None)
+        .await
+        .unwrap();
+    assert_eq!(mission.name, "square");
+    assert_eq!(mission.waypoints.len(), 3);
+    assert_eq!(mission.version, 1);
+
+    MissionBuilder::new("m1", "square")
+        .goto(3.0, 100)
+        .emit(service.clone())
+        .await
+        .unwrap();
+    MissionBuilder::new("m1", "square")
+        .version(7)
+        .emit(service.clone())
+        .await
+        .unwrap();
+    let mission = current_state(service, MissionTwin { id: "m1".into() }, None)
+        .await
+        .unwrap();
+    let versions: Vec<u32> = mission.history.iter().map(|v| v.version).collect();
+    assert_eq!(versions, [1, 2, 7]);
+}
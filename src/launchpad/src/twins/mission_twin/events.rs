@@ -1,4 +1,5 @@
 use crate::twins::mission_twin::types::Waypoint;
+use event_emitter::{EventEmitter, Reducer};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -6,6 +7,10 @@ pub struct DefineMissionEvent {
     pub id: String,
     pub name: String,
     pub waypoints: Vec<Waypoint>,
+    /// set by the planner so the version stays the same after a time travel, missing in events
+    /// of older planners, which count the definitions instead
+    #[serde(default)]
+    pub version: Option<u32>,
 }
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ShowMissionEvent {
@@ -13,9 +18,10 @@ pub struct ShowMissionEvent {
     pub visible: bool,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, EventEmitter, Reducer)]
 #[serde(tag = "eventType")]
 #[serde(rename_all = "camelCase")]
+#[emitter(base = "mission")]
 pub enum MissionEvent {
     DefineMission(DefineMissionEvent),
     ShowMission(ShowMissionEvent),
@@ -1,17 +1,37 @@
 use crate::twin::Twin;
-use crate::twins::mission_twin::events::MissionEvent;
+use crate::twins::mission_twin::events::{
+    DefineMissionEvent, MissionEvent, MissionEventReducer, ShowMissionEvent,
+};
 use crate::twins::mission_twin::types::Waypoint;
-use actyx_sdk::{Event, Payload};
+use actyx_sdk::{Event, Metadata};
+use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
+pub mod builder;
 pub mod events;
 pub mod types;
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct MissionTwinState {
     pub id: String,
     pub name: String,
     pub waypoints: Vec<Waypoint>,
     pub visible: bool,
+    /// version of the last `DefineMission`, `name` and `waypoints` are the ones of this version
+    #[serde(default)]
+    pub version: u32,
+    /// every defined version, the oldest first
+    #[serde(default)]
+    pub history: Vec<MissionVersion>,
+}
+
+/// a mission as one `DefineMission` defined it
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MissionVersion {
+    pub version: u32,
+    pub name: String,
+    pub waypoints: Vec<Waypoint>,
+    /// unix time in ms
+    pub defined_at: u64,
 }
 
 impl Default for MissionTwinState {
@@ -21,6 +41,32 @@ impl Default for MissionTwinState {
             name: Default::default(),
             waypoints: vec![],
             visible: true,
+            version: 0,
+            history: vec![],
+        }
+    }
+}
+
+impl MissionTwinState {
+    /// The mission with the name and waypoints of `version`, the latest one for drones of older
+    /// launchpads without a version. Fails if the version is unknown.
+    pub fn pinned(self, version: Option<u32>) -> Result<Self, anyhow::Error> {
+        let version = match version {
+            Some(version) if version != self.version => version,
+            _ => return Ok(self),
+        };
+        match self.history.iter().find(|v| v.version == version).cloned() {
+            Some(MissionVersion {
+                name, waypoints, ..
+            }) => Ok(Self {
+                name,
+                waypoints,
+                ..self
+            }),
+            None => Err(anyhow::Error::msg(format!(
+                "mission {} has no version {}",
+                self.id, version
+            ))),
         }
     }
 }
@@ -32,6 +78,7 @@ pub struct MissionTwin {
 
 impl Twin for MissionTwin {
     type State = MissionTwinState;
+    type Event = MissionEvent;
     fn name(&self) -> String {
         "mission".to_string()
     }
@@ -43,34 +90,55 @@ impl Twin for MissionTwin {
             .parse()
             .expect("MissionTwin: AQL query not parse-able")
     }
-    fn reducer(state: Self::State, event: Event<Payload>) -> Self::State {
-        //println!("{:?}", event.payload.json_value());
-        if let Ok(ev) = event.extract::<MissionEvent>() {
-            match ev.payload {
-                MissionEvent::DefineMission(e) => Self::State {
-                    id: e.id,
-                    name: e.name,
-                    waypoints: e.waypoints,
-                    visible: state.visible,
-                },
-                MissionEvent::ShowMission(e) => Self::State {
-                    id: state.id,
-                    name: state.name,
-                    waypoints: state.waypoints,
-                    visible: e.visible,
-                },
-            }
-        } else {
-            state
+    fn snapshot_version(&self) -> Option<u32> {
+        Some(2)
+    }
+    fn reducer(state: Self::State, event: Event<MissionEvent>) -> Self::State {
+        Self::on_event(state, event)
+    }
+}
+
+impl MissionEventReducer for MissionTwin {
+    fn on_define_mission(
+        state: Self::State,
+        e: DefineMissionEvent,
+        meta: &Metadata,
+    ) -> Self::State {
+        let version = e.version.unwrap_or(state.version + 1);
+        let mut history = state.history;
+        // a version defined again replaces the older definition
+        history.retain(|v| v.version != version);
+        history.push(MissionVersion {
+            version,
+            name: e.name.clone(),
+            waypoints: e.waypoints.clone(),
+            defined_at: u64::from(meta.timestamp) / 1000,
+        });
+        Self::State {
+            id: e.id,
+            name: e.name,
+            waypoints: e.waypoints,
+            visible: state.visible,
+            version,
+            history,
+        }
+    }
+
+    fn on_show_mission(state: Self::State, e: ShowMissionEvent, _: &Metadata) -> Self::State {
+        Self::State {
+            visible: e.visible,
+            ..state
         }
     }
 }
 
+#[allow(dead_code)]
 #[derive(Clone)]
 pub struct MissionRegistryTwin;
 
 impl Twin for MissionRegistryTwin {
     type State = HashSet<String>;
+    type Event = MissionEvent;
     fn name(&self) -> String {
         "missionRegistry".to_string()
     }
@@ -78,28 +146,77 @@ impl Twin for MissionRegistryTwin {
         "reg".to_string()
     }
     fn query(&self) -> actyx_sdk::language::Query {
-        format!("FROM 'mission'")
+        "FROM 'mission'"
             .parse()
             .expect("MissionTwin: AQL query not parse-able")
     }
-    fn reducer(mut state: Self::State, event: Event<Payload>) -> Self::State {
-        //println!("{:?}", event.payload.json_value());
+    fn reducer(state: Self::State, event: Event<MissionEvent>) -> Self::State {
+        Self::on_event(state, event)
+    }
+}
 
-        match event.extract::<MissionEvent>() {
-            Ok(ev) => match ev.payload {
-                MissionEvent::DefineMission(e) => {
-                    state.insert(e.id);
-                    state
-                }
-                MissionEvent::ShowMission(e) => {
-                    state.remove(&e.id);
-                    state
-                }
-            },
-            Err(e) => {
-                println!("can't parse {:?}", e);
-                state
-            }
-        }
+impl MissionEventReducer for MissionRegistryTwin {
+    fn on_define_mission(
+        mut state: Self::State,
+        e: DefineMissionEvent,
+        _: &Metadata,
+    ) -> Self::State {
+        state.insert(e.id);
+        state
     }
+
+    fn on_show_mission(mut state: Self::State, e: ShowMissionEvent, _: &Metadata) -> Self::State {
+        state.remove(&e.id);
+        state
+    }
+}
+
+#[test]
+fn keeps_every_version_and_pins_the_flown_one() {
+    use crate::twins::mission_twin::types::{DelayWaypoint, Waypoint};
+    let meta = |ms: u64| Metadata {
+        timestamp: actyx_sdk::Timestamp::new(ms * 1000),
+        tags: actyx_sdk::tags!("mission"),
+        app_id: actyx_sdk::app_id!("com.example.launchpad"),
+    };
+    let delay = |duration| Waypoint::Delay(DelayWaypoint { duration });
+    let defined = |state, name: &str, waypoints, version, ms| {
+        MissionTwin::on_define_mission(
+            state,
+            DefineMissionEvent {
+                id: "m1".to_string(),
+                name: name.to_string(),
+                waypoints,
+                version,
+            },
+            &meta(ms),
+        )
+    };
+    let state = defined(
+        MissionTwinState::default(),
+        "square",
+        vec![delay(1.0)],
+        None,
+        1000,
+    );
+    let state = defined(state, "square 2", vec![delay(2.0), delay(3.0)], None, 2000);
+    assert_eq!(state.version, 2);
+    assert_eq!(state.history.len(), 2);
+    assert_eq!(state.history[0].defined_at, 1000);
+
+    let v1 = state.clone().pinned(Some(1)).unwrap();
+    assert_eq!((v1.name.as_str(), v1.waypoints.len()), ("square", 1));
+    assert_eq!(state.clone().pinned(Some(2)).unwrap(), state);
+    // drones of older launchpads fly the latest version
+    assert_eq!(state.clone().pinned(None).unwrap(), state);
+    assert!(state.clone().pinned(Some(7)).is_err());
+
+    // the planner's version is kept, the same version replaces the older definition
+    let state = defined(state, "square 5", vec![delay(5.0)], Some(5), 3000);
+    let state = defined(state, "square 5b", vec![delay(6.0)], Some(5), 4000);
+    assert_eq!((state.version, state.history.len()), (5, 3));
+    let v5 = state.clone().pinned(Some(5)).unwrap();
+    assert_eq!(v5.name, "square 5b");
+    let state = defined(state, "square 6", vec![], None, 5000);
+    assert_eq!(state.version, 6);
 }
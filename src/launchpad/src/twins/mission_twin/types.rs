@@ -3,8 +3,9 @@ use serde::{Deserialize, Serialize};
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct GoToWaypoint {
-    pub map_x: f32,
-    pub map_y: f32,
+    /// point on the map of the `MissionPlanner` (EPSG:3857), 0 0 if the mission has no map
+    pub map_x: f64,
+    pub map_y: f64,
     pub height: i16,
     pub angle: Option<f32>,
     pub distance: f32,
@@ -24,6 +25,76 @@ pub struct DelayWaypoint {
     pub duration: f32,
 }
 
+/// speed of the following moves, in cm/s
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SetSpeedWaypoint {
+    pub speed: u8,
+}
+
+/// Flies an arc through `x1 y1 z1` to `x2 y2 z2`, in cm relative to the drone (`x` ahead, `y`
+/// left). The drone keeps its heading.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CurveWaypoint {
+    pub x1: i16,
+    pub y1: i16,
+    pub z1: i16,
+    pub x2: i16,
+    pub y2: i16,
+    pub z2: i16,
+    /// cm/s
+    pub speed: u8,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum FlipDirection {
+    Left,
+    Right,
+    Forward,
+    Back,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct FlipWaypoint {
+    pub direction: FlipDirection,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum CameraAction {
+    StartVideo,
+    StopVideo,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CameraWaypoint {
+    pub action: CameraAction,
+}
+
+/// lands, waits `duration` ms on the ground and takes off again
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct LandWaypoint {
+    pub duration: f32,
+}
+
+/// Flies to `x y z`, in cm relative to the mission pad `pad` (1-8) below the drone. The axes are
+/// the ones of the pad, `z` is the height above it.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct MissionPadWaypoint {
+    pub pad: u8,
+    pub x: i16,
+    pub y: i16,
+    pub z: i16,
+    /// cm/s
+    pub speed: u8,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(tag = "type")]
 #[serde(rename_all = "camelCase")]
@@ -31,4 +102,10 @@ pub enum Waypoint {
     Goto(GoToWaypoint),
     Turn(TurnWaypoint),
     Delay(DelayWaypoint),
+    SetSpeed(SetSpeedWaypoint),
+    Curve(CurveWaypoint),
+    Flip(FlipWaypoint),
+    Camera(CameraWaypoint),
+    Land(LandWaypoint),
+    MissionPad(MissionPadWaypoint),
 }
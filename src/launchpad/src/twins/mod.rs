@@ -1,4 +1,4 @@
 pub mod drone_twin;
-// pub mod emitter;
+pub mod geofence_twin;
 pub mod launchpad_twin;
 pub mod mission_twin;